@@ -0,0 +1,136 @@
+// MQ2-UNI encoder throughput: the cached trie matcher against the previous
+// linear scan over the token table, on large inputs and on many short
+// documents. Run with `cargo bench --bench uni_encode`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use marqant::{mq2_uni_decode, mq2_uni_encode};
+
+// Reference: the previous encoder, trying each table entry in order at
+// every ASCII position. Table order decides overlaps, so "\n\n\n" comes
+// out as "~PP\n"; output sizes differ slightly from the trie's.
+const TOKEN_MAP: &[(&[u8], &[u8])] = &[
+    (b"\n\n", b"~PP"),
+    (b"  ", b"~SP"),
+    (b"\n- ", b"~LI"),
+    (b"## ", b"~H2"),
+    (b"# ", b"~H1"),
+    (b"```\n", b"~CB"),
+    (b"```", b"~CE"),
+    (b"{\n", b"~OB"),
+    (b"}\n", b"~CL"),
+    (b"[\n", b"~OS"),
+    (b"\n]", b"~CS"),
+    (b": ", b"~CO"),
+    (b", ", b"~CM"),
+    (b"    ", b"~IN"),
+    (b"\n\n\n", b"~TB"),
+];
+
+fn encode_linear(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] < 0x80 {
+            if let Some((pattern, token)) = TOKEN_MAP
+                .iter()
+                .find(|(pattern, _)| input[i..].starts_with(pattern))
+            {
+                out.extend_from_slice(token);
+                i += pattern.len();
+                continue;
+            }
+        }
+        // Whole UTF-8 characters, as the encoder copies them
+        let len = match input[i] {
+            b if b >= 0xF0 => 4,
+            b if b >= 0xE0 => 3,
+            b if b >= 0xC0 => 2,
+            _ => 1,
+        };
+        let next = (i + len).min(input.len());
+        out.extend_from_slice(&input[i..next]);
+        i = next;
+    }
+    out
+}
+
+fn seed() -> Vec<Vec<u8>> {
+    let mut docs = Vec::new();
+    for entry in std::fs::read_dir("example-md").expect("example-md") {
+        let path = entry.expect("entry").path();
+        if path.extension().is_some_and(|e| e == "md") {
+            docs.push(std::fs::read(&path).expect("read"));
+        }
+    }
+    docs.sort();
+    docs
+}
+
+fn corpus(seed: &[Vec<u8>], min_len: usize) -> Vec<u8> {
+    let seed = seed.concat();
+    let mut out = Vec::with_capacity(min_len + seed.len());
+    while out.len() < min_len {
+        out.extend_from_slice(&seed);
+    }
+    out
+}
+
+fn bench(label: &str, len: usize, mut f: impl FnMut() -> usize) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..20 {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+    let mbps = len as f64 / best.as_secs_f64() / 1_000_000.0;
+    println!(
+        "{label:<8} {:>8.2} ms  {mbps:>8.1} MB/s",
+        best.as_secs_f64() * 1000.0
+    );
+    best
+}
+
+fn compare(input_len: usize, mut linear: impl FnMut() -> usize, mut trie: impl FnMut() -> usize) {
+    let old = bench("linear", input_len, &mut linear);
+    let new = bench("trie", input_len, &mut trie);
+    println!("speedup  {:>8.2}x", old.as_secs_f64() / new.as_secs_f64());
+}
+
+fn main() {
+    let seed = seed();
+    for size in [1 << 20, 16 << 20] {
+        let input = corpus(&seed, size);
+        let encoded = mq2_uni_encode(&input).expect("encode");
+        assert_eq!(mq2_uni_decode(&encoded).expect("decode"), input);
+        println!(
+            "input: {} bytes -> {} bytes (linear: {} bytes)",
+            input.len(),
+            encoded.len(),
+            encode_linear(&input).len()
+        );
+        compare(
+            input.len(),
+            || encode_linear(&input).len(),
+            || mq2_uni_encode(&input).unwrap().len(),
+        );
+        bench("decode", input.len(), || {
+            mq2_uni_decode(&encoded).unwrap().len()
+        });
+        println!();
+    }
+
+    // Short inputs: one call per line, where per-call setup would show
+    let lines: Vec<&[u8]> = seed
+        .iter()
+        .flat_map(|doc| doc.split(|&b| b == b'\n').filter(|p| !p.is_empty()))
+        .collect();
+    let total: usize = lines.iter().map(|p| p.len()).sum();
+    println!("short inputs: {} lines, {total} bytes", lines.len());
+    compare(
+        total,
+        || lines.iter().map(|p| encode_linear(p).len()).sum(),
+        || lines.iter().map(|p| mq2_uni_encode(p).unwrap().len()).sum(),
+    );
+}
@@ -0,0 +1,238 @@
+// Encoder throughput on large inputs: trie matcher vs the old first-byte
+// bucket scan. Run with `cargo bench -p mq2-uni`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use mq2_uni::{decode, encode_with, Dictionary, ESC};
+
+// Reference: the previous encoder, scanning a longest-first bucket per byte
+fn encode_buckets(input: &[u8], dict: &Dictionary) -> Vec<u8> {
+    let mut buckets: Vec<Vec<(usize, u8, Vec<u8>)>> = vec![Vec::new(); 256];
+    for (tok, pat) in dict.tokens() {
+        if let Some(&b0) = pat.first() {
+            buckets[b0 as usize].push((pat.len(), *tok, pat.clone()));
+        }
+    }
+    let mut reserved = [false; 256];
+    reserved[ESC as usize] = true;
+    for (tok, _) in dict.tokens() {
+        reserved[*tok as usize] = true;
+    }
+    for bucket in buckets.iter_mut() {
+        bucket.sort_by_key(|b| std::cmp::Reverse(b.0));
+    }
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let mut matched = false;
+        for (len, tok, pat) in &buckets[input[i] as usize] {
+            if i + *len <= input.len() && input[i..i + *len] == pat[..] {
+                out.push(*tok);
+                i += *len;
+                matched = true;
+                break;
+            }
+        }
+        if !matched {
+            if reserved[input[i] as usize] {
+                out.push(ESC);
+            }
+            out.push(input[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+// 112 patterns, many sharing first bytes, to stress bucket scans
+fn dictionary() -> Dictionary {
+    let mut pats: Vec<Vec<u8>> = vec![
+        b"\n\n".to_vec(),
+        b"\n\n\n".to_vec(),
+        b"\n- ".to_vec(),
+        b"# ".to_vec(),
+        b"## ".to_vec(),
+        b"### ".to_vec(),
+        b"```".to_vec(),
+        b"```\n".to_vec(),
+        b"**".to_vec(),
+        b"    ".to_vec(),
+        b"        ".to_vec(),
+        b": ".to_vec(),
+        b", ".to_vec(),
+        b"](".to_vec(),
+        b"| ".to_vec(),
+        b" |".to_vec(),
+    ];
+    for w in [
+        "the ",
+        "that ",
+        "this ",
+        "then ",
+        "there ",
+        "these ",
+        "and ",
+        "any ",
+        "tion",
+        "tions ",
+        "ing ",
+        "ings ",
+        "for ",
+        "from ",
+        "format ",
+        "file ",
+        "compress",
+        "compression ",
+        "token",
+        "tokens ",
+        "dictionary ",
+        "marqant ",
+        "semantic ",
+        "section ",
+        "memory ",
+        "wave ",
+        "with ",
+        "which ",
+        "when ",
+        "where ",
+        "will ",
+        "would ",
+        "into ",
+        "in the ",
+        "of the ",
+        "to the ",
+        "is ",
+        "it ",
+        "in ",
+        "on ",
+        "or ",
+        "of ",
+        "as ",
+        "at ",
+        "an ",
+        "be ",
+        "by ",
+        "we ",
+        "you ",
+        "not ",
+        "can ",
+        "are ",
+        "all ",
+        "use ",
+        "data ",
+        "each ",
+        "more ",
+        "most ",
+        "must ",
+        "should ",
+        "support",
+        "pattern",
+        "patterns ",
+        "encode",
+        "decode",
+        "header",
+        "stream",
+        "bytes ",
+        "byte ",
+        "value ",
+        "values ",
+        "example",
+        "context ",
+        "content ",
+        "contents ",
+        "structure ",
+        "string ",
+        "strings ",
+        "system ",
+        "quantum ",
+        "version ",
+        "vision ",
+        "integration ",
+        "implementation ",
+        "architecture ",
+        "specification ",
+        "project ",
+        "process ",
+        "processing ",
+        "reference ",
+        "relation",
+        "relationship ",
+        "understand",
+        "understanding ",
+        "ation ",
+        "ment ",
+        "ments ",
+        "ness ",
+        "ly ",
+    ] {
+        pats.push(w.as_bytes().to_vec());
+    }
+    pats.truncate(112);
+    let tokens = pats
+        .into_iter()
+        .enumerate()
+        .map(|(i, p)| (0x80 + i as u8, p))
+        .collect();
+    Dictionary::new(tokens).unwrap()
+}
+
+fn corpus(min_len: usize) -> Vec<u8> {
+    let mut seed = Vec::new();
+    for entry in std::fs::read_dir("../example-md").expect("example-md") {
+        let path = entry.expect("entry").path();
+        if path.extension().is_some_and(|e| e == "md") {
+            seed.extend(std::fs::read(&path).expect("read"));
+        }
+    }
+    let mut out = Vec::with_capacity(min_len + seed.len());
+    while out.len() < min_len {
+        out.extend_from_slice(&seed);
+    }
+    out
+}
+
+fn bench(label: &str, input: &[u8], mut f: impl FnMut() -> usize) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+    }
+    let mbps = input.len() as f64 / best.as_secs_f64() / 1_000_000.0;
+    println!(
+        "{label:<10} {:>8.2} ms  {mbps:>8.1} MB/s",
+        best.as_secs_f64() * 1000.0
+    );
+    best
+}
+
+fn main() {
+    let dict = dictionary();
+    let matcher = dict.matcher();
+    for size in [1 << 20, 16 << 20] {
+        let input = corpus(size);
+        println!(
+            "input: {} bytes, {} patterns",
+            input.len(),
+            dict.tokens().len()
+        );
+
+        // Both strategies are longest-match, so the token streams must agree
+        let trie_out = encode_with(&input, &matcher, "0");
+        let body = trie_out
+            .windows(6)
+            .position(|w| w == b"\n~~~~\n")
+            .expect("~T end")
+            + 6;
+        assert_eq!(trie_out[body..], encode_buckets(&input, &dict)[..]);
+        assert_eq!(decode(&trie_out).expect("decode"), input);
+
+        let buckets = bench("buckets", &input, || encode_buckets(&input, &dict).len());
+        let trie = bench("trie", &input, || encode_with(&input, &matcher, "0").len());
+        println!(
+            "speedup    {:>8.2}x\n",
+            buckets.as_secs_f64() / trie.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,127 @@
+// mq2-uni-curate: propose a fixed-size UNI dictionary for a corpus.
+//
+// Usage:
+//   mq2-uni-curate <files|dirs...> [--size N] [--min-len N] [--max-len N] [--ext md]
+//                  [--id <dict-id>] [-t <out.T>] [--rust <out.rs>]
+//
+// Directories are walked recursively (files with --ext, default md), in
+// sorted order, so the same corpus always yields the same dictionary.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use mq2_uni::{curate, t_section, CurateOptions};
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    let mut opts = CurateOptions::default();
+    let mut ext = "md".to_string();
+    let mut dict_id: Option<String> = None;
+    let mut t_out: Option<PathBuf> = None;
+    let mut rust_out: Option<PathBuf> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {a}"));
+        match a.as_str() {
+            "--size" => opts.size = value()?.parse().map_err(|e| format!("--size: {e}"))?,
+            "--min-len" => {
+                opts.min_len = value()?.parse().map_err(|e| format!("--min-len: {e}"))?
+            }
+            "--max-len" => {
+                opts.max_len = value()?.parse().map_err(|e| format!("--max-len: {e}"))?
+            }
+            "--ext" => ext = value()?,
+            "--id" => dict_id = Some(value()?),
+            "-t" | "--t-out" => t_out = Some(PathBuf::from(value()?)),
+            "--rust" => rust_out = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => {
+                println!("usage: mq2-uni-curate <files|dirs...> [--size N] [--min-len N] [--max-len N] [--ext md] [--id <dict-id>] [-t <out.T>] [--rust <out.rs>]");
+                return Ok(());
+            }
+            s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+            _ => return Err(format!("unknown arg: {a}")),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("no corpus given".into());
+    }
+    if opts.size > 255 {
+        return Err("--size must be at most 255".into());
+    }
+
+    let mut files = Vec::new();
+    for input in &inputs {
+        collect(input, &ext, &mut files)?;
+    }
+    files.sort();
+    files.dedup();
+    let docs: Vec<Vec<u8>> = files
+        .iter()
+        .map(|p| fs::read(p).map_err(|e| format!("failed reading {}: {e}", p.display())))
+        .collect::<Result<_, _>>()?;
+
+    let curated = curate(&docs, &opts);
+    let dict_id =
+        dict_id.unwrap_or_else(|| format!("mq2-uni-{}", &curated.dict_hash()["fnv1a64:".len()..]));
+
+    println!(
+        "corpus: {} files, {} bytes",
+        files.len(),
+        curated.corpus_bytes
+    );
+    println!(
+        "encoded: {} bytes ({:.1}%), {} escapes",
+        curated.encoded_bytes,
+        100.0 * curated.encoded_bytes as f64 / curated.corpus_bytes.max(1) as f64,
+        curated.escapes
+    );
+    println!("dict_id: {}  ({})\n", dict_id, curated.dict_hash());
+    println!("rank  tok   savings     uses  pattern");
+    for (i, c) in curated.entries.iter().enumerate() {
+        let shown = String::from_utf8_lossy(&c.pattern);
+        println!(
+            "{:>4}  0x{:02X} {:>8} {:>8}  \"{}\"",
+            i + 1,
+            c.token,
+            c.savings,
+            c.uses,
+            shown.escape_debug()
+        );
+    }
+
+    if let Some(path) = t_out {
+        let dict = curated.dictionary().map_err(|e| e.to_string())?;
+        fs::write(&path, t_section(&dict))
+            .map_err(|e| format!("failed writing {}: {e}", path.display()))?;
+    }
+    if let Some(path) = rust_out {
+        fs::write(&path, curated.rust_source(&dict_id))
+            .map_err(|e| format!("failed writing {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn collect(path: &Path, ext: &str, out: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("failed reading {}: {e}", path.display()))?;
+        for entry in entries {
+            let p = entry.map_err(|e| e.to_string())?.path();
+            if p.is_dir() || p.extension().is_some_and(|e| e == ext) {
+                collect(&p, ext, out)?;
+            }
+        }
+    } else {
+        out.push(path.to_path_buf());
+    }
+    Ok(())
+}
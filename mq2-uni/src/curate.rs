@@ -0,0 +1,435 @@
+// Offline dictionary curation: propose a fixed-size UNI dictionary for a
+// corpus, ranked by byte savings. Deterministic for a given corpus and
+// options, so a new dictionary (and its id) can be regenerated exactly.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{encode_stream, t_section, Dictionary, DictionaryError, ESC, UTF8_SAFE_TOKENS};
+
+pub struct CurateOptions {
+    /// Number of tokens to propose (at most 255; `ESC` is never an id)
+    pub size: usize,
+    pub min_len: usize,
+    pub max_len: usize,
+}
+
+impl Default for CurateOptions {
+    fn default() -> Self {
+        Self {
+            size: UTF8_SAFE_TOKENS.len(),
+            min_len: 2,
+            max_len: 16,
+        }
+    }
+}
+
+pub struct Candidate {
+    pub token: u8,
+    pub pattern: Vec<u8>,
+    /// Times the encoder emits this token over the corpus
+    pub uses: usize,
+    /// `uses * (len - 1)` minus the `~T` entry cost, in bytes
+    pub savings: isize,
+}
+
+pub struct Curated {
+    /// Ranked by savings, best first
+    pub entries: Vec<Candidate>,
+    pub corpus_bytes: usize,
+    /// Encoded stream bytes plus the `~T` section, headers excluded
+    pub encoded_bytes: usize,
+    /// Literal bytes that had to be escaped because they collide with an id
+    pub escapes: usize,
+}
+
+impl Curated {
+    /// Fails only if `entries` were edited to reuse an id or take `ESC`.
+    pub fn dictionary(&self) -> Result<Dictionary, DictionaryError> {
+        Dictionary::new(self.pairs())
+    }
+
+    /// `fnv1a64:<hex>` over the `~T` section bytes
+    pub fn dict_hash(&self) -> String {
+        format!(
+            "fnv1a64:{:016x}",
+            fnv1a64(&t_section(&Dictionary {
+                tokens: self.pairs()
+            }))
+        )
+    }
+
+    fn pairs(&self) -> Vec<(u8, Vec<u8>)> {
+        self.entries
+            .iter()
+            .map(|c| (c.token, c.pattern.clone()))
+            .collect()
+    }
+
+    /// Rust source defining `MQ2_UNI_DICT_ID` and a `dictionary()` constructor
+    pub fn rust_source(&self, dict_id: &str) -> String {
+        let mut src = String::new();
+        src.push_str("// @generated by mq2-uni-curate. Do not edit by hand.\n");
+        src.push_str(&format!(
+            "// corpus: {} bytes -> {} bytes ({} escapes), {}\n\n",
+            self.corpus_bytes,
+            self.encoded_bytes,
+            self.escapes,
+            self.dict_hash()
+        ));
+        src.push_str("use mq2_uni::Dictionary;\n\n");
+        src.push_str(&format!(
+            "pub const MQ2_UNI_DICT_ID: &str = {:?};\n\n",
+            dict_id
+        ));
+        src.push_str("pub fn dictionary() -> Dictionary {\n    Dictionary::new(vec![\n");
+        for c in &self.entries {
+            src.push_str(&format!(
+                "        (0x{:02X}, b\"{}\".to_vec()), // {} uses, {} bytes saved\n",
+                c.token,
+                c.pattern.escape_ascii(),
+                c.uses,
+                c.savings
+            ));
+        }
+        src.push_str("    ])\n    .expect(\"curated token ids are distinct and never 0xFF\")\n}\n");
+        src
+    }
+}
+
+// Cost of one `~T` entry: id byte, u16 length, pattern
+fn entry_cost(pattern: &[u8]) -> isize {
+    3 + pattern.len() as isize
+}
+
+fn is_boundary(doc: &[u8], i: usize) -> bool {
+    i >= doc.len() || doc[i] & 0xC0 != 0x80
+}
+
+pub fn curate(docs: &[Vec<u8>], opts: &CurateOptions) -> Curated {
+    let size = opts.size.min(255);
+    let min_len = opts.min_len.max(2);
+    let max_len = opts.max_len;
+    // How far an n-gram overlapping a covered span can reach past it
+    let reach = max_len.saturating_sub(1);
+    let score = |pat: &[u8], n: usize| n as isize * (pat.len() as isize - 1) - entry_cost(pat);
+    let mut patterns: Vec<Vec<u8>> = Vec::new();
+
+    // Greedy: n-grams are counted in the literal runs no chosen pattern covers
+    // and the single best one is adopted, so overlapping picks are not
+    // double-counted. Counts carry over between rounds.
+    let mut runs: Vec<(usize, usize, usize)> = docs
+        .iter()
+        .enumerate()
+        .map(|(d, doc)| (d, 0, doc.len()))
+        .collect();
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for &(d, start, end) in &runs {
+        for_each_ngram(&docs[d], start, end, min_len, max_len, |pat| {
+            *counts.entry(pat).or_insert(0) += 1
+        });
+    }
+    // Covering bytes only lowers counts, so a popped entry whose score is
+    // still current is the best; stale ones go back with their new score.
+    let mut heap: BinaryHeap<(isize, usize, Reverse<&[u8]>)> = counts
+        .iter()
+        .map(|(&pat, &n)| (score(pat, n), pat.len(), Reverse(pat)))
+        .filter(|(score, _, _)| *score > 0)
+        .collect();
+    while patterns.len() < size {
+        let Some((best, len, Reverse(pat))) = heap.pop() else {
+            break;
+        };
+        let current = counts.get(pat).map_or(0, |&n| score(pat, n));
+        if current != best {
+            if current > 0 {
+                heap.push((current, len, Reverse(pat)));
+            }
+            continue;
+        }
+        patterns.push(pat.to_vec());
+
+        // Covering an occurrence only changes the n-grams that overlap it:
+        // recount the window they fit in, whole and around the occurrence.
+        let mut next = Vec::with_capacity(runs.len());
+        for &(d, start, end) in &runs {
+            let doc = &docs[d];
+            let mut from = start;
+            for a in occurrences(doc, start, end, pat) {
+                let b = a + pat.len();
+                let lo = clear_repeats(
+                    doc,
+                    from.max(a.saturating_sub(reach)),
+                    (from, end),
+                    min_len,
+                    max_len,
+                    false,
+                );
+                let hi =
+                    clear_repeats(doc, end.min(b + reach), (from, end), min_len, max_len, true);
+                let mut inc = |p| *counts.entry(p).or_insert(0) += 1;
+                for_each_ngram(doc, lo, a, min_len, max_len, &mut inc);
+                for_each_ngram(doc, b, hi, min_len, max_len, &mut inc);
+                for_each_ngram(doc, lo, hi, min_len, max_len, |p| {
+                    if let Some(n) = counts.get_mut(p) {
+                        *n = n.saturating_sub(1);
+                    }
+                });
+                if a - from >= min_len {
+                    next.push((d, from, a));
+                }
+                from = b;
+            }
+            if end - from >= min_len {
+                next.push((d, from, end));
+            }
+        }
+        runs = next;
+    }
+
+    // Measure real use with the final pattern set, then rank and assign ids
+    let measure = Dictionary {
+        tokens: patterns
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i as u8, p.clone()))
+            .collect(),
+    };
+    let matcher = measure.matcher();
+    let mut uses = vec![0usize; patterns.len()];
+    for doc in docs {
+        let mut i = 0;
+        while i < doc.len() {
+            match matcher.longest_match(&doc[i..]) {
+                Some((tok, len)) => {
+                    uses[tok as usize] += 1;
+                    i += len;
+                }
+                None => i += 1,
+            }
+        }
+    }
+    let mut entries: Vec<Candidate> = patterns
+        .into_iter()
+        .zip(uses)
+        .map(|(pattern, uses)| {
+            let savings = uses as isize * (pattern.len() as isize - 1) - entry_cost(&pattern);
+            Candidate {
+                token: 0,
+                pattern,
+                uses,
+                savings,
+            }
+        })
+        .filter(|c| c.savings > 0)
+        .collect();
+    entries.sort_by(|a, b| b.savings.cmp(&a.savings).then(a.pattern.cmp(&b.pattern)));
+    for (c, id) in entries.iter_mut().zip(token_ids(docs)) {
+        c.token = id;
+    }
+
+    let dict = Dictionary {
+        tokens: entries
+            .iter()
+            .map(|c| (c.token, c.pattern.clone()))
+            .collect(),
+    };
+    let matcher = dict.matcher();
+    let mut encoded_bytes = t_section(&dict).len();
+    let mut escapes = 0;
+    for doc in docs {
+        let stream = encode_stream(doc, &matcher);
+        encoded_bytes += stream.len();
+        escapes += stream.iter().filter(|&&b| b == ESC).count();
+    }
+
+    Curated {
+        entries,
+        corpus_bytes: docs.iter().map(Vec::len).sum(),
+        encoded_bytes,
+        escapes,
+    }
+}
+
+// Non-overlapping occurrences of each candidate n-gram in `doc[start..end]`,
+// scanning left to right. Counts add up over disjoint runs.
+fn for_each_ngram<'a>(
+    doc: &'a [u8],
+    start: usize,
+    end: usize,
+    min_len: usize,
+    max_len: usize,
+    mut f: impl FnMut(&'a [u8]),
+) {
+    // End of the last counted occurrence, by pattern
+    let mut last_end: HashMap<&[u8], usize> = HashMap::new();
+    for i in (start..end).filter(|&i| is_boundary(doc, i)) {
+        for len in min_len..=max_len.min(end - i) {
+            if !is_boundary(doc, i + len) {
+                continue;
+            }
+            let pat = &doc[i..i + len];
+            let e = last_end.entry(pat).or_insert(0);
+            if i >= *e {
+                *e = i + len;
+                f(pat);
+            }
+        }
+    }
+}
+
+// Moves a recount window edge `c` out of any stretch of `run` with a
+// period below `max_len` that holds two overlapping n-grams. Non-overlapping
+// counts only add up over whole chains of overlapping occurrences, so the
+// window must not cut one.
+fn clear_repeats(
+    doc: &[u8],
+    mut c: usize,
+    (start, end): (usize, usize),
+    min_len: usize,
+    max_len: usize,
+    forward: bool,
+) -> usize {
+    loop {
+        let before = c;
+        for p in 1..max_len {
+            // Stretches with period p that hold both `c - 1` and `c` match
+            // `doc[x] == doc[x - p]` somewhere in `c..c + p`
+            let mut x = c.max(start + p);
+            while x < (c + p).min(end) {
+                if doc[x] != doc[x - p] {
+                    x += 1;
+                    continue;
+                }
+                let (mut x0, mut x1) = (x, x + 1);
+                while x0 > start + p && doc[x0 - 1] == doc[x0 - 1 - p] {
+                    x0 -= 1;
+                }
+                while x1 < end && doc[x1] == doc[x1 - p] {
+                    x1 += 1;
+                }
+                if x0 - p < c && x1 > c && x1 - x0 >= min_len.max(p + 1) {
+                    c = if forward { x1 } else { x0 - p };
+                }
+                x = x1;
+            }
+        }
+        if c == before {
+            return c;
+        }
+    }
+}
+
+// Starts of the occurrences of `pat` that `for_each_ngram` counts
+fn occurrences(doc: &[u8], start: usize, end: usize, pat: &[u8]) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut i = start;
+    while i + pat.len() <= end {
+        if doc[i..].starts_with(pat) && is_boundary(doc, i) && is_boundary(doc, i + pat.len()) {
+            found.push(i);
+            i += pat.len();
+        } else {
+            i += 1;
+        }
+    }
+    found
+}
+
+// Ids least likely to need escaping: UTF-8-invalid bytes first, then the
+// rarest bytes in the corpus. Ties break on byte value for determinism.
+fn token_ids(docs: &[Vec<u8>]) -> Vec<u8> {
+    let mut freq = [0usize; 256];
+    for doc in docs {
+        for &b in doc {
+            freq[b as usize] += 1;
+        }
+    }
+    let mut rest: Vec<u8> = (0..=255u8)
+        .filter(|b| *b != ESC && !UTF8_SAFE_TOKENS.contains(b))
+        .collect();
+    rest.sort_by_key(|b| (freq[*b as usize], *b));
+    let mut safe = UTF8_SAFE_TOKENS.to_vec();
+    safe.sort_by_key(|b| (freq[*b as usize], *b));
+    safe.into_iter().chain(rest).collect()
+}
+
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x00000100000001B3);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposes_repeated_markdown_patterns() {
+        let doc =
+            "## Setup\n\nRun the installer.\n\n## Usage\n\nRun the installer again.\n\n".repeat(20);
+        let curated = curate(&[doc.clone().into_bytes()], &CurateOptions::default());
+        assert!(!curated.entries.is_empty());
+        assert!(curated
+            .entries
+            .windows(2)
+            .all(|w| w[0].savings >= w[1].savings));
+        assert!(curated
+            .entries
+            .iter()
+            .all(|c| UTF8_SAFE_TOKENS.contains(&c.token)));
+        assert!(curated.encoded_bytes < curated.corpus_bytes);
+
+        let dict = curated.dictionary().unwrap();
+        let enc = crate::encode(doc.as_bytes(), &dict, "0");
+        assert_eq!(crate::decode(&enc).unwrap(), doc.as_bytes());
+    }
+
+    #[test]
+    fn output_is_deterministic() {
+        let docs = vec![
+            "# a b c\n\n- item one\n- item two\n".repeat(8).into_bytes(),
+            "日本語のテキスト。日本語のテキスト。".into(),
+        ];
+        let a = curate(&docs, &CurateOptions::default());
+        let b = curate(&docs, &CurateOptions::default());
+        assert_eq!(a.rust_source("mq2-uni-test"), b.rust_source("mq2-uni-test"));
+        // Patterns never split a UTF-8 character
+        assert!(a
+            .entries
+            .iter()
+            .all(|c| std::str::from_utf8(&c.pattern).is_ok()));
+    }
+
+    #[test]
+    fn counts_occurrences_per_document() {
+        // "needle-phrase" ends late in the first document and only appears
+        // early in the others, so a shared end offset would skip them all
+        // Filler with no repeated 13-byte runs, so it proposes nothing itself
+        let mut seed = 1u32;
+        let mut first: Vec<u8> = (0..5000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                b'a' + (seed >> 16) as u8 % 26
+            })
+            .collect();
+        first.extend_from_slice(b"needle-phrase");
+        let mut docs = vec![first];
+        for _ in 0..4 {
+            docs.push(b"needle-phrase and the rest".to_vec());
+        }
+        let curated = curate(
+            &docs,
+            &CurateOptions {
+                size: 1,
+                min_len: 13,
+                max_len: 13,
+            },
+        );
+        assert_eq!(curated.entries.len(), 1);
+        assert_eq!(curated.entries[0].pattern, b"needle-phrase");
+        assert_eq!(curated.entries[0].uses, 5);
+    }
+}
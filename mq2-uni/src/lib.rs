@@ -1,52 +1,102 @@
 // MQ2-UNI reference encoder/decoder (deterministic, fixed dictionary)
 // Minimal, no-alloc hot path where possible.
+//
+// `no_std` + `alloc` without the default `std` feature. Decoding into a
+// caller buffer (`decode_into`) and streaming encode (`StreamEncoder`) do not
+// allocate once a `Matcher` is built; curation needs `std`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+mod curate;
+mod matcher;
+#[cfg(feature = "std")]
+pub use curate::{curate, Candidate, CurateOptions, Curated};
+pub use matcher::{Matcher, StreamEncoder};
+
+/// Escape byte. `0xFF` never occurs in well-formed UTF-8 and may not be a
+/// token id; in the stream, `ESC b` means the literal byte `b`.
+pub const ESC: u8 = 0xFF;
+
+/// Token ids that never occur in well-formed UTF-8 (`0xC0`, `0xC1`,
+/// `0xF5..=0xFE`). Dictionaries restricted to these never need to escape
+/// UTF-8 text; any other id still round-trips, at one extra byte per
+/// colliding literal.
+pub const UTF8_SAFE_TOKENS: [u8; 12] = [
+    0xC0, 0xC1, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD, 0xFE,
+];
 
 pub struct Dictionary {
     // token -> bytes
-    pub tokens: Vec<(u8, Vec<u8>)>,
+    tokens: Vec<(u8, Vec<u8>)>,
 }
 
 impl Dictionary {
-    pub fn new(tokens: Vec<(u8, Vec<u8>)>) -> Self { Self { tokens } }
+    /// Refuses [`ESC`] as a token id and ids given twice; either would make
+    /// the stream ambiguous.
+    pub fn new(tokens: Vec<(u8, Vec<u8>)>) -> Result<Self, DictionaryError> {
+        let mut seen = [false; 256];
+        for (t, _) in &tokens {
+            if *t == ESC {
+                return Err(DictionaryError::ReservedToken);
+            }
+            if core::mem::replace(&mut seen[*t as usize], true) {
+                return Err(DictionaryError::DuplicateToken(*t));
+            }
+        }
+        Ok(Self { tokens })
+    }
+
+    /// `(token, pattern)` pairs, in the order given.
+    pub fn tokens(&self) -> &[(u8, Vec<u8>)] {
+        &self.tokens
+    }
+
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher::new(self)
+    }
 }
 
-// Simple header: MQ2~UNI~<ts_hex>~<orig_hex>~<comp_hex>~<tokc_hex>~text
+// Simple header: MQ2~UNI~<ts_hex>~<orig_hex>~<comp_hex>~<tokc_hex>~<format>
+// `esc` streams escape literal bytes that collide with token ids (or ESC);
+// legacy `text` streams pass every non-token byte through unescaped.
 fn header(ts_hex: &str, orig: usize, comp: usize, tokc: usize) -> String {
-    format!("MQ2~UNI~{}~{:X}~{:X}~{:X}~text\n", ts_hex, orig, comp, tokc)
+    format!("MQ2~UNI~{}~{:X}~{:X}~{:X}~esc\n", ts_hex, orig, comp, tokc)
 }
 
 pub fn encode(input: &[u8], dict: &Dictionary, ts_hex: &str) -> Vec<u8> {
-    // Build lookup by first byte for greedy longest match
-    let mut buckets: Vec<Vec<(usize, u8, Vec<u8>)>> = vec![Vec::new(); 256];
-    for (tok, pat) in &dict.tokens {
-        if let Some(&b0) = pat.first() {
-            buckets[b0 as usize].push((pat.len(), *tok, pat.clone()));
-        }
-    }
-    for bucket in buckets.iter_mut() {
-        bucket.sort_by(|a, b| b.0.cmp(&a.0)); // longest first
-    }
+    encode_with(input, &dict.matcher(), ts_hex)
+}
 
-    let mut out: Vec<u8> = Vec::with_capacity(input.len());
-    let mut i = 0;
-    while i < input.len() {
-        let b0 = input[i];
-        let mut matched = false;
-        for (len, tok, pat) in &buckets[b0 as usize] {
-            if i + *len <= input.len() && &input[i..i + *len] == &pat[..] {
-                out.push(*tok);
-                i += *len;
-                matched = true;
-                break;
-            }
-        }
-        if !matched {
-            out.push(input[i]); // passthrough
-            i += 1;
-        }
-    }
+/// Like [`encode`], but reuses a prebuilt [`Matcher`] (and its dictionary)
+/// across calls.
+pub fn encode_with(input: &[u8], matcher: &Matcher, ts_hex: &str) -> Vec<u8> {
+    let dict = matcher.dictionary();
+    let out = encode_stream(input, matcher);
+    let header = header(ts_hex, input.len(), out.len(), dict.tokens.len());
+    let mut result = header.into_bytes();
+    result.extend_from_slice(&t_section(dict));
+    result.extend_from_slice(&out);
+    result
+}
+
+// Token stream only: no header, no ~T section
+pub(crate) fn encode_stream(input: &[u8], matcher: &Matcher) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    out.extend(StreamEncoder::new(input, matcher));
+    out
+}
 
-    // Build ~T map (sorted by token)
+/// The `~T` section for `dict`, entries sorted by token, through `\n~~~~\n`.
+pub fn t_section(dict: &Dictionary) -> Vec<u8> {
     let mut tok_map = dict.tokens.clone();
     tok_map.sort_by_key(|(t, _)| *t);
     let mut t_section = Vec::new();
@@ -58,42 +108,193 @@ pub fn encode(input: &[u8], dict: &Dictionary, ts_hex: &str) -> Vec<u8> {
         t_section.extend_from_slice(&pat);
     }
     t_section.extend_from_slice(b"\n~~~~\n");
-
-    let header = header(ts_hex, input.len(), out.len(), dict.tokens.len());
-    let mut result = header.into_bytes();
-    result.extend_from_slice(&t_section);
-    result.extend_from_slice(&out);
-    result
+    t_section
 }
 
 pub fn decode(encoded: &[u8]) -> Option<Vec<u8>> {
-    // Parse header line
-    let mut parts = encoded.splitn(2, |&b| b == b'\n');
-    let header = parts.next()?;
-    let rest = parts.next()?;
-    if !header.starts_with(b"MQ2~UNI~") { return None; }
-
-    // Parse ~T section until ~~~~\n
-    if !rest.starts_with(b"~T") { return None; }
-    let mut i = 2;
-    let mut tok_map: Vec<Option<Vec<u8>>> = vec![None; 256];
-    while i + 1 < rest.len() {
-        if i + 6 <= rest.len() && &rest[i..i+6] == b"\n~~~~\n" { i += 6; break; }
-        let tok = rest[i];
-        let len = u16::from_be_bytes([rest[i+1], rest[i+2]]) as usize;
-        i += 3;
-        if i + len > rest.len() { return None; }
-        tok_map[tok as usize] = Some(rest[i..i+len].to_vec());
-        i += len;
-    }
-    let stream = &rest[i..];
-    let mut out = Vec::with_capacity(stream.len());
-    for &b in stream {
-        if let Some(pat) = &tok_map[b as usize] { out.extend_from_slice(pat) } else { out.push(b) }
-    }
+    let parsed = Parsed::new(encoded).ok()?;
+    let mut out = vec![0; parsed.decoded_len().ok()?];
+    let n = parsed.decode_into(&mut out).ok()?;
+    out.truncate(n);
     Some(out)
 }
 
+/// Decode into `out` without allocating; returns the number of bytes written.
+pub fn decode_into(encoded: &[u8], out: &mut [u8]) -> Result<usize, DecodeError> {
+    Parsed::new(encoded)?.decode_into(out)
+}
+
+/// Exact decoded size, found by walking the stream (no allocation).
+pub fn decoded_len(encoded: &[u8]) -> Result<usize, DecodeError> {
+    Parsed::new(encoded)?.decoded_len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryError {
+    /// A token id was [`ESC`]
+    ReservedToken,
+    /// A token id was given twice
+    DuplicateToken(u8),
+}
+
+impl fmt::Display for DictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryError::ReservedToken => {
+                f.write_str("0xFF is reserved as the MQ2-UNI escape byte")
+            }
+            DictionaryError::DuplicateToken(t) => write!(f, "token 0x{:02X} is given twice", t),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DictionaryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not an MQ2-UNI payload, or a truncated header, `~T` section or escape
+    Malformed,
+    /// The output buffer cannot hold the decoded bytes
+    BufferTooSmall,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Malformed => f.write_str("malformed MQ2-UNI payload"),
+            DecodeError::BufferTooSmall => f.write_str("output buffer too small"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+// Header and ~T section parsed in place: patterns borrow from the payload
+#[derive(Clone, Copy)]
+struct Parsed<'a> {
+    tok_map: [Option<&'a [u8]>; 256],
+    stream: &'a [u8],
+    escaped: bool,
+}
+
+impl<'a> Parsed<'a> {
+    fn new(encoded: &'a [u8]) -> Result<Self, DecodeError> {
+        // Parse header line
+        let nl = encoded
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(DecodeError::Malformed)?;
+        let (header, rest) = (&encoded[..nl], &encoded[nl + 1..]);
+        if !header.starts_with(b"MQ2~UNI~") {
+            return Err(DecodeError::Malformed);
+        }
+        let escaped = header.ends_with(b"~esc");
+
+        // Parse ~T section until ~~~~\n
+        if !rest.starts_with(b"~T") {
+            return Err(DecodeError::Malformed);
+        }
+        let mut i = 2;
+        let mut tok_map = [None; 256];
+        while i + 1 < rest.len() {
+            if rest[i..].starts_with(b"\n~~~~\n") {
+                i += 6;
+                break;
+            }
+            if i + 3 > rest.len() {
+                return Err(DecodeError::Malformed);
+            }
+            let tok = rest[i];
+            let len = u16::from_be_bytes([rest[i + 1], rest[i + 2]]) as usize;
+            i += 3;
+            if i + len > rest.len() {
+                return Err(DecodeError::Malformed);
+            }
+            tok_map[tok as usize] = Some(&rest[i..i + len]);
+            i += len;
+        }
+        Ok(Parsed {
+            tok_map,
+            stream: &rest[i.min(rest.len())..],
+            escaped,
+        })
+    }
+
+    fn decoded_len(self) -> Result<usize, DecodeError> {
+        let mut n = 0;
+        for run in self.chunks() {
+            n += run?.len();
+        }
+        Ok(n)
+    }
+
+    fn decode_into(self, out: &mut [u8]) -> Result<usize, DecodeError> {
+        let mut n = 0;
+        for run in self.chunks() {
+            let run = run?;
+            let dst = out
+                .get_mut(n..n + run.len())
+                .ok_or(DecodeError::BufferTooSmall)?;
+            dst.copy_from_slice(run);
+            n += run.len();
+        }
+        Ok(n)
+    }
+
+    fn chunks(self) -> Chunks<'a> {
+        Chunks {
+            parsed: self,
+            pos: 0,
+        }
+    }
+}
+
+/// Zero-copy incremental decode: yields the output in order as slices of the
+/// payload, each a literal run or a dictionary pattern. Nothing is allocated.
+pub fn decode_chunks(encoded: &[u8]) -> Result<Chunks<'_>, DecodeError> {
+    Ok(Parsed::new(encoded)?.chunks())
+}
+
+/// Iterator returned by [`decode_chunks`]. Yields `Err(Malformed)` once, at a
+/// dangling escape byte, and then ends.
+pub struct Chunks<'a> {
+    parsed: Parsed<'a>,
+    pos: usize,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<&'a [u8], DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = &self.parsed;
+        let stream = p.stream;
+        let start = self.pos;
+        let b = *stream.get(start)?;
+        if p.escaped && b == ESC {
+            // An escaped literal is the byte after ESC, borrowed in place
+            let Some(lit) = stream.get(start + 1..start + 2) else {
+                self.pos = stream.len();
+                return Some(Err(DecodeError::Malformed));
+            };
+            self.pos += 2;
+            return Some(Ok(lit));
+        }
+        if let Some(pat) = p.tok_map[b as usize] {
+            self.pos += 1;
+            return Some(Ok(pat));
+        }
+        // Literal run: up to the next token or escape
+        let len = stream[start..]
+            .iter()
+            .position(|&b| p.tok_map[b as usize].is_some() || (p.escaped && b == ESC))
+            .unwrap_or(stream.len() - start);
+        self.pos += len;
+        Some(Ok(&stream[start..start + len]))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,12 +305,112 @@ mod tests {
             (0x81, b"# ".to_vec()),
             (0x82, b"\n\n".to_vec()),
             (0x83, b"**".to_vec()),
-        ]);
+        ])
+        .unwrap();
         let input = b"# T\n\n## H\n\n**x**";
         let enc = encode(input, &dict, "00000000");
         let dec = decode(&enc).unwrap();
         assert_eq!(input, &dec[..]);
     }
-}
 
+    #[test]
+    fn dictionary_rejects_escape_and_duplicate_ids() {
+        assert_eq!(
+            Dictionary::new(vec![(ESC, b"# ".to_vec())]).err(),
+            Some(DictionaryError::ReservedToken)
+        );
+        let twice = vec![(0xC0, b"# ".to_vec()), (0xC0, b"## ".to_vec())];
+        assert_eq!(
+            Dictionary::new(twice).err(),
+            Some(DictionaryError::DuplicateToken(0xC0))
+        );
+    }
+
+    #[test]
+    fn high_byte_tokens_do_not_corrupt_utf8() {
+        // 0x80..0x83 are UTF-8 continuation bytes, e.g. inside "€" and "🚀"
+        let dict = Dictionary::new(vec![
+            (0x80, b"## ".to_vec()),
+            (0x81, b"# ".to_vec()),
+            (0x82, b"\n\n".to_vec()),
+            (0xFE, b"**".to_vec()),
+        ])
+        .unwrap();
+        let input = "# €\n\n## 🚀 **ありがとう** \u{FFFD}".as_bytes();
+        let enc = encode(input, &dict, "0");
+        assert_eq!(decode(&enc).unwrap(), input);
+    }
+
+    #[test]
+    fn safe_range_never_escapes_utf8() {
+        let tokens = UTF8_SAFE_TOKENS
+            .iter()
+            .zip([&b"# "[..], b"\n\n"])
+            .map(|(t, p)| (*t, p.to_vec()))
+            .collect();
+        let dict = Dictionary::new(tokens).unwrap();
+        let input = "# 日本語\n\n👨‍👩‍👧‍👦".as_bytes();
+        let enc = encode(input, &dict, "0");
+        assert!(!enc.contains(&ESC));
+        assert_eq!(decode(&enc).unwrap(), input);
+    }
+
+    #[test]
+    fn legacy_text_streams_still_decode() {
+        let enc = b"MQ2~UNI~0~4~2~1~text\n~T\x80\x00\x02# \n~~~~\n\x80T";
+        assert_eq!(decode(enc).unwrap(), b"# T");
+        // A dangling escape is malformed
+        assert!(decode(b"MQ2~UNI~0~0~1~0~esc\n~T\n~~~~\n\xFF").is_none());
+    }
+
+    #[test]
+    fn decode_into_caller_buffer() {
+        let dict = Dictionary::new(vec![(0xC0, b"# ".to_vec()), (0xC1, b"\n\n".to_vec())]).unwrap();
+        let input = "# Title\n\n# 見出し\n\n".as_bytes();
+        let enc = encode(input, &dict, "0");
+
+        let mut buf = [0u8; 64];
+        let n = decode_into(&enc, &mut buf).unwrap();
+        assert_eq!(&buf[..n], input);
+        assert_eq!(decoded_len(&enc), Ok(input.len()));
+
+        let mut small = [0u8; 8];
+        assert_eq!(
+            decode_into(&enc, &mut small),
+            Err(DecodeError::BufferTooSmall)
+        );
+        assert_eq!(
+            decode_into(b"garbage", &mut buf),
+            Err(DecodeError::Malformed)
+        );
+    }
 
+    #[test]
+    fn chunks_borrow_literal_runs_and_patterns() {
+        let dict = Dictionary::new(vec![(0xC0, b"# ".to_vec()), (0x80, b"\n\n".to_vec())]).unwrap();
+        let input = "# Title\n\nbody €".as_bytes();
+        let enc = encode(input, &dict, "0");
+        let chunks: Vec<&[u8]> = decode_chunks(&enc).unwrap().map(Result::unwrap).collect();
+        // "€" is E2 82 AC: no token byte, so it stays in the literal run
+        assert_eq!(chunks, [&b"# "[..], b"Title", b"\n\n", "body €".as_bytes()]);
+        let range = enc.as_ptr_range();
+        assert!(chunks.iter().all(|c| range.contains(&c.as_ptr())));
+
+        let dangling = b"MQ2~UNI~0~0~1~0~esc\n~T\n~~~~\nab\xFF";
+        let mut it = decode_chunks(dangling).unwrap();
+        assert_eq!(it.next(), Some(Ok(&b"ab"[..])));
+        assert_eq!(it.next(), Some(Err(DecodeError::Malformed)));
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn stream_encoder_matches_encode() {
+        let dict = Dictionary::new(vec![(0x80, b"## ".to_vec()), (0xC0, b"**".to_vec())]).unwrap();
+        let matcher = dict.matcher();
+        let input = "## €uro **bold**".as_bytes();
+        let streamed: Vec<u8> = StreamEncoder::new(input, &matcher).collect();
+        let enc = encode(input, &dict, "0");
+        assert!(enc.ends_with(&streamed));
+        assert_eq!(StreamEncoder::new(input, &matcher).count(), streamed.len());
+    }
+}
@@ -0,0 +1,177 @@
+// Longest-match automaton over the dictionary patterns.
+// A byte trie walked once per input position. No per-pattern loop, so cost
+// is bounded by the longest pattern rather than the dictionary size. The
+// root, where every walk starts, has a dense 256-wide row; other nodes keep
+// sorted sparse edges, so memory grows with the pattern bytes, not 1 KiB
+// per node.
+
+use alloc::vec::Vec;
+
+use crate::{Dictionary, ESC};
+
+const NONE: u32 = u32::MAX;
+
+struct Node {
+    // (byte, child node), sorted by byte
+    edges: Vec<(u8, u32)>,
+    // token emitted when a pattern ends here
+    accept: Option<u8>,
+}
+
+/// Built from, and tied to, one [`Dictionary`]: encoding with a matcher
+/// always writes that dictionary's header and `~T` section.
+pub struct Matcher<'d> {
+    dict: &'d Dictionary,
+    // byte -> node reached from the root (NONE if absent)
+    root: [u32; 256],
+    nodes: Vec<Node>,
+    // bytes a literal must be escaped for: token ids and ESC
+    reserved: [bool; 256],
+}
+
+impl<'d> Matcher<'d> {
+    pub fn new(dict: &'d Dictionary) -> Self {
+        let mut m = Matcher {
+            dict,
+            root: [NONE; 256],
+            nodes: Vec::new(),
+            reserved: [false; 256],
+        };
+        m.reserved[ESC as usize] = true;
+        for (tok, pat) in &dict.tokens {
+            m.reserved[*tok as usize] = true;
+            let Some((&first, rest)) = pat.split_first() else {
+                continue;
+            };
+            let mut node = m.root[first as usize];
+            if node == NONE {
+                node = m.push_node();
+                m.root[first as usize] = node;
+            }
+            for &b in rest {
+                let edges = &m.nodes[node as usize].edges;
+                node = match edges.binary_search_by_key(&b, |e| e.0) {
+                    Ok(i) => edges[i].1,
+                    Err(i) => {
+                        let child = m.push_node();
+                        m.nodes[node as usize].edges.insert(i, (b, child));
+                        child
+                    }
+                };
+            }
+            // First definition of a pattern wins, matching dictionary order
+            let accept = &mut m.nodes[node as usize].accept;
+            if accept.is_none() {
+                *accept = Some(*tok);
+            }
+        }
+        for node in &mut m.nodes {
+            node.edges.shrink_to_fit();
+        }
+        m
+    }
+
+    fn push_node(&mut self) -> u32 {
+        self.nodes.push(Node {
+            edges: Vec::new(),
+            accept: None,
+        });
+        self.nodes.len() as u32 - 1
+    }
+
+    /// The dictionary this matcher was built from.
+    pub fn dictionary(&self) -> &'d Dictionary {
+        self.dict
+    }
+
+    /// Longest pattern that is a prefix of `input`, as `(token, pattern_len)`.
+    #[inline]
+    pub fn longest_match(&self, input: &[u8]) -> Option<(u8, usize)> {
+        let (&first, rest) = input.split_first()?;
+        let mut node = self.root[first as usize];
+        if node == NONE {
+            return None;
+        }
+        let mut best = self.nodes[node as usize].accept.map(|tok| (tok, 1));
+        for (i, &b) in rest.iter().enumerate() {
+            let edges = &self.nodes[node as usize].edges;
+            match edges.binary_search_by_key(&b, |e| e.0) {
+                Ok(j) => node = edges[j].1,
+                Err(_) => break,
+            }
+            if let Some(tok) = self.nodes[node as usize].accept {
+                best = Some((tok, i + 2));
+            }
+        }
+        best
+    }
+
+    /// Whether a literal `b` must be escaped in the stream.
+    pub fn is_reserved(&self, b: u8) -> bool {
+        self.reserved[b as usize]
+    }
+}
+
+/// Iterator over the encoded token stream (no header or `~T` section);
+/// allocation-free given a prebuilt [`Matcher`].
+pub struct StreamEncoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    matcher: &'a Matcher<'a>,
+    // literal byte still owed after an ESC
+    pending: Option<u8>,
+}
+
+impl<'a> StreamEncoder<'a> {
+    pub fn new(input: &'a [u8], matcher: &'a Matcher<'a>) -> Self {
+        Self {
+            input,
+            pos: 0,
+            matcher,
+            pending: None,
+        }
+    }
+}
+
+impl Iterator for StreamEncoder<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if let Some(b) = self.pending.take() {
+            return Some(b);
+        }
+        let rest = self.input.get(self.pos..).filter(|r| !r.is_empty())?;
+        if let Some((tok, len)) = self.matcher.longest_match(rest) {
+            self.pos += len;
+            return Some(tok);
+        }
+        // Passthrough, escaping bytes the decoder would read as tokens
+        let b = rest[0];
+        self.pos += 1;
+        if self.matcher.is_reserved(b) {
+            self.pending = Some(b);
+            return Some(ESC);
+        }
+        Some(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_longest_pattern() {
+        let dict = Dictionary::new(vec![
+            (0x80, b"\n\n".to_vec()),
+            (0x81, b"\n\n\n".to_vec()),
+            (0x82, b"#".to_vec()),
+        ])
+        .unwrap();
+        let m = Matcher::new(&dict);
+        assert_eq!(m.longest_match(b"\n\n\nx"), Some((0x81, 3)));
+        assert_eq!(m.longest_match(b"\n\nx"), Some((0x80, 2)));
+        assert_eq!(m.longest_match(b"\nx"), None);
+        assert_eq!(m.longest_match(b"## "), Some((0x82, 1)));
+    }
+}
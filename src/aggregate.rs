@@ -0,0 +1,939 @@
+// Aggregate bundles (`example-md/MARQANT_AGGREGATE_SPEC.md`): many markdown
+// (or other text) files in one `.mq`, sharing a single dictionary.
+//
+//   MARQANT_V2 <ts> <orig> <comp> -aggregate [-zlib] [-seekable] [-std:<id>]
+//   ::manifest::
+//   [::shared:<start>:<length>:b<blocks>:d<saved>:r<char>]
+//   <path>:<start>:<length>[:s<size>][:m<mtime>][:o<mode>][:d<saved>]
+//   [::removed:<path>:<start>:<length>]
+//   [::budget:<tokens>
+//   ::dropped:<path>:<tokens>]
+//   ::end-manifest::
+//   <token>=<pattern>
+//   ---
+//   [::shared::
+//   <tokenized shared blocks>]
+//   ::file:<path>::
+//   <tokenized content>
+//
+// Manifest offsets index the tokenized body (after inflating, for `-zlib`;
+// across blocks, for `-seekable`, where every file starts a block) and span
+// each file's `::file:` marker line plus its content. `<orig>` is the total
+// size of the source files, `<comp>` everything after the header. The
+// optional tagged fields record each source's size, modification time
+// (Unix seconds) and, for documents read from archives, permission bits
+// (octal), so bundles can be listed without touching the body.
+//
+// Paragraphs and code blocks that recur across files are stored once in
+// the `::shared::` segment (see `dedup`) and referenced from each file;
+// `d` records the bytes that saved, net of the segment on the `::shared`
+// line, and `r` the code point of the reference char.
+//
+// `Marqant::update_aggregate` appends new sections under the existing
+// dictionary and turns the ones they supersede into `::removed:`
+// tombstones: dead bytes that `Marqant::compact_aggregate` reclaims.
+// Bundles packed to a token budget (`Marqant::pack`) record the budget and
+// the documents left out, with their estimated tokens.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::seek::{self, SeekEntry};
+use crate::{
+    decoded_len, now_timestamp, parse_std_flag, read_dictionary, std_tokens, write_dictionary,
+    DecodeOptions, Marqant, Selection, TokenTable,
+};
+use crate::{dedup, filetype};
+
+pub(crate) const AGGREGATE_MAGIC: &str = "MARQANT_V2";
+const MANIFEST_START: &str = "::manifest::";
+const MANIFEST_END: &str = "::end-manifest::";
+const SHARED_LINE: &str = "::shared:";
+const SHARED_MARKER: &str = "::shared::\n";
+const TOMBSTONE_LINE: &str = "::removed:";
+const BUDGET_LINE: &str = "::budget:";
+const DROPPED_LINE: &str = "::dropped:";
+
+/// Where one document sits in an aggregate's tokenized body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AggregateEntry {
+    pub path: String,
+    pub start: usize,
+    pub len: usize,
+    /// Size of the source document.
+    pub size: Option<u64>,
+    /// Modification time of the source, in Unix seconds.
+    pub mtime: Option<u64>,
+    /// Unix permission bits of the source, when it came from an archive.
+    pub mode: Option<u32>,
+    /// Bytes saved by referencing shared blocks instead of repeating them.
+    pub dedup: Option<u64>,
+}
+
+/// The segment of an aggregate holding the blocks its documents share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SharedBlocks {
+    pub start: usize,
+    pub len: usize,
+    /// Number of distinct blocks stored.
+    pub count: usize,
+    /// Bytes saved across all documents, less the segment itself.
+    pub savings: u64,
+    /// Char that introduces a reference in the documents.
+    pub ref_char: char,
+}
+
+/// The token budget a packed aggregate was made for, and what did not fit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Packing {
+    pub budget: usize,
+    /// Documents left out, with their estimated tokens, best ranked first.
+    pub dropped: Vec<(String, usize)>,
+}
+
+/// One document to bundle.
+#[derive(Debug, Clone, Default)]
+pub struct AggregateFile {
+    /// Name inside the bundle, `/`-separated.
+    pub path: String,
+    pub content: String,
+    pub mtime: Option<u64>,
+    /// Unix permission bits, kept in the manifest when known.
+    pub mode: Option<u32>,
+}
+
+/// A parsed aggregate bundle; documents are decoded on demand.
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+    pub timestamp: String,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub flags: Vec<String>,
+    pub entries: Vec<AggregateEntry>,
+    pub shared: Option<SharedBlocks>,
+    /// Sections superseded by an update, kept until the bundle is compacted.
+    pub tombstones: Vec<AggregateEntry>,
+    pub packing: Option<Packing>,
+    tokens: HashMap<String, String>,
+    body: Body,
+    // Shared blocks, decoded on first use
+    blocks: OnceLock<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+enum Body {
+    Whole(String),
+    /// `-seekable`: stored blocks, inflated per file on demand
+    Blocks {
+        stored: String,
+        index: Vec<SeekEntry>,
+        zlib: bool,
+    },
+}
+
+impl Body {
+    fn tokenized_len(&self) -> usize {
+        match self {
+            Body::Whole(text) => text.len(),
+            Body::Blocks { index, .. } => index.last().map_or(0, |e| e.tokenized().end),
+        }
+    }
+
+    // Tokenized text in `range`, from the blocks that cover it
+    fn tokenized(&self, range: Range<usize>) -> Result<Cow<'_, str>> {
+        let (stored, index, zlib) = match self {
+            Body::Whole(text) => {
+                return text.get(range.clone()).map(Cow::Borrowed).ok_or_else(|| {
+                    anyhow!("manifest range {range:?} is outside the body or splits a character")
+                })
+            }
+            Body::Blocks {
+                stored,
+                index,
+                zlib,
+            } => (stored, index, *zlib),
+        };
+        let mut text = String::new();
+        let mut first = None;
+        for entry in seek::overlapping(index, &range, SeekEntry::tokenized) {
+            first.get_or_insert(entry.tokenized_start);
+            text.push_str(&seek::block_text(stored, entry, zlib)?);
+        }
+        let offset = first.unwrap_or(range.start);
+        range
+            .start
+            .checked_sub(offset)
+            .zip(range.end.checked_sub(offset))
+            .and_then(|(start, end)| text.get(start..end))
+            .map(|t| Cow::Owned(t.to_string()))
+            .ok_or_else(|| anyhow!("seek table does not cover bytes {range:?}"))
+    }
+}
+
+impl Aggregate {
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::parse_with_options(text, &DecodeOptions::default())
+    }
+
+    /// [`Aggregate::parse`], resolving a `-std:` dictionary as `options` say.
+    pub fn parse_with_options(text: &str, options: &DecodeOptions) -> Result<Self> {
+        let Manifest {
+            header: parts,
+            entries,
+            shared,
+            tombstones,
+            packing,
+            rest,
+        } = split_manifest(text)?;
+        let size = |i: usize, what: &str| {
+            parts[i]
+                .parse::<u64>()
+                .map_err(|_| anyhow!("invalid {what} size `{}` in header", parts[i]))
+        };
+        let flags: Vec<String> = parts[4..].iter().map(|f| f.to_string()).collect();
+
+        let joined = flags.join(" ");
+        let mut tokens = std_tokens(&joined, options)?;
+        let body = read_dictionary(rest, &mut tokens);
+        let zlib = flags.iter().any(|f| f == "-zlib");
+        let body = if seek::block_size(&joined).is_some() {
+            let (stored, index) = seek::read_table(body)?;
+            Body::Blocks {
+                stored: stored.to_string(),
+                index,
+                zlib,
+            }
+        } else if zlib {
+            Body::Whole(seek::inflate(body)?)
+        } else {
+            Body::Whole(body.to_string())
+        };
+        let outside = |start: usize, len: usize| {
+            start
+                .checked_add(len)
+                .is_none_or(|end| end > body.tokenized_len())
+        };
+        for e in entries.iter().chain(&tombstones) {
+            if outside(e.start, e.len) {
+                bail!("manifest entry `{}` lies outside the body", e.path);
+            }
+        }
+        if shared.as_ref().is_some_and(|s| outside(s.start, s.len)) {
+            bail!("shared segment lies outside the body");
+        }
+
+        Ok(Aggregate {
+            timestamp: parts[1].to_string(),
+            original_size: size(2, "original")?,
+            compressed_size: size(3, "compressed")?,
+            flags,
+            entries,
+            shared,
+            tombstones,
+            packing,
+            tokens,
+            body,
+            blocks: OnceLock::new(),
+        })
+    }
+
+    /// Document compression `flags` reused for a bundle: `-semantic`, which
+    /// aggregates lack, is dropped and the rest kept. `None` if none remain.
+    pub fn flags_for(flags: Option<&str>) -> Option<String> {
+        let kept: Vec<&str> = flags?
+            .split_whitespace()
+            .filter(|f| *f != "-semantic")
+            .collect();
+        (!kept.is_empty()).then(|| kept.join(" "))
+    }
+
+    /// The manifest alone, without inflating or decoding the body.
+    pub fn list(text: &str) -> Result<Vec<AggregateEntry>> {
+        Ok(split_manifest(text)?.entries)
+    }
+
+    /// The manifest's `::shared` line, if the bundle deduplicated anything.
+    pub fn list_shared(text: &str) -> Result<Option<SharedBlocks>> {
+        Ok(split_manifest(text)?.shared)
+    }
+
+    /// The manifest's `::removed:` tombstones: sections an update superseded.
+    pub fn list_tombstones(text: &str) -> Result<Vec<AggregateEntry>> {
+        Ok(split_manifest(text)?.tombstones)
+    }
+
+    /// The manifest's budget and dropped documents, for a bundle made by
+    /// [`Marqant::pack`].
+    pub fn list_packing(text: &str) -> Result<Option<Packing>> {
+        Ok(split_manifest(text)?.packing)
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&AggregateEntry> {
+        self.entries.iter().find(|e| e.path == path)
+    }
+
+    /// Number of dictionary entries shared by all documents.
+    pub fn token_count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Decode the document stored under `path`.
+    pub fn extract(&self, path: &str) -> Result<String> {
+        let entry = self
+            .entry(path)
+            .ok_or_else(|| anyhow!("`{path}` is not in the aggregate"))?;
+        self.decode(entry)
+    }
+
+    pub(crate) fn decode(&self, entry: &AggregateEntry) -> Result<String> {
+        let section = self.body.tokenized(entry.start..entry.start + entry.len)?;
+        let marker = format!("::file:{}::\n", entry.path);
+        let content = section
+            .strip_prefix(&marker)
+            .ok_or_else(|| anyhow!("`{}` does not start with its ::file: marker", entry.path))?;
+        let content = self.detokenize(content)?;
+        match &self.shared {
+            Some(shared) => dedup::resolve(&content, shared.ref_char, self.shared_blocks()?),
+            None => Ok(content),
+        }
+    }
+
+    fn detokenize(&self, text: &str) -> Result<String> {
+        let mut out = Vec::with_capacity(text.len() * 2);
+        TokenTable::new(&self.tokens).detokenize(text, &mut |chunk| {
+            out.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    fn shared_blocks(&self) -> Result<&[String]> {
+        if let Some(blocks) = self.blocks.get() {
+            return Ok(blocks);
+        }
+        let Some(shared) = &self.shared else {
+            return Ok(&[]);
+        };
+        let section = self
+            .body
+            .tokenized(shared.start..shared.start + shared.len)?;
+        let segment = section
+            .strip_prefix(SHARED_MARKER)
+            .ok_or_else(|| anyhow!("shared segment does not start with its marker"))?;
+        let blocks = dedup::read_shared(&self.detokenize(segment)?)?;
+        if blocks.len() != shared.count {
+            bail!(
+                "shared segment holds {} blocks, manifest says {}",
+                blocks.len(),
+                shared.count
+            );
+        }
+        Ok(self.blocks.get_or_init(|| blocks))
+    }
+
+    // The live documents whose paths pass `keep`, decoded
+    fn documents(&self, keep: impl Fn(&str) -> bool) -> Result<Vec<AggregateFile>> {
+        self.entries
+            .iter()
+            .filter(|e| keep(&e.path))
+            .map(|e| {
+                Ok(AggregateFile {
+                    path: e.path.clone(),
+                    content: self.decode(e)?,
+                    mtime: e.mtime,
+                    mode: e.mode,
+                })
+            })
+            .collect()
+    }
+
+    // The flags to rebuild the bundle with
+    pub(crate) fn bundle_flags(&self) -> String {
+        self.flags
+            .iter()
+            .map(String::as_str)
+            .filter(|f| *f != "-aggregate")
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Every document with its `::file:` marker line, in manifest order:
+    /// the whole bundle as one readable text.
+    pub fn render(&self) -> Result<String> {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&format!("::file:{}::\n", entry.path));
+            out.push_str(&self.decode(entry)?);
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Marqant {
+    /// Bundle the markdown files under `paths` (directories are walked for
+    /// `.md` files, skipping hidden entries and whatever `.gitignore` and
+    /// `.ignore` files rule out) into one aggregate `.mq`.
+    pub fn aggregate(paths: &[impl AsRef<Path>]) -> Result<String> {
+        Self::aggregate_with_flags(paths, None)
+    }
+
+    /// [`Marqant::aggregate`] with any of `-zlib`, `-seekable` and `-std:<id>`.
+    pub fn aggregate_with_flags(paths: &[impl AsRef<Path>], flags: Option<&str>) -> Result<String> {
+        Self::aggregate_selected(paths, &Selection::default(), flags)
+    }
+
+    /// [`Marqant::aggregate_with_flags`], walking directories with
+    /// `selection`; files named directly are always bundled.
+    pub fn aggregate_selected(
+        paths: &[impl AsRef<Path>],
+        selection: &Selection,
+        flags: Option<&str>,
+    ) -> Result<String> {
+        let mut files = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            if !path.is_dir() {
+                files.push(AggregateFile::read(path, slash_path(path))?);
+                continue;
+            }
+            for (src, name) in selection.walk(path)? {
+                files.push(AggregateFile::read(&src, name)?);
+            }
+        }
+        Self::aggregate_documents(&files, flags)
+    }
+
+    /// Bundle in-memory `(path, content)` documents, in the given order.
+    pub fn aggregate_files(files: &[(String, String)], flags: Option<&str>) -> Result<String> {
+        let files: Vec<AggregateFile> = files
+            .iter()
+            .map(|(path, content)| AggregateFile {
+                path: path.clone(),
+                content: content.clone(),
+                mtime: None,
+                mode: None,
+            })
+            .collect();
+        Self::aggregate_documents(&files, flags)
+    }
+
+    /// Bundle documents, in the given order, recording their modification times.
+    pub fn aggregate_documents(files: &[AggregateFile], flags: Option<&str>) -> Result<String> {
+        Self::build_aggregate(files, flags, None)
+    }
+
+    pub(crate) fn build_aggregate(
+        files: &[AggregateFile],
+        flags: Option<&str>,
+        packing: Option<&Packing>,
+    ) -> Result<String> {
+        let flags: Vec<&str> = flags.unwrap_or("").split_whitespace().collect();
+        if let Some(bad) = flags
+            .iter()
+            .find(|f| **f != "-zlib" && !f.starts_with("-std:") && seek::block_size(f).is_none())
+        {
+            bail!("flag `{bad}` is not supported for aggregates");
+        }
+        check_paths(files)?;
+
+        // Blocks repeated across files are stored once, then one dictionary
+        // covers every document (and the shared blocks), applied to each
+        // separately so that no token straddles two files
+        let originals: Vec<&str> = files.iter().map(|f| f.content.as_str()).collect();
+        let deduped = dedup::dedup(&originals);
+        let segment = deduped.as_ref().map(|d| d.shared_segment());
+        let contents: Vec<&str> = match &deduped {
+            Some(d) => d.files.iter().map(String::as_str).collect(),
+            None => originals,
+        };
+        let joined = contents
+            .iter()
+            .copied()
+            .chain(segment.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let patterns = filetype::patterns_for(files.iter().map(|f| f.path.as_str()));
+        let (order, _) = Self::extend_tokens(&joined, Vec::new(), &HashSet::new(), &patterns);
+        let mut body = String::new();
+        let mut starts = Vec::new();
+        let mut shared = None;
+        if let (Some(d), Some(segment)) = (&deduped, &segment) {
+            starts.push(0);
+            body.push_str(SHARED_MARKER);
+            body.push_str(&Self::apply_tokens(segment, &order));
+            shared = Some(SharedBlocks {
+                start: 0,
+                len: body.len(),
+                count: d.blocks.len(),
+                savings: d.net_savings(),
+                ref_char: d.ref_char,
+            });
+        }
+        let mut entries = Vec::new();
+        for (i, (file, content)) in files.iter().zip(&contents).enumerate() {
+            let start = body.len();
+            starts.push(start);
+            body.push_str(&format!("::file:{}::\n", file.path));
+            body.push_str(&Self::apply_tokens(content, &order));
+            entries.push(AggregateEntry {
+                path: file.path.clone(),
+                start,
+                len: body.len() - start,
+                size: Some(file.content.len() as u64),
+                mtime: file.mtime,
+                mode: file.mode,
+                dedup: deduped.as_ref().map(|d| d.savings[i]).filter(|&d| d > 0),
+            });
+        }
+        let tokens: HashMap<String, String> = order.into_iter().collect();
+        let zlib = flags.contains(&"-zlib");
+        let flags = flags.join(" ");
+        let body = if let Some(block_size) = seek::block_size(&flags) {
+            let blocks = seek::split_blocks(&body, &starts, block_size);
+            let table = TokenTable::new(&tokens);
+            seek::write_blocks(&body, &blocks, zlib, |text| {
+                decoded_len(&table, false, text)
+            })?
+        } else if zlib {
+            seek::deflate(&body)?
+        } else {
+            body
+        };
+
+        let original: u64 = files.iter().map(|f| f.content.len() as u64).sum();
+        let manifest = write_manifest(shared.as_ref(), &entries, &[], packing);
+        Ok(write_bundle(&manifest, &tokens, &flags, &body, original))
+    }
+
+    /// Replace or add `files` in the aggregate `bundle` and drop the
+    /// documents named in `remove`, without re-tokenizing what is already
+    /// there: new sections are appended under the existing dictionary
+    /// (extended only with phrases that pay for themselves in the new
+    /// documents), and the sections they supersede stay behind `::removed:`
+    /// tombstones until [`Marqant::compact_aggregate`]. Documents identical
+    /// to the stored ones are left alone. A new document that uses one of
+    /// the bundle's token chars forces a full rebuild instead.
+    pub fn update_aggregate(
+        bundle: &str,
+        files: &[AggregateFile],
+        remove: &[String],
+    ) -> Result<String> {
+        Self::update_aggregate_with_options(bundle, files, remove, &DecodeOptions::default())
+    }
+
+    /// [`Marqant::update_aggregate`], reading `bundle` with `options`.
+    pub fn update_aggregate_with_options(
+        bundle: &str,
+        files: &[AggregateFile],
+        remove: &[String],
+        options: &DecodeOptions,
+    ) -> Result<String> {
+        let old = Aggregate::parse_with_options(bundle, options)?;
+        check_paths(files)?;
+        let mut changed = Vec::new();
+        for file in files {
+            let same = match old.entry(&file.path) {
+                Some(e) if e.size.is_none_or(|s| s == file.content.len() as u64) => {
+                    old.decode(e)? == file.content
+                }
+                _ => false,
+            };
+            if !same {
+                changed.push(file.clone());
+            }
+        }
+        let files = &changed[..];
+        if files.is_empty() && remove.is_empty() {
+            return Ok(bundle.to_string());
+        }
+        for path in remove {
+            if old.entry(path).is_none() {
+                bail!("`{path}` is not in the aggregate");
+            }
+            if files.iter().any(|f| &f.path == path) {
+                bail!("`{path}` is both updated and removed");
+            }
+        }
+        let superseded =
+            |path: &str| remove.iter().any(|p| p == path) || files.iter().any(|f| f.path == path);
+        let flags = old.bundle_flags();
+
+        let mut reserved: HashSet<char> = old
+            .tokens
+            .keys()
+            .flat_map(|t| t.chars())
+            .chain(old.shared.as_ref().map(|s| s.ref_char))
+            .collect();
+        if files
+            .iter()
+            .any(|f| f.content.chars().any(|c| reserved.contains(&c)))
+        {
+            let mut all = old.documents(|path| !superseded(path))?;
+            all.extend(files.iter().cloned());
+            return Self::build_aggregate(&all, Some(&flags), old.packing.as_ref());
+        }
+        // New token chars must not occur in the documents already stored
+        let stored = old.body.tokenized(0..old.body.tokenized_len())?;
+        reserved.extend(stored.chars());
+        reserved.extend(old.tokens.values().flat_map(|p| p.chars()));
+        let mut existing: Vec<(String, String)> = old.tokens.clone().into_iter().collect();
+        existing.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+        let joined = files
+            .iter()
+            .map(|f| f.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let patterns = filetype::patterns_for(files.iter().map(|f| f.path.as_str()));
+        let (order, _) = Self::extend_tokens(&joined, existing, &reserved, &patterns);
+
+        let base = old.body.tokenized_len();
+        let mut appended = String::new();
+        let mut starts = Vec::new();
+        let mut added = Vec::new();
+        for file in files {
+            starts.push(appended.len());
+            let start = base + appended.len();
+            appended.push_str(&format!("::file:{}::\n", file.path));
+            appended.push_str(&Self::apply_tokens(&file.content, &order));
+            added.push(AggregateEntry {
+                path: file.path.clone(),
+                start,
+                len: base + appended.len() - start,
+                size: Some(file.content.len() as u64),
+                mtime: file.mtime,
+                mode: file.mode,
+                dedup: None,
+            });
+        }
+
+        // Superseded sections become tombstones; replacements keep their
+        // place in the manifest, new documents go last
+        let mut tombstones = old.tombstones.clone();
+        let mut entries = Vec::new();
+        let mut original = 0;
+        for e in &old.entries {
+            if !superseded(&e.path) {
+                original += match e.size {
+                    Some(size) => size,
+                    None => old.decode(e)?.len() as u64,
+                };
+                entries.push(e.clone());
+                continue;
+            }
+            tombstones.push(AggregateEntry {
+                path: e.path.clone(),
+                start: e.start,
+                len: e.len,
+                size: None,
+                mtime: None,
+                mode: None,
+                dedup: None,
+            });
+            if let Some(at) = added.iter().position(|a| a.path == e.path) {
+                entries.push(added.remove(at));
+            }
+        }
+        entries.append(&mut added);
+        original += files.iter().map(|f| f.content.len() as u64).sum::<u64>();
+
+        let tokens: HashMap<String, String> = order.into_iter().collect();
+        let body = match &old.body {
+            Body::Blocks {
+                stored,
+                index,
+                zlib,
+            } => {
+                let block_size = seek::block_size(&flags).unwrap_or(seek::SEEK_BLOCK_SIZE);
+                let blocks = seek::split_blocks(&appended, &starts, block_size);
+                let table = TokenTable::new(&tokens);
+                seek::extend_blocks(stored, index, &appended, &blocks, *zlib, |text| {
+                    decoded_len(&table, false, text)
+                })?
+            }
+            Body::Whole(text) if flags.split_whitespace().any(|f| f == "-zlib") => {
+                seek::deflate(&format!("{text}{appended}"))?
+            }
+            Body::Whole(text) => format!("{text}{appended}"),
+        };
+        let manifest = write_manifest(
+            old.shared.as_ref(),
+            &entries,
+            &tombstones,
+            old.packing.as_ref(),
+        );
+        Ok(write_bundle(&manifest, &tokens, &flags, &body, original))
+    }
+
+    /// Rewrite an aggregate from its live documents, as
+    /// [`Marqant::aggregate_documents`] would: tombstoned sections are
+    /// dropped and the dictionary and shared blocks rebuilt over everything.
+    pub fn compact_aggregate(bundle: &str) -> Result<String> {
+        Self::compact_aggregate_with_options(bundle, &DecodeOptions::default())
+    }
+
+    /// [`Marqant::compact_aggregate`], reading `bundle` with `options`.
+    pub fn compact_aggregate_with_options(bundle: &str, options: &DecodeOptions) -> Result<String> {
+        let old = Aggregate::parse_with_options(bundle, options)?;
+        let files = old.documents(|_| true)?;
+        Self::build_aggregate(&files, Some(&old.bundle_flags()), old.packing.as_ref())
+    }
+}
+
+impl AggregateFile {
+    /// Read `src` from disk, to be stored under `path`.
+    pub fn read(src: &Path, path: String) -> Result<Self> {
+        let content =
+            fs::read_to_string(src).with_context(|| format!("failed reading {}", src.display()))?;
+        let mtime = fs::metadata(src)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        Ok(Self {
+            path,
+            content,
+            mtime,
+            mode: None,
+        })
+    }
+}
+
+// Header fields, manifest entries and what follows the manifest
+fn check_paths(files: &[AggregateFile]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for AggregateFile { path, .. } in files {
+        if path.is_empty() || path.contains('\n') || path.contains("::") {
+            bail!("invalid path {path:?} for an aggregate");
+        }
+        if !seen.insert(path) {
+            bail!("`{path}` appears twice");
+        }
+    }
+    Ok(())
+}
+
+fn write_manifest(
+    shared: Option<&SharedBlocks>,
+    entries: &[AggregateEntry],
+    tombstones: &[AggregateEntry],
+    packing: Option<&Packing>,
+) -> String {
+    let mut manifest = String::new();
+    if let Some(s) = shared {
+        manifest.push_str(&format!(
+            "{SHARED_LINE}{}:{}:b{}:d{}:r{}\n",
+            s.start, s.len, s.count, s.savings, s.ref_char as u32
+        ));
+    }
+    for e in entries {
+        manifest.push_str(&format!("{}:{}:{}", e.path, e.start, e.len));
+        for (tag, value) in [('s', e.size), ('m', e.mtime)] {
+            if let Some(v) = value {
+                manifest.push_str(&format!(":{tag}{v}"));
+            }
+        }
+        if let Some(mode) = e.mode {
+            manifest.push_str(&format!(":o{mode:o}"));
+        }
+        if let Some(d) = e.dedup {
+            manifest.push_str(&format!(":d{d}"));
+        }
+        manifest.push('\n');
+    }
+    for t in tombstones {
+        manifest.push_str(&format!(
+            "{TOMBSTONE_LINE}{}:{}:{}\n",
+            t.path, t.start, t.len
+        ));
+    }
+    if let Some(p) = packing {
+        manifest.push_str(&format!("{BUDGET_LINE}{}\n", p.budget));
+        for (path, tokens) in &p.dropped {
+            manifest.push_str(&format!("{DROPPED_LINE}{path}:{tokens}\n"));
+        }
+    }
+    manifest
+}
+
+// Header, manifest and dictionary in front of the stored `body`
+fn write_bundle(
+    manifest: &str,
+    tokens: &HashMap<String, String>,
+    flags: &str,
+    body: &str,
+    original: u64,
+) -> String {
+    let mut rest = format!("{MANIFEST_START}\n{manifest}{MANIFEST_END}\n");
+    write_dictionary(&mut rest, tokens, parse_std_flag(Some(flags)).as_deref());
+    rest.push_str(body);
+    let mut header = format!(
+        "{AGGREGATE_MAGIC} {} {original} {} -aggregate",
+        now_timestamp(),
+        rest.len()
+    );
+    if !flags.is_empty() {
+        header.push(' ');
+        header.push_str(flags);
+    }
+    format!("{header}\n{rest}")
+}
+
+// A bundle split at its manifest
+struct Manifest<'a> {
+    header: Vec<&'a str>,
+    entries: Vec<AggregateEntry>,
+    shared: Option<SharedBlocks>,
+    tombstones: Vec<AggregateEntry>,
+    packing: Option<Packing>,
+    // Dictionary and body
+    rest: &'a str,
+}
+
+fn split_manifest(text: &str) -> Result<Manifest<'_>> {
+    let (header, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let parts: Vec<&str> = header.split_whitespace().collect();
+    if parts.first() != Some(&AGGREGATE_MAGIC) || parts.len() < 4 {
+        bail!("not an aggregate bundle (expected `{AGGREGATE_MAGIC}` header)");
+    }
+    if !parts[4..].contains(&"-aggregate") {
+        bail!("aggregate header lacks the -aggregate flag");
+    }
+    let rest = rest
+        .strip_prefix(MANIFEST_START)
+        .and_then(|r| r.strip_prefix('\n'))
+        .ok_or_else(|| anyhow!("missing {MANIFEST_START}"))?;
+    let (manifest, rest) = rest
+        .split_once(&format!("{MANIFEST_END}\n"))
+        .ok_or_else(|| anyhow!("missing {MANIFEST_END}"))?;
+    let mut entries = Vec::new();
+    let mut shared = None;
+    let mut tombstones = Vec::new();
+    let mut packing: Option<Packing> = None;
+    for line in manifest.lines() {
+        // Paths never contain `::`, so none of these lines can name a file
+        if line.starts_with(SHARED_LINE) {
+            let (_, start, len, tags) = parse_fields(line)?;
+            let tag = |t: char| {
+                tags.iter()
+                    .find(|(c, _)| *c == t)
+                    .map(|&(_, v)| v)
+                    .ok_or_else(|| anyhow!("manifest line `{line}` lacks its `{t}` field"))
+            };
+            let ref_char = char::from_u32(tag('r')? as u32)
+                .filter(|c| !c.is_ascii())
+                .ok_or_else(|| anyhow!("invalid reference char in `{line}`"))?;
+            shared = Some(SharedBlocks {
+                start,
+                len,
+                count: tag('b')? as usize,
+                savings: tag('d')?,
+                ref_char,
+            });
+            continue;
+        }
+        if let Some(budget) = line.strip_prefix(BUDGET_LINE) {
+            let budget = budget
+                .parse()
+                .map_err(|_| anyhow!("invalid manifest line `{line}`"))?;
+            packing.get_or_insert_with(Packing::default).budget = budget;
+            continue;
+        }
+        if let Some(dropped) = line.strip_prefix(DROPPED_LINE) {
+            let (path, tokens) = dropped
+                .rsplit_once(':')
+                .and_then(|(path, tokens)| Some((path.to_string(), tokens.parse().ok()?)))
+                .ok_or_else(|| anyhow!("invalid manifest line `{line}`"))?;
+            packing
+                .get_or_insert_with(Packing::default)
+                .dropped
+                .push((path, tokens));
+            continue;
+        }
+        if let Some(tombstone) = line.strip_prefix(TOMBSTONE_LINE) {
+            let (path, start, len, _) = parse_fields(tombstone)?;
+            tombstones.push(AggregateEntry {
+                path: path.to_string(),
+                start,
+                len,
+                size: None,
+                mtime: None,
+                mode: None,
+                dedup: None,
+            });
+            continue;
+        }
+        let (path, start, len, tags) = parse_fields(line)?;
+        let tag = |t: char| tags.iter().find(|(c, _)| *c == t).map(|&(_, v)| v);
+        entries.push(AggregateEntry {
+            path: path.to_string(),
+            start,
+            len,
+            size: tag('s'),
+            mtime: tag('m'),
+            mode: tag('o').map(|m| m as u32),
+            dedup: tag('d'),
+        });
+    }
+    Ok(Manifest {
+        header: parts,
+        entries,
+        shared,
+        tombstones,
+        packing,
+        rest,
+    })
+}
+
+// Name, start, length and tagged fields of one manifest line
+type Fields<'a> = (&'a str, usize, usize, Vec<(char, u64)>);
+
+// `name:start:len[:<tag><number>]...`, read from the right: paths may
+// contain `:`, the numeric fields never do. `o` (mode) is octal
+fn parse_fields(line: &str) -> Result<Fields<'_>> {
+    let invalid = || anyhow!("invalid manifest line `{line}`");
+    let mut tags = Vec::new();
+    let mut rest = line;
+    loop {
+        let (head, field) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let radix = if field.starts_with('o') { 8 } else { 10 };
+        let tagged = field
+            .strip_prefix(['s', 'm', 'o', 'd', 'b', 'r'])
+            .filter(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|v| u64::from_str_radix(v, radix).ok());
+        let Some(v) = tagged else {
+            break;
+        };
+        tags.push((field.chars().next().unwrap_or_default(), v));
+        rest = head;
+    }
+    let (rest, len) = rest.rsplit_once(':').ok_or_else(invalid)?;
+    let (name, start) = rest.rsplit_once(':').ok_or_else(invalid)?;
+    Ok((
+        name,
+        start.parse().map_err(|_| invalid())?,
+        len.parse().map_err(|_| invalid())?,
+        tags,
+    ))
+}
+
+// Path with `/` separators and no leading `./`
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,230 @@
+// Compression report for a markdown document: what each storage mode
+// really costs once the dictionary is paid for, where the savings come
+// from, and which parts of the document compress well.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{is_dynamic_token_char, Marqant, MAX_DYNAMIC_TOKENS, STATIC_TOKENS};
+
+/// Full report produced by [`analyze_markdown`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Analysis {
+    pub original_size: usize,
+    /// Dictionary entries chosen by the tokenizer.
+    pub tokens: usize,
+    /// Body size after token substitution, before dictionary overhead.
+    pub tokenized_size: usize,
+    /// `original_size - tokenized_size`, ignoring the dictionary.
+    pub savings: isize,
+    pub modes: Vec<ModeSize>,
+    /// Name of the smallest entry in `modes`.
+    pub best_mode: String,
+    pub static_tokens: Contribution,
+    pub dynamic_tokens: Contribution,
+    /// Every dictionary entry, largest net saving first.
+    pub phrases: Vec<PhraseSavings>,
+    pub warnings: Vec<String>,
+    pub sections: Vec<SectionSize>,
+}
+
+/// Size of the `.mq` file written with one set of flags.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModeSize {
+    pub mode: String,
+    pub flags: Option<String>,
+    /// Bytes on disk, header and dictionary included.
+    pub size: usize,
+    pub savings: isize,
+}
+
+/// Net effect of one class of tokens (static markdown syntax or dynamic phrases).
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Contribution {
+    pub count: usize,
+    pub occurrences: usize,
+    pub savings: isize,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhraseSavings {
+    pub pattern: String,
+    pub dynamic: bool,
+    pub occurrences: usize,
+    /// Bytes saved in the body minus the cost of the dictionary line.
+    pub savings: isize,
+}
+
+/// One `#`/`##` section (the same split `-semantic` uses); text before the
+/// first heading has an empty title.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionSize {
+    pub title: String,
+    pub original_size: usize,
+    pub tokenized_size: usize,
+}
+
+const MODES: [(&str, Option<&str>); 4] = [
+    ("plain", None),
+    ("zlib", Some("-zlib")),
+    ("std", Some("-std:std-static-v1")),
+    ("semantic", Some("-semantic")),
+];
+
+/// Compress `content` every way `mq compress` can and report on the result.
+pub fn analyze_markdown(content: &str) -> Result<Analysis> {
+    let original_size = content.len();
+    let (tokens, tokenized) = Marqant::tokenize_content(content);
+
+    let mut modes = Vec::new();
+    for (mode, flags) in MODES {
+        let size = Marqant::compress_markdown_with_flags(content, flags)?.len();
+        modes.push(ModeSize {
+            mode: mode.to_string(),
+            flags: flags.map(str::to_string),
+            size,
+            savings: original_size as isize - size as isize,
+        });
+    }
+    let best_mode = modes
+        .iter()
+        .min_by_key(|m| m.size)
+        .map(|m| m.mode.clone())
+        .unwrap_or_default();
+
+    // Walk the token stream once: tokens expand exactly once, so this maps
+    // every tokenized char back to its offset in the original
+    let boundaries = section_starts(content);
+    let mut sections: Vec<SectionSize> = boundaries
+        .iter()
+        .enumerate()
+        .map(|(i, &(start, ref title))| SectionSize {
+            title: title.clone(),
+            original_size: boundaries.get(i + 1).map_or(original_size, |b| b.0) - start,
+            tokenized_size: 0,
+        })
+        .collect();
+    let mut occurrences: HashMap<char, usize> = HashMap::new();
+    let (mut offset, mut section) = (0, 0);
+    for c in tokenized.chars() {
+        while boundaries.get(section + 1).is_some_and(|b| b.0 <= offset) {
+            section += 1;
+        }
+        sections[section].tokenized_size += c.len_utf8();
+        offset += match tokens.get(c.encode_utf8(&mut [0; 4]) as &str) {
+            Some(pattern) => {
+                *occurrences.entry(c).or_default() += 1;
+                pattern.len()
+            }
+            None => c.len_utf8(),
+        };
+    }
+
+    let mut phrases: Vec<PhraseSavings> = tokens
+        .iter()
+        .map(|(token, pattern)| {
+            let count = token
+                .chars()
+                .next()
+                .and_then(|c| occurrences.get(&c))
+                .copied()
+                .unwrap_or(0);
+            let body = count as isize * (pattern.len() as isize - token.len() as isize);
+            PhraseSavings {
+                pattern: pattern.clone(),
+                dynamic: !STATIC_TOKENS.iter().any(|(t, _)| t == token),
+                occurrences: count,
+                savings: body - (token.len() + pattern.len() + 3) as isize,
+            }
+        })
+        .collect();
+    phrases.sort_by(|a, b| b.savings.cmp(&a.savings).then(a.pattern.cmp(&b.pattern)));
+
+    let contribution = |dynamic: bool| {
+        phrases
+            .iter()
+            .filter(|p| p.dynamic == dynamic)
+            .fold(Contribution::default(), |acc, p| Contribution {
+                count: acc.count + 1,
+                occurrences: acc.occurrences + p.occurrences,
+                savings: acc.savings + p.savings,
+            })
+    };
+    let static_tokens = contribution(false);
+    let dynamic_tokens = contribution(true);
+    let warnings = collisions(content, dynamic_tokens.count);
+
+    Ok(Analysis {
+        original_size,
+        tokens: tokens.len(),
+        tokenized_size: tokenized.len(),
+        savings: original_size as isize - tokenized.len() as isize,
+        modes,
+        best_mode,
+        static_tokens,
+        dynamic_tokens,
+        phrases,
+        warnings,
+        sections,
+    })
+}
+
+// Characters already in the text can't serve as tokens: a static token that
+// collides leaves its pattern untokenized, and dynamic ones shrink the pool
+fn collisions(content: &str, dynamic: usize) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (token, pattern) in STATIC_TOKENS {
+        if content.contains(token) {
+            let c = token.chars().next().unwrap_or_default();
+            warnings.push(format!(
+                "U+{:04X} occurs in the text; {pattern:?} is not tokenized",
+                c as u32
+            ));
+        }
+    }
+    let taken: HashSet<char> = content
+        .chars()
+        .filter(|&c| is_dynamic_token_char(c))
+        .collect();
+    if !taken.is_empty() {
+        warnings.push(format!(
+            "{} dynamic token char(s) occur in the text and are unavailable",
+            taken.len()
+        ));
+    }
+    if dynamic >= MAX_DYNAMIC_TOKENS {
+        warnings.push(format!(
+            "dynamic token limit ({MAX_DYNAMIC_TOKENS}) reached; more phrases could be tokenized"
+        ));
+    }
+    warnings
+}
+
+// Byte offset and title of each section, mirroring `add_section_tags`
+fn section_starts(content: &str) -> Vec<(usize, String)> {
+    let mut starts = vec![(0, String::new())];
+    let mut in_code_block = false;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        if !in_code_block {
+            let title = line.strip_prefix("# ").or_else(|| line.strip_prefix("## "));
+            if let Some(title) = title {
+                if offset == 0 {
+                    starts.clear();
+                }
+                starts.push((offset, title.trim().to_string()));
+            }
+        }
+        offset += line.len();
+    }
+    starts
+}
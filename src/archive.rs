@@ -0,0 +1,248 @@
+// Standard archives in and out of aggregates, in memory: tar (plain or
+// gzipped) and zip. Regular members become documents under their archive
+// path, with their modification time and permission bits, which the
+// manifest keeps and `Aggregate::to_archive` writes back. Directories,
+// links and other special members are skipped. Zip times are DOS local
+// times without a zone; they are read and written as UTC, to the even
+// second, between 1980 and 2107. Only the permission bits 0777 are kept:
+// setuid, setgid and sticky bits from an archive are dropped.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Datelike, Timelike};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::{Aggregate, AggregateEntry, AggregateFile, Selection};
+
+/// Largest archive member read into memory when the selection sets no
+/// smaller `max_size`. Larger members are an error, not skipped.
+const MAX_MEMBER_SIZE: u64 = 64 << 20;
+
+/// Archive formats aggregates convert from and to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format a file name implies: `.tar`, `.tar.gz` / `.tgz` or `.zip`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// The format of an archive, from its leading bytes.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if bytes.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+impl AggregateFile {
+    /// The members of a tar, gzipped tar or zip archive (sniffed from its
+    /// bytes) that `selection` takes, in archive order. A path stored twice
+    /// keeps its last copy, as unpacking would. Members that are not UTF-8
+    /// are skipped when include globs are set, and an error otherwise.
+    /// Members are filtered on their declared size before they are read,
+    /// and reading stops at `max_size` or `MAX_MEMBER_SIZE`.
+    pub fn from_archive(bytes: &[u8], selection: &Selection) -> Result<Vec<AggregateFile>> {
+        let format = ArchiveFormat::detect(bytes)
+            .ok_or_else(|| anyhow!("not a tar, tar.gz or zip archive"))?;
+        let mut files: Vec<AggregateFile> = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let limit = selection
+            .max_size
+            .map_or(MAX_MEMBER_SIZE, |max| max.min(MAX_MEMBER_SIZE));
+        let mut add = |name: &str, size: u64, reader: &mut dyn Read, mtime, mode| -> Result<()> {
+            let path = member_path(name)?;
+            if !selection.takes_member(&path, size) {
+                return Ok(());
+            }
+            if size > limit {
+                bail!("archive member {path} is over {limit} bytes");
+            }
+            // The declared size may lie; never hold more than the limit
+            let mut data = Vec::new();
+            reader.take(limit + 1).read_to_end(&mut data)?;
+            if data.len() as u64 > limit {
+                bail!("archive member {path} is over {limit} bytes");
+            }
+            let content = match String::from_utf8(data) {
+                Ok(content) => content,
+                Err(_) if !selection.include.is_empty() => return Ok(()),
+                Err(_) => bail!("archive member {path} is not UTF-8"),
+            };
+            let file = AggregateFile {
+                path: path.clone(),
+                content,
+                mtime,
+                mode,
+            };
+            match seen.get(&path) {
+                Some(&i) => files[i] = file,
+                None => {
+                    seen.insert(path, files.len());
+                    files.push(file);
+                }
+            }
+            Ok(())
+        };
+        match format {
+            ArchiveFormat::Tar => read_tar(bytes, &mut add)?,
+            ArchiveFormat::TarGz => read_tar(GzDecoder::new(bytes), &mut add)?,
+            ArchiveFormat::Zip => {
+                let mut zip = zip::ZipArchive::new(Cursor::new(bytes))
+                    .context("failed reading zip archive")?;
+                for i in 0..zip.len() {
+                    let mut member = zip.by_index(i).context("failed reading zip archive")?;
+                    if !member.is_file() || member.unix_mode().is_some_and(is_link) {
+                        continue;
+                    }
+                    let mtime = member.last_modified().and_then(|t| {
+                        let date = chrono::NaiveDate::from_ymd_opt(
+                            t.year().into(),
+                            t.month().into(),
+                            t.day().into(),
+                        )?;
+                        let time = date.and_hms_opt(
+                            t.hour().into(),
+                            t.minute().into(),
+                            t.second().into(),
+                        )?;
+                        u64::try_from(time.and_utc().timestamp()).ok()
+                    });
+                    let mode = member.unix_mode().map(|m| m & 0o777);
+                    let name = member.name().to_string();
+                    let size = member.size();
+                    add(&name, size, &mut member, mtime, mode)?;
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl Aggregate {
+    /// Write `entries` of the bundle as an archive, with their recorded
+    /// modification times and permission bits (0644 and the bundle's
+    /// conversion time where none were recorded).
+    pub fn to_archive(
+        &self,
+        entries: &[&AggregateEntry],
+        format: ArchiveFormat,
+    ) -> Result<Vec<u8>> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        match format {
+            ArchiveFormat::Tar => self.write_tar(entries, Vec::new(), now),
+            ArchiveFormat::TarGz => {
+                let gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                Ok(self.write_tar(entries, gz, now)?.finish()?)
+            }
+            ArchiveFormat::Zip => {
+                let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+                for e in entries {
+                    let content = self.extract(&e.path)?;
+                    let time = chrono::DateTime::from_timestamp(e.mtime.unwrap_or(now) as i64, 0)
+                        .and_then(|t| {
+                            zip::DateTime::from_date_and_time(
+                                u16::try_from(t.year()).ok()?,
+                                t.month() as u8,
+                                t.day() as u8,
+                                t.hour() as u8,
+                                t.minute() as u8,
+                                t.second() as u8,
+                            )
+                            .ok()
+                        })
+                        .unwrap_or_default();
+                    let options = zip::write::SimpleFileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated)
+                        .last_modified_time(time)
+                        .unix_permissions(e.mode.unwrap_or(0o644));
+                    zip.start_file(e.path.as_str(), options)?;
+                    zip.write_all(content.as_bytes())?;
+                }
+                Ok(zip.finish()?.into_inner())
+            }
+        }
+    }
+
+    fn write_tar<W: Write>(&self, entries: &[&AggregateEntry], out: W, now: u64) -> Result<W> {
+        let mut tar = tar::Builder::new(out);
+        for e in entries {
+            let content = self.extract(&e.path)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(content.len() as u64);
+            header.set_mode(e.mode.unwrap_or(0o644));
+            header.set_mtime(e.mtime.unwrap_or(now));
+            tar.append_data(&mut header, &e.path, content.as_bytes())
+                .with_context(|| format!("failed archiving {}", e.path))?;
+        }
+        Ok(tar.into_inner()?)
+    }
+}
+
+type AddMember<'a> =
+    dyn FnMut(&str, u64, &mut dyn Read, Option<u64>, Option<u32>) -> Result<()> + 'a;
+
+fn read_tar(reader: impl Read, add: &mut AddMember) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().context("failed reading tar archive")? {
+        let mut entry = entry.context("failed reading tar archive")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mtime = entry.header().mtime().ok();
+        let mode = entry.header().mode().ok().map(|m| m & 0o777);
+        let size = entry.size();
+        add(&name, size, &mut entry, mtime, mode)?;
+    }
+    Ok(())
+}
+
+// Zip stores symlinks as files with the link target as content
+fn is_link(mode: u32) -> bool {
+    mode & 0o170000 == 0o120000
+}
+
+// A member name as a bundle path: `/`-separated, no leading `./`; names
+// that would land outside the extraction directory are refused
+fn member_path(name: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for c in Path::new(name).components() {
+        match c {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir => {}
+            _ => bail!("unsafe path {name:?} in archive"),
+        }
+    }
+    if parts.is_empty() {
+        bail!("empty path in archive");
+    }
+    Ok(parts.join("/"))
+}
@@ -0,0 +1,268 @@
+// Batch mode for `compress` / `decompress`: expand files and directories
+// into jobs, mirror the tree under `--output-dir`, run the jobs on a small
+// thread pool and print a summary table.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use marqant::config::Config;
+
+pub struct Job {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+}
+
+pub enum Outcome {
+    Written { input: usize, output: usize },
+    Skipped,
+    Failed(String),
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub output_dir: Option<PathBuf>,
+    pub force: bool,
+    pub jobs: usize,
+    /// Project config; its ignore globs prune directory walks
+    pub config: Option<Arc<Config>>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            output_dir: None,
+            force: false,
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            config: None,
+        }
+    }
+}
+
+/// Expand `inputs` into jobs. Directories are walked recursively for files
+/// ending in `in_ext`, skipping hidden entries and config ignore globs;
+/// files named directly are taken as-is. Outputs get `out_ext` and keep
+/// their path relative to the directory they were found in, under
+/// `output_dir` if given. A source reached twice is planned once; two
+/// sources that would write the same output (`a/x.md` and `b/x.md` under
+/// one `output_dir`) are an error.
+pub fn plan(inputs: &[PathBuf], in_ext: &str, out_ext: &str, opts: &Options) -> Result<Vec<Job>> {
+    // Never walk into our own output
+    let skip = opts
+        .output_dir
+        .as_deref()
+        .and_then(|d| fs::canonicalize(d).ok());
+    let mut jobs: Vec<Job> = Vec::new();
+    for (src, rel) in sources(inputs, in_ext, skip.as_deref(), opts.config.as_deref())? {
+        let job = job(src, &rel, out_ext, opts);
+        if let Some(other) = jobs.iter().find(|j| j.dst == job.dst) {
+            if same_file(&other.src, &job.src) {
+                continue;
+            }
+            return Err(anyhow!(
+                "{} and {} would both be written to {}",
+                other.src.display(),
+                job.src.display(),
+                job.dst.display()
+            ));
+        }
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || fs::canonicalize(a).is_ok_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b))
+}
+
+/// Files behind `inputs` with their paths relative to the input they came
+/// from: directories are walked for `ext` files (sorted, hidden entries, `skip`
+/// and config ignores left out), files given directly keep just their name.
+pub fn sources(
+    inputs: &[PathBuf],
+    ext: &str,
+    skip: Option<&Path>,
+    config: Option<&Config>,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        let meta =
+            fs::metadata(input).with_context(|| format!("failed reading {}", input.display()))?;
+        if meta.is_dir() {
+            let mut found = Vec::new();
+            walk(input, ext, skip, &mut found)?;
+            if let Some(config) = config {
+                found.retain(|src| !config.is_ignored(src));
+            }
+            found.sort();
+            for src in found {
+                let rel = src.strip_prefix(input).unwrap_or(&src).to_path_buf();
+                sources.push((src, rel));
+            }
+        } else {
+            let name = input
+                .file_name()
+                .ok_or_else(|| anyhow!("not a file: {}", input.display()))?;
+            sources.push((input.clone(), PathBuf::from(name)));
+        }
+    }
+    Ok(sources)
+}
+
+/// Files named in `inputs`, plus every `ext` file found under the
+/// directories among them, in the order `plan` would visit them.
+pub fn files(inputs: &[PathBuf], ext: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut found = Vec::new();
+            walk(input, ext, None, &mut found)?;
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn job(src: PathBuf, rel: &Path, out_ext: &str, opts: &Options) -> Job {
+    let dst = match &opts.output_dir {
+        Some(dir) => dir.join(rel).with_extension(out_ext),
+        None => src.with_extension(out_ext),
+    };
+    Job { src, dst }
+}
+
+fn walk(dir: &Path, ext: &str, skip: Option<&Path>, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("failed reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            if skip.is_some_and(|s| fs::canonicalize(&path).is_ok_and(|p| p == s)) {
+                continue;
+            }
+            walk(&path, ext, skip, found)?;
+        } else if path.extension().is_some_and(|e| e == ext) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Output exists and is at least as new as its input. Only mtimes are
+// compared: an output made with other flags or config still counts as up
+// to date, so changing those needs `--force`.
+fn up_to_date(job: &Job) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    matches!((modified(&job.src), modified(&job.dst)), (Some(src), Some(dst)) if dst >= src)
+}
+
+/// Run `convert(src, bytes)` over every job on `opts.jobs` worker threads. Outcomes are
+/// returned in job order; one failure does not stop the others.
+pub fn run(
+    jobs: &[Job],
+    opts: &Options,
+    convert: impl Fn(&Path, &[u8]) -> Result<Vec<u8>> + Sync,
+) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new(jobs.iter().map(|_| None).collect());
+    let process = |job: &Job| -> Result<Outcome> {
+        if !opts.force && up_to_date(job) {
+            return Ok(Outcome::Skipped);
+        }
+        let input =
+            fs::read(&job.src).with_context(|| format!("failed reading {}", job.src.display()))?;
+        let output = convert(&job.src, &input)?;
+        if let Some(parent) = job.dst.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed creating {}", parent.display()))?;
+        }
+        // Write then rename, so readers never see a half-written output
+        let name = job.dst.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = job.dst.with_file_name(format!(".{name}.tmp"));
+        fs::write(&tmp, &output)
+            .and_then(|()| fs::rename(&tmp, &job.dst))
+            .with_context(|| format!("failed writing {}", job.dst.display()))?;
+        Ok(Outcome::Written {
+            input: input.len(),
+            output: output.len(),
+        })
+    };
+    std::thread::scope(|scope| {
+        for _ in 0..opts.jobs.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(i) else { break };
+                let outcome = process(job).unwrap_or_else(|e| Outcome::Failed(format!("{e:#}")));
+                outcomes.lock().unwrap()[i] = Some(outcome);
+            });
+        }
+    });
+    outcomes
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|o| o.expect("every job ran"))
+        .collect()
+}
+
+/// Print one row per job plus a total; returns the number of failures.
+pub fn print_summary(jobs: &[Job], outcomes: &[Outcome]) -> usize {
+    let count = |f: fn(&Outcome) -> bool| outcomes.iter().filter(|o| f(o)).count();
+    let skipped = count(|o| matches!(o, Outcome::Skipped));
+    let failed = count(|o| matches!(o, Outcome::Failed(_)));
+    let total = format!(
+        "total ({} written, {skipped} skipped, {failed} failed)",
+        jobs.len() - skipped - failed
+    );
+    let width = jobs
+        .iter()
+        .map(|j| j.src.display().to_string().chars().count())
+        .chain([total.len()])
+        .max()
+        .unwrap_or(0);
+
+    println!(
+        "{:<width$}  {:>10}  {:>10}  {:>7}",
+        "file", "input", "output", "ratio"
+    );
+    let (mut total_in, mut total_out) = (0, 0);
+    for (job, outcome) in jobs.iter().zip(outcomes) {
+        let name = job.src.display();
+        match outcome {
+            Outcome::Written { input, output } => {
+                total_in += input;
+                total_out += output;
+                println!(
+                    "{name:<width$}  {input:>10}  {output:>10}  {:>7}",
+                    ratio(*input, *output)
+                );
+            }
+            Outcome::Skipped => println!("{name:<width$}  {:>10}  {:>10}  up to date", "-", "-"),
+            Outcome::Failed(err) => {
+                println!("{name:<width$}  {:>10}  {:>10}  error: {err}", "-", "-")
+            }
+        }
+    }
+    println!(
+        "{total:<width$}  {total_in:>10}  {total_out:>10}  {:>7}",
+        ratio(total_in, total_out)
+    );
+    failed
+}
+
+/// Output size as a share of input size.
+pub fn ratio(input: usize, output: usize) -> String {
+    if input == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", output as f64 * 100.0 / input as f64)
+}
@@ -0,0 +1,52 @@
+// Serde representation for byte fields: a JSON string when the bytes are
+// UTF-8 (the common case, and what dashboards want to read), otherwise an
+// array of numbers. Both forms deserialize back to the same bytes.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<Repr> for Vec<u8> {
+    fn from(repr: Repr) -> Self {
+        match repr {
+            Repr::Text(s) => s.into_bytes(),
+            Repr::Bytes(b) => b,
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => serializer.serialize_str(s),
+        Err(_) => bytes.serialize(serializer),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    Repr::deserialize(deserializer).map(Vec::from)
+}
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => super::serialize(bytes, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<Repr>::deserialize(deserializer)?.map(Vec::from))
+    }
+}
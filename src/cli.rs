@@ -1,13 +1,43 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 
-use marqant::{mq2_uni_decode, mq2_uni_encode, read_mq_metadata, Marqant, MQ2_UNI_DICT_ID};
+use crate::{batch, grep, watch};
+
+use marqant::config::{Compression, Config};
+use marqant::m8;
+use marqant::{
+    analyze_markdown, diff_mq_with_options, estimate_tokens, mq2_uni_decode, mq2_uni_encode,
+    read_mq_metadata, verify_mq_with_options, Aggregate, AggregateEntry, AggregateFile, Analysis,
+    ArchiveFormat, DecodeOptions, DictChange, Marqant, MqDiff, MqInfo, ProjectIdentity, Selection,
+    Severity, VerifyReport, MQ2_UNI_DICT_ID,
+};
 
 pub fn run_cli() -> Result<()> {
-    let mut args = std::env::args().skip(1);
+    // `--no-config` is accepted anywhere on the command line
+    let mut no_config = false;
+    let argv: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| {
+            no_config |= a == "--no-config";
+            a != "--no-config"
+        })
+        .collect();
+    let config = if no_config {
+        None
+    } else {
+        Config::discover(&std::env::current_dir()?)?.map(Arc::new)
+    };
+    let config = config.as_deref();
+    // `MARQANT_RESOLVERS` still wins over the project file
+    let decode = config.map(Config::decode_options).unwrap_or_default();
+
+    let mut args = argv.into_iter();
     let Some(cmd) = args.next() else {
         return print_help();
     };
@@ -17,29 +47,32 @@ pub fn run_cli() -> Result<()> {
             // Print dict_id derived from ~T/~S if present; or --uni prints built-in UNI dict id
             let mut input: Option<PathBuf> = None;
             let mut uni = false;
+            let mut json = false;
             for a in args.by_ref() {
                 match a.as_str() {
                     "--uni" => uni = true,
+                    "--json" => json = true,
                     s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
                     _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
                 }
             }
             if uni {
-                println!("{}", MQ2_UNI_DICT_ID);
-                return Ok(());
+                return print_dict_id(MQ2_UNI_DICT_ID, json);
             }
+            // Bytes, not text: MQ2 `~T` sections are binary
             let mq = match input {
-                Some(path) => fs::read_to_string(&path)
-                    .with_context(|| format!("failed reading {}", path.display()))?,
+                Some(path) => {
+                    fs::read(&path).with_context(|| format!("failed reading {}", path.display()))?
+                }
                 None => {
-                    let mut buf = String::new();
-                    io::stdin().read_to_string(&mut buf)?;
+                    let mut buf = Vec::new();
+                    io::stdin().read_to_end(&mut buf)?;
                     buf
                 }
             };
             let info = read_mq_metadata(&mq)?;
             if let Some(id) = info.dict_id {
-                println!("{}", id);
+                print_dict_id(&id, json)?;
             } else {
                 return Err(anyhow!(
                     "no ~T/~S maps present; cannot derive dict_id (use --uni for built-in)"
@@ -115,11 +148,10 @@ pub fn run_cli() -> Result<()> {
             }
         }
         "compress" => {
-            let mut input: Option<PathBuf> = None;
+            let mut inputs: Vec<PathBuf> = Vec::new();
             let mut output: Option<PathBuf> = None;
-            let mut use_zlib = false;
-            let mut use_semantic = false;
-            let mut std_id: Option<String> = None;
+            let mut batch_opts = batch_options(config);
+            let mut flags = CompressFlags::default();
 
             let iter = args.by_ref();
             while let Some(a) = iter.next() {
@@ -130,25 +162,30 @@ pub fn run_cli() -> Result<()> {
                         };
                         output = Some(PathBuf::from(p));
                     }
-                    "--binary" => {
-                        use_zlib = true;
-                    }
-                    "--semantic" => {
-                        use_semantic = true;
-                    }
-                    "--std" => {
-                        let Some(id) = iter.next() else {
-                            return Err(anyhow!("missing value for --std"));
-                        };
-                        std_id = Some(id);
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, iter)?
+                            && !parse_batch_arg(&a, iter, &mut batch_opts)?
+                        {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
                     }
-                    s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
-                    _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
                 }
             }
+            if is_batch(&inputs, &batch_opts) {
+                if output.is_some() {
+                    return Err(anyhow!("-o takes a single input; use --output-dir"));
+                }
+                if batch_opts.output_dir.is_none() {
+                    batch_opts.output_dir = config.and_then(|c| c.output_dir.clone());
+                }
+                return run_batch(&inputs, "md", "mq", &batch_opts, |src, bytes| {
+                    compress_bytes(bytes, flags.resolve(config, Some(src)).as_deref())
+                });
+            }
 
-            let content = match input {
-                Some(path) => fs::read_to_string(&path)
+            let content = match inputs.first() {
+                Some(path) => fs::read_to_string(path)
                     .with_context(|| format!("failed reading {}", path.display()))?,
                 None => {
                     let mut buf = String::new();
@@ -156,41 +193,147 @@ pub fn run_cli() -> Result<()> {
                     buf
                 }
             };
+            let flags = flags.resolve(config, inputs.first().map(PathBuf::as_path));
+            let mq = Marqant::compress_markdown_with_flags(&content, flags.as_deref())?;
 
-            let mut flags = String::new();
-            if use_zlib {
-                flags.push_str("-zlib");
+            match output {
+                Some(path) => fs::write(&path, mq)
+                    .with_context(|| format!("failed writing {}", path.display()))?,
+                None => {
+                    io::stdout().write_all(mq.as_bytes())?;
+                }
             }
-            if use_semantic {
-                if !flags.is_empty() {
-                    flags.push(' ');
+        }
+        "aggregate" => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut output: Option<PathBuf> = None;
+            let mut update: Option<PathBuf> = None;
+            let mut remove: Vec<String> = Vec::new();
+            let mut archives: Vec<PathBuf> = Vec::new();
+            let mut flags = CompressFlags::default();
+            let mut flagged = false;
+            let mut selection = Selection::default();
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-o" | "--output" | "--update" | "--remove" | "--include" | "--exclude"
+                    | "--from-tar" | "--from-zip" => {
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        match a.as_str() {
+                            "--update" => update = Some(PathBuf::from(p)),
+                            "--remove" => remove.push(p),
+                            "--from-tar" | "--from-zip" => archives.push(PathBuf::from(p)),
+                            "--include" => selection.include.push(p),
+                            "--exclude" => selection.exclude.push(p),
+                            _ => output = Some(PathBuf::from(p)),
+                        }
+                    }
+                    "--max-size" => {
+                        let size = args.next().and_then(|s| s.parse::<u64>().ok());
+                        let Some(size) = size else {
+                            return Err(anyhow!("--max-size needs a size in bytes"));
+                        };
+                        selection.max_size = Some(size);
+                    }
+                    "--no-ignore" => selection.no_ignore = true,
+                    "--semantic" => return Err(anyhow!("aggregates do not support --semantic")),
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, &mut args)? {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                        flagged = true;
+                    }
                 }
-                flags.push_str("-semantic");
             }
-            if let Some(id) = &std_id {
-                if !flags.is_empty() {
-                    flags.push(' ');
+            if let Some(bundle) = update {
+                if flagged {
+                    return Err(anyhow!(
+                        "--update keeps the bundle's flags; rebuild it to change them"
+                    ));
                 }
-                flags.push_str("-std:");
-                flags.push_str(id);
+                let text = read_text(&bundle)?;
+                let entries = Aggregate::list(&text)?;
+                let out = output.unwrap_or(bundle);
+                let skip = fs::canonicalize(&out).ok();
+                let mut files = Vec::new();
+                for input in &inputs {
+                    // A file that is gone stands for its entry's removal
+                    if !input.exists() {
+                        let name = bundle_entry_for(&entries, input).ok_or_else(|| {
+                            anyhow!(
+                                "{} does not exist and is not in the bundle",
+                                input.display()
+                            )
+                        })?;
+                        remove.push(name.to_string());
+                        continue;
+                    }
+                    for (src, rel) in
+                        selected_sources(std::slice::from_ref(input), &selection, config)?
+                    {
+                        if skip.is_some() && fs::canonicalize(&src).ok() == skip {
+                            continue;
+                        }
+                        let name = match bundle_entry_for(&entries, &src) {
+                            Some(name) if !input.is_dir() => name.to_string(),
+                            _ => rel,
+                        };
+                        files.push(AggregateFile::read(&src, name)?);
+                    }
+                }
+                files.extend(archive_files(&archives, &selection)?);
+                let mq = Marqant::update_aggregate_with_options(&text, &files, &remove, &decode)?;
+                fs::write(&out, &mq)
+                    .with_context(|| format!("failed writing {}", out.display()))?;
+                let dead: usize = Aggregate::list_tombstones(&mq)?.iter().map(|t| t.len).sum();
+                eprintln!(
+                    "updated {}  {} -> {} bytes ({dead} bytes in tombstones)",
+                    out.display(),
+                    text.len(),
+                    mq.len()
+                );
+                return Ok(());
             }
-            let flags_opt = if flags.is_empty() {
-                None
-            } else {
-                Some(flags.as_str())
-            };
-
-            let mq = Marqant::compress_markdown_with_flags(&content, flags_opt)?;
-
+            if !remove.is_empty() {
+                return Err(anyhow!("--remove needs --update <bundle.mq>"));
+            }
+            if inputs.is_empty() && archives.is_empty() {
+                return Err(anyhow!(
+                    "usage: mq aggregate <files|dirs...> [-o <out.mq>] [--binary] [--std <id>]"
+                ));
+            }
+            // Never bundle the bundle we are about to overwrite
+            let skip = output.as_deref().and_then(|p| fs::canonicalize(p).ok());
+            let mut files = Vec::new();
+            for (src, rel) in selected_sources(&inputs, &selection, config)? {
+                if skip.is_some() && fs::canonicalize(&src).ok() == skip {
+                    continue;
+                }
+                files.push(AggregateFile::read(&src, rel)?);
+            }
+            files.extend(archive_files(&archives, &selection)?);
+            // Project defaults apply, except `-semantic`, which bundles lack
+            let flags = Aggregate::flags_for(flags.resolve(config, None).as_deref());
+            let mq = Marqant::aggregate_documents(&files, flags.as_deref())?;
             match output {
-                Some(path) => fs::write(&path, mq)
-                    .with_context(|| format!("failed writing {}", path.display()))?,
-                None => {
-                    io::stdout().write_all(mq.as_bytes())?;
+                Some(path) => {
+                    fs::write(&path, &mq)
+                        .with_context(|| format!("failed writing {}", path.display()))?;
+                    let original: usize = files.iter().map(|f| f.content.len()).sum();
+                    eprintln!(
+                        "aggregated {} files into {}  {original} -> {} bytes ({})",
+                        files.len(),
+                        path.display(),
+                        mq.len(),
+                        batch::ratio(original, mq.len())
+                    );
                 }
+                None => io::stdout().write_all(mq.as_bytes())?,
             }
         }
-        "decompress" => {
+        "compact" => {
             let mut input: Option<PathBuf> = None;
             let mut output: Option<PathBuf> = None;
             while let Some(a) = args.next() {
@@ -205,30 +348,284 @@ pub fn run_cli() -> Result<()> {
                     _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
                 }
             }
+            let input =
+                input.ok_or_else(|| anyhow!("usage: mq compact <bundle.mq> [-o <out.mq>]"))?;
+            let text = read_text(&input)?;
+            let tombstones = Aggregate::list_tombstones(&text)?.len();
+            let mq = Marqant::compact_aggregate_with_options(&text, &decode)?;
+            let out = output.unwrap_or(input);
+            fs::write(&out, &mq).with_context(|| format!("failed writing {}", out.display()))?;
+            eprintln!(
+                "compacted {}  {} -> {} bytes ({tombstones} tombstones dropped)",
+                out.display(),
+                text.len(),
+                mq.len()
+            );
+        }
+        "pack" => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut output: Option<PathBuf> = None;
+            let mut budget: Option<usize> = None;
+            let mut query: Option<String> = None;
+            let mut flags = CompressFlags::default();
+            let mut selection = Selection::default();
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-o" | "--output" | "--query" | "--include" | "--exclude" => {
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        match a.as_str() {
+                            "--query" => query = Some(p),
+                            "--include" => selection.include.push(p),
+                            "--exclude" => selection.exclude.push(p),
+                            _ => output = Some(PathBuf::from(p)),
+                        }
+                    }
+                    "--budget" => {
+                        let tokens = args.next().and_then(|s| s.parse::<usize>().ok());
+                        let Some(tokens) = tokens.filter(|t| *t > 0) else {
+                            return Err(anyhow!("--budget needs a number of tokens"));
+                        };
+                        budget = Some(tokens);
+                    }
+                    "--max-size" => {
+                        let size = args.next().and_then(|s| s.parse::<u64>().ok());
+                        let Some(size) = size else {
+                            return Err(anyhow!("--max-size needs a size in bytes"));
+                        };
+                        selection.max_size = Some(size);
+                    }
+                    "--no-ignore" => selection.no_ignore = true,
+                    "--semantic" => return Err(anyhow!("aggregates do not support --semantic")),
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, &mut args)? {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                    }
+                }
+            }
+            let (Some(budget), false) = (budget, inputs.is_empty()) else {
+                return Err(anyhow!(
+                    "usage: mq pack <files|dirs...> --budget <tokens> [--query <text>] [-o <out.mq>]"
+                ));
+            };
+            let skip = output.as_deref().and_then(|p| fs::canonicalize(p).ok());
+            let mut files = Vec::new();
+            for (src, rel) in selected_sources(&inputs, &selection, config)? {
+                if skip.is_some() && fs::canonicalize(&src).ok() == skip {
+                    continue;
+                }
+                files.push(AggregateFile::read(&src, rel)?);
+            }
+            let flags = Aggregate::flags_for(flags.resolve(config, None).as_deref());
+            let (mq, ranking) = Marqant::pack(&files, budget, query.as_deref(), flags.as_deref())?;
+            for f in &ranking {
+                eprintln!(
+                    "{} {:>8} tokens  relevance {:.2}  novelty {:.2}  {}",
+                    if f.packed { "+" } else { "-" },
+                    f.tokens,
+                    f.relevance,
+                    f.novelty,
+                    f.path
+                );
+            }
+            eprintln!(
+                "packed {} of {} files  ~{} tokens (budget {budget})",
+                ranking.iter().filter(|f| f.packed).count(),
+                ranking.len(),
+                estimate_tokens(&mq)
+            );
+            match output {
+                Some(path) => fs::write(&path, &mq)
+                    .with_context(|| format!("failed writing {}", path.display()))?,
+                None => io::stdout().write_all(mq.as_bytes())?,
+            }
+        }
+        "m8" => run_m8(args, config, &decode)?,
+        "summarize" => {
+            let mut dir: Option<PathBuf> = None;
+            let mut output: Option<PathBuf> = None;
+            let mut budget: Option<usize> = None;
+            let mut flags = CompressFlags::default();
+            let mut selection = Selection::default();
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-o" | "--output" | "--include" | "--exclude" => {
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        match a.as_str() {
+                            "--include" => selection.include.push(p),
+                            "--exclude" => selection.exclude.push(p),
+                            _ => output = Some(PathBuf::from(p)),
+                        }
+                    }
+                    "--budget" => {
+                        let tokens = args.next().and_then(|s| s.parse::<usize>().ok());
+                        let Some(tokens) = tokens.filter(|t| *t > 0) else {
+                            return Err(anyhow!("--budget needs a number of tokens"));
+                        };
+                        budget = Some(tokens);
+                    }
+                    "--max-size" => {
+                        let size = args.next().and_then(|s| s.parse::<u64>().ok());
+                        let Some(size) = size else {
+                            return Err(anyhow!("--max-size needs a size in bytes"));
+                        };
+                        selection.max_size = Some(size);
+                    }
+                    "--no-ignore" => selection.no_ignore = true,
+                    s if !s.starts_with('-') && dir.is_none() => dir = Some(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, &mut args)? {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                    }
+                }
+            }
+            let Some(dir) = dir.filter(|d| d.is_dir()) else {
+                return Err(anyhow!(
+                    "usage: mq summarize <project-dir> [-o <project.m8>] [--budget <tokens>]"
+                ));
+            };
+            // `<dir>/<dir name>.m8`, as the MEM8 integration lays out
+            let output = output.unwrap_or_else(|| {
+                let name = std::path::absolute(&dir)
+                    .ok()
+                    .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "project".to_string());
+                dir.join(format!("{name}.m8"))
+            });
+            let container = Marqant::summarize_project(
+                &dir,
+                &selection,
+                budget,
+                flags.resolve(config, None).as_deref(),
+            )?;
+            let bytes = container.to_bytes()?;
+            fs::write(&output, &bytes)
+                .with_context(|| format!("failed writing {}", output.display()))?;
+            let identity = container
+                .sections
+                .iter()
+                .find(|s| s.kind == m8::IDENTITY)
+                .map(|s| ProjectIdentity::parse(&String::from_utf8_lossy(&s.data)))
+                .unwrap_or_default();
+            eprintln!(
+                "wrote {}  {}{}  {} sections, {} bytes",
+                output.display(),
+                identity.name,
+                identity.version.map_or(String::new(), |v| format!(" {v}")),
+                container.sections.len(),
+                bytes.len()
+            );
+        }
+        "watch" => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut batch_opts = batch_options(config);
+            let mut flags = CompressFlags::default();
+            let mut opts = watch::Options {
+                interval: Duration::from_secs(1),
+                once: false,
+            };
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "--interval" => {
+                        let secs = args.next().and_then(|s| s.parse::<f64>().ok());
+                        let Some(secs) = secs.filter(|s| s.is_finite() && *s > 0.0) else {
+                            return Err(anyhow!("--interval needs a positive number of seconds"));
+                        };
+                        opts.interval = Duration::from_secs_f64(secs);
+                    }
+                    "--once" => opts.once = true,
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, &mut args)?
+                            && !parse_batch_arg(&a, &mut args, &mut batch_opts)?
+                        {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                    }
+                }
+            }
+            if inputs.is_empty() {
+                return Err(anyhow!(
+                    "usage: mq watch <files|dirs...> [--output-dir <dir>]"
+                ));
+            }
+            if batch_opts.output_dir.is_none() {
+                batch_opts.output_dir = config.and_then(|c| c.output_dir.clone());
+            }
+            watch::watch(&inputs, &batch_opts, &opts, |src, bytes| {
+                compress_bytes(bytes, flags.resolve(config, Some(src)).as_deref())
+            })?;
+        }
+        "decompress" => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut output: Option<PathBuf> = None;
+            let mut batch_opts = batch_options(config);
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-o" | "--output" => {
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        output = Some(PathBuf::from(p));
+                    }
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !parse_batch_arg(&a, &mut args, &mut batch_opts)? {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                    }
+                }
+            }
 
-            let mq = match input {
-                Some(path) => fs::read_to_string(&path)
-                    .with_context(|| format!("failed reading {}", path.display()))?,
+            // Any supported format; the decoder is picked from the magic bytes
+            if is_batch(&inputs, &batch_opts) {
+                if output.is_some() {
+                    return Err(anyhow!("-o takes a single input; use --output-dir"));
+                }
+                // Writing next to the input could clobber the original .md
+                if batch_opts.output_dir.is_none() {
+                    return Err(anyhow!("batch decompress needs --output-dir"));
+                }
+                return run_batch(&inputs, "mq", "md", &batch_opts, |_, bytes| {
+                    Marqant::decompress_auto_with_options(bytes, &decode)
+                });
+            }
+            let mq = match inputs.first() {
+                Some(path) => {
+                    fs::read(path).with_context(|| format!("failed reading {}", path.display()))?
+                }
                 None => {
-                    let mut buf = String::new();
-                    io::stdin().read_to_string(&mut buf)?;
+                    let mut buf = Vec::new();
+                    io::stdin().read_to_end(&mut buf)?;
                     buf
                 }
             };
-            let md = Marqant::decompress_marqant(&mq)?;
+            let md = Marqant::decompress_auto_with_options(&mq, &decode)?;
             match output {
                 Some(path) => fs::write(&path, md)
                     .with_context(|| format!("failed writing {}", path.display()))?,
                 None => {
-                    io::stdout().write_all(md.as_bytes())?;
+                    io::stdout().write_all(&md)?;
                 }
             }
         }
         "analyze" => {
-            // very simple analysis: show token count and size effect
             let mut input: Option<PathBuf> = None;
-            for a in args.by_ref() {
+            let mut json = false;
+            let mut top = 10;
+            while let Some(a) = args.next() {
                 match a.as_str() {
+                    "--json" => json = true,
+                    "--top" => {
+                        let n = args.next().ok_or_else(|| anyhow!("--top needs a count"))?;
+                        top = n.parse().with_context(|| format!("invalid --top: {n}"))?;
+                    }
                     s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
                     _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
                 }
@@ -242,32 +639,43 @@ pub fn run_cli() -> Result<()> {
                     buf
                 }
             };
-            let (tokens, tokenized) = Marqant::tokenize_content(&content);
-            let savings = content.len() as isize - tokenized.len() as isize;
-            println!("tokens: {}\nsavings: {} bytes", tokens.len(), savings);
+            let mut analysis = analyze_markdown(&content)?;
+            analysis.phrases.truncate(top);
+            if json {
+                return print_json(&analysis);
+            }
+            print_analysis(&analysis);
         }
         "inspect" => {
             let mut input: Option<PathBuf> = None;
             let mut show_tokens = false;
+            let mut json = false;
             for a in args {
                 match a.as_str() {
                     "--show-tokens" => {
                         show_tokens = true;
                     }
+                    "--json" => json = true,
                     s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
                     _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
                 }
             }
+            // Bytes, not text: MQ2 `~T` sections are binary
             let mq = match input {
-                Some(path) => fs::read_to_string(&path)
-                    .with_context(|| format!("failed reading {}", path.display()))?,
+                Some(path) => {
+                    fs::read(&path).with_context(|| format!("failed reading {}", path.display()))?
+                }
                 None => {
-                    let mut buf = String::new();
-                    io::stdin().read_to_string(&mut buf)?;
+                    let mut buf = Vec::new();
+                    io::stdin().read_to_end(&mut buf)?;
                     buf
                 }
             };
             let info = read_mq_metadata(&mq)?;
+            if json {
+                // Machine output always carries the token list
+                return print_json(&info);
+            }
             println!("kind: {}", info.kind);
             if let Some(v) = info.variant.as_deref() {
                 println!("variant: {}", v);
@@ -291,13 +699,280 @@ pub fn run_cli() -> Result<()> {
                 println!("dict_id: {}", id);
             }
             if show_tokens {
-                if let Some(t) = info.dict_t.as_deref() {
-                    println!("~T{}", t);
+                print_token_table(&info);
+            }
+        }
+        "verify" => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut json = false;
+            for a in args {
+                match a.as_str() {
+                    "--json" => json = true,
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => return Err(anyhow!("unknown arg: {a}")),
+                }
+            }
+            let mut reports = Vec::new();
+            if inputs.is_empty() {
+                let mut buf = Vec::new();
+                io::stdin().read_to_end(&mut buf)?;
+                reports.push(("<stdin>".to_string(), verify_mq_with_options(&buf, &decode)));
+            }
+            for path in &inputs {
+                let bytes =
+                    fs::read(path).with_context(|| format!("failed reading {}", path.display()))?;
+                reports.push((
+                    path.display().to_string(),
+                    verify_mq_with_options(&bytes, &decode),
+                ));
+            }
+            let failed = reports.iter().filter(|(_, r)| !r.is_ok()).count();
+            if json {
+                let files: BTreeMap<&str, &VerifyReport> =
+                    reports.iter().map(|(name, r)| (name.as_str(), r)).collect();
+                print_json(&files)?;
+            } else {
+                for (name, report) in &reports {
+                    let status = if report.is_ok() { "ok" } else { "FAIL" };
+                    println!("{status:<5} {name} ({})", report.kind);
+                    for d in &report.diagnostics {
+                        let severity = match d.severity {
+                            Severity::Error => "error",
+                            Severity::Warning => "warning",
+                        };
+                        println!("  {severity}[{}]: {}", d.check, d.message);
+                    }
+                }
+                if reports.len() > 1 {
+                    println!(
+                        "{} files: {} ok, {failed} failed",
+                        reports.len(),
+                        reports.len() - failed
+                    );
+                }
+            }
+            if failed > 0 {
+                return Err(anyhow!(
+                    "{failed} of {} file(s) failed verification",
+                    reports.len()
+                ));
+            }
+        }
+        "cat" => {
+            let inputs: Vec<PathBuf> = args.map(PathBuf::from).collect();
+            let mut stdout = io::stdout().lock();
+            if inputs.is_empty() {
+                let mut buf = Vec::new();
+                io::stdin().read_to_end(&mut buf)?;
+                stdout.write_all(&Marqant::decompress_auto_with_options(&buf, &decode)?)?;
+            }
+            for path in &inputs {
+                let bytes =
+                    fs::read(path).with_context(|| format!("failed reading {}", path.display()))?;
+                let md = Marqant::decompress_auto_with_options(&bytes, &decode)
+                    .with_context(|| format!("failed decoding {}", path.display()))?;
+                stdout.write_all(&md)?;
+            }
+        }
+        "grep" => grep_status(run_grep(args, &decode)),
+        "ls" | "list" => {
+            let mut input: Option<PathBuf> = None;
+            let mut json = false;
+            for a in args.by_ref() {
+                match a.as_str() {
+                    "--json" => json = true,
+                    s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
+                    _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
+                }
+            }
+            let input = input.ok_or_else(|| anyhow!("usage: mq ls <bundle.mq> [--json]"))?;
+            let text = read_text(&input)?;
+            let entries = Aggregate::list(&text)?;
+            if json {
+                return print_json(&entries);
+            }
+            let mut stdout = io::stdout().lock();
+            writeln!(
+                stdout,
+                "{:>10} {:>10}  {:<16}  path",
+                "original", "stored", "modified"
+            )?;
+            let opt = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+            for e in &entries {
+                let modified = e
+                    .mtime
+                    .and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0))
+                    .map(|t| t.with_timezone(&chrono::Local))
+                    .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string());
+                writeln!(
+                    stdout,
+                    "{:>10} {:>10}  {modified:<16}  {}",
+                    opt(e.size),
+                    e.len,
+                    e.path
+                )?;
+            }
+            let original: u64 = entries.iter().filter_map(|e| e.size).sum();
+            writeln!(
+                stdout,
+                "{} files  {original} -> {} bytes ({})",
+                entries.len(),
+                text.len(),
+                batch::ratio(original as usize, text.len())
+            )?;
+            let tombstones = Aggregate::list_tombstones(&text)?;
+            if !tombstones.is_empty() {
+                writeln!(
+                    stdout,
+                    "{} tombstones  {} dead bytes (mq compact reclaims them)",
+                    tombstones.len(),
+                    tombstones.iter().map(|t| t.len).sum::<usize>()
+                )?;
+            }
+            if let Some(shared) = Aggregate::list_shared(&text)? {
+                writeln!(
+                    stdout,
+                    "{} shared blocks  {} bytes saved by deduplication",
+                    shared.count, shared.savings
+                )?;
+            }
+            if let Some(packing) = Aggregate::list_packing(&text)? {
+                writeln!(
+                    stdout,
+                    "packed for {} tokens  {} files dropped",
+                    packing.budget,
+                    packing.dropped.len()
+                )?;
+                for (path, tokens) in &packing.dropped {
+                    writeln!(stdout, "{:>10} tokens  {path} (dropped)", tokens)?;
+                }
+            }
+        }
+        "extract" => {
+            let mut positional: Vec<String> = Vec::new();
+            let mut dir = PathBuf::from(".");
+            let mut archive: Option<(PathBuf, ArchiveFormat)> = None;
+            let (mut to_stdout, mut force) = (false, false);
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-C" | "--directory" => {
+                        let Some(d) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        dir = PathBuf::from(d);
+                    }
+                    "--to-tar" | "--to-zip" => {
+                        let Some(p) = args.next().map(PathBuf::from) else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        let format = match (a.as_str(), ArchiveFormat::from_path(&p)) {
+                            ("--to-zip", _) => ArchiveFormat::Zip,
+                            (_, Some(ArchiveFormat::TarGz)) => ArchiveFormat::TarGz,
+                            _ => ArchiveFormat::Tar,
+                        };
+                        archive = Some((p, format));
+                    }
+                    "-O" | "--stdout" => to_stdout = true,
+                    "--force" => force = true,
+                    s if !s.starts_with('-') => positional.push(a),
+                    _ => return Err(anyhow!("unknown arg: {a}")),
+                }
+            }
+            let Some((input, wanted)) = positional.split_first() else {
+                return Err(anyhow!(
+                    "usage: mq extract <bundle.mq> [paths...] [-C <dir>] [-O] [--force]"
+                ));
+            };
+            let bundle = Aggregate::parse_with_options(&read_text(Path::new(input))?, &decode)?;
+            let entries = select_entries(&bundle.entries, wanted)?;
+            if let Some((path, format)) = archive {
+                let bytes = bundle.to_archive(&entries, format)?;
+                if path.as_os_str() == "-" {
+                    io::stdout().write_all(&bytes)?;
+                } else {
+                    fs::write(&path, bytes)
+                        .with_context(|| format!("failed writing {}", path.display()))?;
+                    eprintln!("{} files into {}", entries.len(), path.display());
+                }
+                return Ok(());
+            }
+            let mut stdout = io::stdout().lock();
+            for entry in entries {
+                let content = bundle.extract(&entry.path)?;
+                if to_stdout {
+                    stdout.write_all(content.as_bytes())?;
+                    continue;
+                }
+                let dst = dir.join(bundle_path(&entry.path)?);
+                if dst.exists() && !force {
+                    return Err(anyhow!(
+                        "{} exists; use --force to overwrite",
+                        dst.display()
+                    ));
+                }
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed creating {}", parent.display()))?;
+                }
+                fs::write(&dst, &content)
+                    .with_context(|| format!("failed writing {}", dst.display()))?;
+                if let Some(mtime) = entry.mtime {
+                    let time = std::time::UNIX_EPOCH + Duration::from_secs(mtime);
+                    fs::File::options()
+                        .write(true)
+                        .open(&dst)
+                        .and_then(|f| f.set_modified(time))
+                        .with_context(|| format!("failed setting mtime of {}", dst.display()))?;
+                }
+                #[cfg(unix)]
+                if let Some(mode) = entry.mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    // Never setuid, setgid or sticky, whatever the bundle says
+                    fs::set_permissions(&dst, fs::Permissions::from_mode(mode & 0o777))
+                        .with_context(|| format!("failed setting mode of {}", dst.display()))?;
+                }
+                eprintln!("{}", dst.display());
+            }
+        }
+        "search" => grep_status(run_search(args, &decode)),
+        "diff" => {
+            let mut files: Vec<String> = Vec::new();
+            let mut json = false;
+            let mut git = false;
+            for a in args {
+                match a.as_str() {
+                    "--json" => json = true,
+                    "--git" => git = true,
+                    _ if git || !a.starts_with('-') => files.push(a),
+                    _ => return Err(anyhow!("unknown arg: {a}")),
                 }
-                if let Some(s) = info.dict_s.as_deref() {
-                    println!("~S{}", s);
+            }
+            // As `diff.<driver>.command`, git passes
+            // <path> <old-file> <old-hex> <old-mode> <new-file> <new-hex> <new-mode>
+            let (old, new, old_label, new_label) = match (git, files.as_slice()) {
+                (true, [path, old, _, _, new, _, _]) => {
+                    (old, new, format!("a/{path}"), format!("b/{path}"))
+                }
+                (true, [path]) => {
+                    println!("* Unmerged path {path}");
+                    return Ok(());
                 }
+                (false, [old, new]) => (old, new, old.clone(), new.clone()),
+                _ => return Err(anyhow!("usage: mq diff <old.mq> <new.mq> [--json]")),
+            };
+            let read =
+                |path: &String| fs::read(path).with_context(|| format!("failed reading {path}"));
+            let (old, new) = (read(old)?, read(new)?);
+            let diff = diff_mq_with_options(&old, &new, &old_label, &new_label, &decode)?;
+            if json {
+                return print_json(&diff);
+            }
+            if git && !diff.is_empty() {
+                println!("mq diff {old_label} {new_label}");
             }
+            // Token ids render as chars for MARQANT and as bytes for MQ2
+            let mq2 = [&new, &old].iter().any(|b| b.starts_with(b"MQ2~"));
+            print_diff(&diff, mq2);
         }
         _ => return print_help(),
     }
@@ -305,17 +980,768 @@ pub fn run_cli() -> Result<()> {
     Ok(())
 }
 
+// --binary, --semantic and --std <id>, shared by compress and watch;
+// they add to whatever the project config sets
+#[derive(Default)]
+struct CompressFlags {
+    /// `--binary` / `--no-binary`; unset keeps the project default
+    zlib: Option<bool>,
+    /// `--semantic` / `--no-semantic`
+    semantic: Option<bool>,
+    /// `--seekable [<bytes>]`: seek table with the given block size
+    seekable: Option<String>,
+    /// `--std <id>`; `Some("")` (`--no-std`) turns the project's off
+    std_id: Option<String>,
+}
+
+impl CompressFlags {
+    // Returns false if `arg` is not a compression flag
+    fn parse_arg(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<bool> {
+        match arg {
+            "--binary" => self.zlib = Some(true),
+            "--no-binary" => self.zlib = Some(false),
+            "--semantic" => self.semantic = Some(true),
+            "--no-semantic" => self.semantic = Some(false),
+            "--seekable" => self.seekable = Some("-seekable".to_string()),
+            "--block-size" => {
+                let size = args.next().and_then(|n| n.parse::<usize>().ok());
+                let Some(size) = size.filter(|&n| n > 0) else {
+                    return Err(anyhow!("--block-size needs a positive number of bytes"));
+                };
+                self.seekable = Some(format!("-seekable:{size}"));
+            }
+            "--std" => {
+                let Some(id) = args.next().filter(|id| !id.is_empty()) else {
+                    return Err(anyhow!("missing value for --std"));
+                };
+                self.std_id = Some(id);
+            }
+            "--no-std" => self.std_id = Some(String::new()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // Header flags for `path`: the project config's, with CLI switches on top
+    fn resolve(&self, config: Option<&Config>, path: Option<&Path>) -> Option<String> {
+        let mut c = match (config, path) {
+            (Some(config), Some(path)) => config.compression_for(path),
+            (Some(config), None) => config.compression.clone(),
+            (None, _) => Compression::default(),
+        };
+        c.binary = self.zlib.unwrap_or(c.binary);
+        c.semantic = self.semantic.unwrap_or(c.semantic);
+        if let Some(id) = &self.std_id {
+            c.std = (!id.is_empty()).then(|| id.clone());
+        }
+        // Not a project default: seek tables only pay off for large files
+        match (c.flags(), &self.seekable) {
+            (Some(flags), Some(seek)) => Some(format!("{flags} {seek}")),
+            (None, seek) => seek.clone(),
+            (flags, None) => flags,
+        }
+    }
+}
+
+// Batch defaults, with the project config's ignore globs and thread count
+fn batch_options(config: Option<&Config>) -> batch::Options {
+    let mut opts = batch::Options::default();
+    if let Some(config) = config {
+        opts.jobs = config.jobs.unwrap_or(opts.jobs);
+        opts.config = Some(Arc::new(config.clone()));
+    }
+    opts
+}
+
+fn compress_bytes(bytes: &[u8], flags: Option<&str>) -> Result<Vec<u8>> {
+    let content = std::str::from_utf8(bytes).context("input is not UTF-8 text")?;
+    Ok(Marqant::compress_markdown_with_flags(content, flags)?.into_bytes())
+}
+
+// --output-dir, --force and -j/--jobs, shared by compress and decompress.
+// Returns false if `arg` is not a batch option.
+fn parse_batch_arg(
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+    opts: &mut batch::Options,
+) -> Result<bool> {
+    match arg {
+        "--output-dir" => {
+            let Some(dir) = args.next() else {
+                return Err(anyhow!("missing value for {arg}"));
+            };
+            opts.output_dir = Some(PathBuf::from(dir));
+        }
+        "--force" => opts.force = true,
+        "-j" | "--jobs" => {
+            let n = args.next().and_then(|n| n.parse::<usize>().ok());
+            let Some(n) = n.filter(|n| *n > 0) else {
+                return Err(anyhow!("{arg} needs a positive number"));
+            };
+            opts.jobs = n;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// More than one input, a directory, or an output dir: write files, not stdout
+fn is_batch(inputs: &[PathBuf], opts: &batch::Options) -> bool {
+    inputs.len() > 1 || opts.output_dir.is_some() || inputs.iter().any(|p| p.is_dir())
+}
+
+fn run_batch(
+    inputs: &[PathBuf],
+    in_ext: &str,
+    out_ext: &str,
+    opts: &batch::Options,
+    convert: impl Fn(&Path, &[u8]) -> Result<Vec<u8>> + Sync,
+) -> Result<()> {
+    let jobs = batch::plan(inputs, in_ext, out_ext, opts)?;
+    if jobs.is_empty() {
+        return Err(anyhow!("no .{in_ext} files found"));
+    }
+    let outcomes = batch::run(&jobs, opts, convert);
+    let failed = batch::print_summary(&jobs, &outcomes);
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} file(s) failed", jobs.len()));
+    }
+    Ok(())
+}
+
+fn print_analysis(a: &Analysis) {
+    let percent = |size: usize| ratio_of(size, a.original_size);
+    println!("original: {} bytes", a.original_size);
+    println!(
+        "\n{:<10} {:>10} {:>8} {:>10}",
+        "mode", "size", "ratio", "saved"
+    );
+    for m in &a.modes {
+        println!(
+            "{:<10} {:>10} {:>8} {:>10}",
+            m.mode,
+            m.size,
+            percent(m.size),
+            m.savings
+        );
+    }
+    println!("best: {}", a.best_mode);
+
+    println!(
+        "\ntokens: {} (body {} -> {} bytes, before dictionary)",
+        a.tokens, a.original_size, a.tokenized_size
+    );
+    for (kind, c) in [("static", &a.static_tokens), ("dynamic", &a.dynamic_tokens)] {
+        println!(
+            "  {kind:<8} {:>4} entries {:>6} uses  net {:>+7} bytes",
+            c.count, c.occurrences, c.savings
+        );
+    }
+
+    if !a.phrases.is_empty() {
+        println!("\ntop phrases:");
+        println!("  {:>7} {:>5}  {:<7}  pattern", "net", "uses", "kind");
+        for p in &a.phrases {
+            let kind = if p.dynamic { "dynamic" } else { "static" };
+            println!(
+                "  {:>+7} {:>5}  {kind:<7}  {:?}",
+                p.savings, p.occurrences, p.pattern
+            );
+        }
+    }
+
+    println!(
+        "\n{:>10} {:>10} {:>8}  section",
+        "original", "tokenized", "ratio"
+    );
+    for s in &a.sections {
+        let title = if s.title.is_empty() {
+            "(preamble)"
+        } else {
+            &s.title
+        };
+        println!(
+            "{:>10} {:>10} {:>8}  {title}",
+            s.original_size,
+            s.tokenized_size,
+            ratio_of(s.tokenized_size, s.original_size)
+        );
+    }
+
+    for w in &a.warnings {
+        println!("warning: {w}");
+    }
+}
+
+fn ratio_of(part: usize, whole: usize) -> String {
+    if whole == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", part as f64 * 100.0 / whole as f64)
+}
+
+// grep convention: exit status 1 when nothing matched, 2 on errors
+fn grep_status(matched: Result<bool>) {
+    match matched {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            std::process::exit(2);
+        }
+    }
+}
+
+// `mq grep`: whether any line matched
+fn run_grep(args: impl Iterator<Item = String>, decode: &DecodeOptions) -> Result<bool> {
+    let grep_args = GrepArgs::parse(args)?;
+    let mut positional = grep_args.positional.iter();
+    let pattern = positional
+        .next()
+        .ok_or_else(|| anyhow!("usage: mq grep <pattern> [paths...]"))?;
+    let inputs: Vec<PathBuf> = positional.map(PathBuf::from).collect();
+    let (re, opts) = (grep_args.regex(pattern)?, grep_args.opts);
+
+    let mut searcher = grep::Searcher::default();
+    let mut stdout = io::stdout().lock();
+    let mut matched = false;
+    if inputs.is_empty() {
+        let mut buf = Vec::new();
+        io::stdin().read_to_end(&mut buf)?;
+        let text = String::from_utf8_lossy(&Marqant::decompress_auto_with_options(&buf, decode)?)
+            .into_owned();
+        matched |= searcher.search("(standard input)", &text, &re, &opts, &mut stdout)? > 0;
+    }
+    // Undecodable files are reported and skipped, like unreadable ones in grep
+    let mut failed = 0;
+    for path in batch::files(&inputs, "mq")? {
+        let decoded = fs::read(&path)
+            .with_context(|| format!("failed reading {}", path.display()))
+            .and_then(|bytes| Marqant::decompress_auto_with_options(&bytes, decode));
+        match decoded {
+            Ok(md) => {
+                let text = String::from_utf8_lossy(&md);
+                let name = path.display().to_string();
+                matched |= searcher.search(&name, &text, &re, &opts, &mut stdout)? > 0;
+            }
+            Err(e) => {
+                eprintln!("mq grep: {}: {e:#}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    stdout.flush()?;
+    if failed > 0 {
+        return Err(anyhow!("{failed} file(s) could not be searched"));
+    }
+    Ok(matched)
+}
+
+// `mq search`: whether any line of the selected bundle entries matched
+fn run_search(args: impl Iterator<Item = String>, decode: &DecodeOptions) -> Result<bool> {
+    let grep_args = GrepArgs::parse(args)?;
+    let [input, pattern, wanted @ ..] = grep_args.positional.as_slice() else {
+        return Err(anyhow!("usage: mq search <bundle.mq> <pattern> [paths...]"));
+    };
+    let re = grep_args.regex(pattern)?;
+    let bundle = Aggregate::parse_with_options(&read_text(Path::new(input))?, decode)?;
+    let mut searcher = grep::Searcher::default();
+    let mut stdout = io::stdout().lock();
+    let mut matched = false;
+    for entry in select_entries(&bundle.entries, wanted)? {
+        let text = bundle.extract(&entry.path)?;
+        matched |= searcher.search(&entry.path, &text, &re, &grep_args.opts, &mut stdout)? > 0;
+    }
+    stdout.flush()?;
+    Ok(matched)
+}
+
+// grep-style options shared by `mq grep` and `mq search`; everything else
+// is kept, in order, as a positional arg
+struct GrepArgs {
+    positional: Vec<String>,
+    opts: grep::Options,
+    ignore_case: bool,
+    fixed: bool,
+}
+
+impl GrepArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = GrepArgs {
+            positional: Vec::new(),
+            opts: grep::Options::default(),
+            ignore_case: false,
+            fixed: false,
+        };
+        let opts = &mut parsed.opts;
+        while let Some(a) = args.next() {
+            let mut lines = |flag: &str| -> Result<usize> {
+                let n = args.next().ok_or_else(|| anyhow!("{flag} needs a count"))?;
+                n.parse().with_context(|| format!("invalid {flag}: {n}"))
+            };
+            match a.as_str() {
+                "-i" | "--ignore-case" => parsed.ignore_case = true,
+                "-F" | "--fixed-strings" => parsed.fixed = true,
+                "-l" | "--files-with-matches" => opts.files_only = true,
+                "-c" | "--count" => opts.count = true,
+                "-A" => opts.after = lines("-A")?,
+                "-B" => opts.before = lines("-B")?,
+                "-C" => {
+                    opts.before = lines("-C")?;
+                    opts.after = opts.before;
+                }
+                s if !s.starts_with('-') || s == "-" => parsed.positional.push(a),
+                _ => return Err(anyhow!("unknown arg: {a}")),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn regex(&self, pattern: &str) -> Result<regex::Regex> {
+        let pattern = if self.fixed {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .with_context(|| format!("invalid pattern: {pattern}"))
+    }
+}
+
+// `rel` as a `/`-separated bundle path
+fn slash_join(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Files to bundle: directories walked with `selection` (config ignores
+// apply too), files given directly under their own name
+fn selected_sources(
+    inputs: &[PathBuf],
+    selection: &Selection,
+    config: Option<&Config>,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        if !input.is_dir() {
+            for (src, rel) in batch::sources(std::slice::from_ref(input), "md", None, None)? {
+                sources.push((src, slash_join(&rel)));
+            }
+            continue;
+        }
+        for (src, rel) in selection.walk(input)? {
+            if !config.is_some_and(|c| c.is_ignored(&src)) {
+                sources.push((src, rel));
+            }
+        }
+    }
+    Ok(sources)
+}
+
+// Documents from tar, tar.gz or zip archives, as `selection` takes them
+fn archive_files(archives: &[PathBuf], selection: &Selection) -> Result<Vec<AggregateFile>> {
+    let mut files = Vec::new();
+    for path in archives {
+        let bytes = fs::read(path).with_context(|| format!("failed reading {}", path.display()))?;
+        files.extend(
+            AggregateFile::from_archive(&bytes, selection)
+                .with_context(|| format!("failed reading {}", path.display()))?,
+        );
+    }
+    Ok(files)
+}
+
+// `mq m8 compress|ls|extract`: MEM8 containers
+fn run_m8(
+    mut args: impl Iterator<Item = String>,
+    config: Option<&Config>,
+    decode: &DecodeOptions,
+) -> Result<()> {
+    let usage = "usage: mq m8 compress <files...> [-o <out.m8>] [--append] [--tree <dir>] \
+                 [--waves <file>] [--section <type> <file>] [flags]\n       \
+                 mq m8 ls <file.m8> [--json]\n       \
+                 mq m8 extract <file.m8> [--markdown|--mq|--tree|--waves|--section <type>] \
+                 [--index <n>] [-o <out>]";
+    let section_type = |v: Option<String>| {
+        v.and_then(|v| match v.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+            None => v.parse().ok(),
+        })
+        .ok_or_else(|| anyhow!("--section needs a type, e.g. 0x0A"))
+    };
+    match args.next().as_deref() {
+        Some("compress") => {
+            let mut inputs: Vec<PathBuf> = Vec::new();
+            let mut output: Option<PathBuf> = None;
+            let mut append = false;
+            let mut raw: Vec<(u8, PathBuf)> = Vec::new();
+            let mut tree: Option<PathBuf> = None;
+            let mut flags = CompressFlags::default();
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "-o" | "--output" | "--tree" | "--waves" => {
+                        let Some(p) = args.next().map(PathBuf::from) else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        match a.as_str() {
+                            "--tree" => tree = Some(p),
+                            "--waves" => raw.push((m8::WAVE_MEMORY_BLOB, p)),
+                            _ => output = Some(p),
+                        }
+                    }
+                    "--section" => {
+                        let kind = section_type(args.next())?;
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing file for --section"));
+                        };
+                        raw.push((kind, PathBuf::from(p)));
+                    }
+                    "--append" => append = true,
+                    s if !s.starts_with('-') => inputs.push(PathBuf::from(s)),
+                    _ => {
+                        if !flags.parse_arg(&a, &mut args)? {
+                            return Err(anyhow!("unknown or duplicate arg: {a}"));
+                        }
+                    }
+                }
+            }
+            // `README.md` alone becomes `README.m8`
+            let output = match (output, inputs.as_slice()) {
+                (Some(out), _) => out,
+                (None, [input]) => input.with_extension("m8"),
+                _ => return Err(anyhow!("{usage}")),
+            };
+            let mut container = if append && output.exists() {
+                let bytes = fs::read(&output)
+                    .with_context(|| format!("failed reading {}", output.display()))?;
+                m8::M8::parse(&bytes)?
+            } else {
+                m8::M8::new()
+            };
+            for input in &inputs {
+                let text = read_text(input)?;
+                // Already compressed documents go in as they are
+                if text.starts_with("MARQANT") {
+                    container.push_marqant(&text)?;
+                } else {
+                    container
+                        .push_markdown(&text, flags.resolve(config, Some(input)).as_deref())?;
+                }
+            }
+            if let Some(dir) = tree {
+                container.push(m8::QUANTUM_TREE, m8::tree_snapshot(&dir)?.into_bytes());
+            }
+            for (kind, path) in raw {
+                let data = fs::read(&path)
+                    .with_context(|| format!("failed reading {}", path.display()))?;
+                container.push(kind, data);
+            }
+            let bytes = container.to_bytes()?;
+            fs::write(&output, &bytes)
+                .with_context(|| format!("failed writing {}", output.display()))?;
+            eprintln!(
+                "wrote {}  {} sections, {} bytes",
+                output.display(),
+                container.sections.len(),
+                bytes.len()
+            );
+        }
+        Some("ls") => {
+            let mut input: Option<PathBuf> = None;
+            let mut json = false;
+            for a in args {
+                match a.as_str() {
+                    "--json" => json = true,
+                    s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
+                    _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
+                }
+            }
+            let input = input.ok_or_else(|| anyhow!("{usage}"))?;
+            let bytes =
+                fs::read(&input).with_context(|| format!("failed reading {}", input.display()))?;
+            let sections = m8::M8::parse(&bytes)?.list();
+            if json {
+                return print_json(&sections);
+            }
+            for (i, s) in sections.iter().enumerate() {
+                let name = s.name.as_deref().unwrap_or("unknown");
+                match &s.marqant {
+                    Some(m) => println!(
+                        "{i:>3}  0x{:02X} {name:<20} {:>10} bytes  {} -> {} bytes, {} tokens, flags {:04b}",
+                        s.kind, s.len, m.original_size, m.compressed_size, m.token_count, m.flags
+                    ),
+                    None => println!("{i:>3}  0x{:02X} {name:<20} {:>10} bytes", s.kind, s.len),
+                }
+            }
+        }
+        Some("extract") => {
+            let mut input: Option<PathBuf> = None;
+            let mut output: Option<PathBuf> = None;
+            let mut kind = m8::MARQANT_DOCUMENT;
+            let mut mq = false;
+            let mut index: Option<usize> = None;
+            while let Some(a) = args.next() {
+                match a.as_str() {
+                    "--markdown" => kind = m8::MARQANT_DOCUMENT,
+                    "--mq" => (kind, mq) = (m8::MARQANT_DOCUMENT, true),
+                    "--tree" => kind = m8::QUANTUM_TREE,
+                    "--waves" => kind = m8::WAVE_MEMORY_BLOB,
+                    "--section" => kind = section_type(args.next())?,
+                    "--index" => {
+                        let Some(n) = args.next().and_then(|n| n.parse().ok()) else {
+                            return Err(anyhow!("--index needs a number"));
+                        };
+                        index = Some(n);
+                    }
+                    "-o" | "--output" => {
+                        let Some(p) = args.next() else {
+                            return Err(anyhow!("missing value for {a}"));
+                        };
+                        output = Some(PathBuf::from(p));
+                    }
+                    s if !s.starts_with('-') && input.is_none() => input = Some(PathBuf::from(s)),
+                    _ => return Err(anyhow!("unknown or duplicate arg: {a}")),
+                }
+            }
+            let input = input.ok_or_else(|| anyhow!("{usage}"))?;
+            let bytes =
+                fs::read(&input).with_context(|| format!("failed reading {}", input.display()))?;
+            let container = m8::M8::parse(&bytes)?;
+            // Every section of the type, or the n-th of them
+            let sections: Vec<&m8::M8Section> = container
+                .sections
+                .iter()
+                .filter(|s| s.kind == kind)
+                .enumerate()
+                .filter(|(i, _)| index.is_none_or(|n| n == *i))
+                .map(|(_, s)| s)
+                .collect();
+            if sections.is_empty() {
+                let name = m8::section_name(kind).unwrap_or("unknown");
+                return Err(anyhow!(
+                    "{} has no 0x{kind:02X} {name} section{}",
+                    input.display(),
+                    index.map_or(String::new(), |n| format!(" #{n}"))
+                ));
+            }
+            let mut out = Vec::new();
+            for s in sections {
+                if kind != m8::MARQANT_DOCUMENT {
+                    out.extend_from_slice(&s.data);
+                    continue;
+                }
+                let doc = m8::MarqantSection::parse(&s.data)?;
+                if mq {
+                    out.extend_from_slice(doc.mq.as_bytes());
+                } else {
+                    out.extend_from_slice(doc.decompress_with_options(decode)?.as_bytes());
+                }
+            }
+            match output {
+                Some(path) => fs::write(&path, out)
+                    .with_context(|| format!("failed writing {}", path.display()))?,
+                None => io::stdout().write_all(&out)?,
+            }
+        }
+        _ => return Err(anyhow!("{usage}")),
+    }
+    Ok(())
+}
+
+// The bundle entry a file on the command line stands for: the longest entry
+// path that ends `file` at a component boundary
+fn bundle_entry_for<'a>(entries: &'a [AggregateEntry], file: &Path) -> Option<&'a str> {
+    let file = slash_join(file);
+    entries
+        .iter()
+        .map(|e| e.path.as_str())
+        .filter(|p| *p == file || file.ends_with(&format!("/{p}")))
+        .max_by_key(|p| p.len())
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed reading {}", path.display()))
+}
+
+// Bundle entries named in `wanted`, exactly or as a directory prefix, in
+// manifest order; all of them when `wanted` is empty
+fn select_entries<'a>(
+    entries: &'a [AggregateEntry],
+    wanted: &[String],
+) -> Result<Vec<&'a AggregateEntry>> {
+    let matches = |e: &AggregateEntry, w: &str| {
+        e.path == w || e.path.starts_with(&format!("{}/", w.trim_end_matches('/')))
+    };
+    if let Some(w) = wanted
+        .iter()
+        .find(|w| !entries.iter().any(|e| matches(e, w)))
+    {
+        return Err(anyhow!("`{w}` is not in the bundle"));
+    }
+    Ok(entries
+        .iter()
+        .filter(|e| wanted.is_empty() || wanted.iter().any(|w| matches(e, w)))
+        .collect())
+}
+
+// A bundle path as a relative path that stays inside the extraction directory
+fn bundle_path(path: &str) -> Result<PathBuf> {
+    let rel = PathBuf::from(path);
+    let safe = rel
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)));
+    if !safe {
+        return Err(anyhow!("refusing to extract unsafe path `{path}`"));
+    }
+    Ok(rel)
+}
+
+fn print_dict_id(id: &str, json: bool) -> Result<()> {
+    if json {
+        #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+        #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+        struct DictId<'a> {
+            dict_id: &'a str,
+        }
+        return print_json(&DictId { dict_id: id });
+    }
+    println!("{}", id);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json<T>(_: &T) -> Result<()> {
+    Err(anyhow!("--json needs mq built with the `serde` feature"))
+}
+
+fn print_diff(diff: &MqDiff, mq2: bool) {
+    if !diff.header.is_empty() {
+        println!("header:");
+        for c in &diff.header {
+            let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(none)".to_string());
+            println!("  {}: {} -> {}", c.field, show(&c.old), show(&c.new));
+        }
+    }
+    if !diff.dictionary.is_empty() {
+        let count = |f: fn(&DictChange) -> bool| diff.dictionary.iter().filter(|c| f(c)).count();
+        println!(
+            "dictionary: {} removed, {} added, {} reassigned",
+            count(|c| matches!(c, DictChange::Removed { .. })),
+            count(|c| matches!(c, DictChange::Added { .. })),
+            count(|c| matches!(c, DictChange::Reassigned { .. })),
+        );
+        for c in &diff.dictionary {
+            match c {
+                DictChange::Removed { id, pattern } => {
+                    println!("  - {:<10} {}", token_label(id, mq2), printable(pattern))
+                }
+                DictChange::Added { id, pattern } => {
+                    println!("  + {:<10} {}", token_label(id, mq2), printable(pattern))
+                }
+                DictChange::Reassigned {
+                    pattern,
+                    old_id,
+                    new_id,
+                } => println!(
+                    "  ~ {} -> {} {}",
+                    token_label(old_id, mq2),
+                    token_label(new_id, mq2),
+                    printable(pattern)
+                ),
+            }
+        }
+    }
+    print!("{}", diff.text);
+}
+
+// MQ2 ids are raw bytes; MARQANT ids are (usually invisible) chars
+fn token_label(id: &[u8], mq2: bool) -> String {
+    match std::str::from_utf8(id) {
+        Ok(id) if !mq2 => id
+            .chars()
+            .map(|c| format!("U+{:04X}", c as u32))
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => id.iter().map(|b| format!("0x{b:02X}")).collect(),
+    }
+}
+
+fn print_token_table(info: &MqInfo) {
+    println!("tokens: {}", info.tokens.len());
+    if !info.tokens.is_empty() {
+        println!("  {:<10} {:>5}  pattern", "id", "len");
+    }
+    for entry in &info.tokens {
+        let id = token_label(&entry.id, info.kind == "MQ2");
+        println!(
+            "  {:<10} {:>5}  {}",
+            id,
+            entry.pattern.len(),
+            printable(&entry.pattern)
+        );
+    }
+    if let Some(s) = info.dict_s.as_deref() {
+        println!("~S {}", printable(s));
+    }
+}
+
+// Quoted, with newlines and other control or non-UTF-8 bytes escaped
+fn printable(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => format!("{s:?}"),
+        Err(_) => format!("b\"{}\"", bytes.escape_ascii()),
+    }
+}
+
 fn print_help() -> Result<()> {
     let help = "mq - Marqant CLI\n\n\
 Usage:\n\
-  mq dict-id [<file.mq>|stdin] [--uni]\n\
+  mq dict-id [<file.mq>|stdin] [--uni] [--json]\n\
   mq uni-encode <input> [-o <output>]\n\
   mq uni-decode <input> [-o <output>]\n\
-  mq compress <input.md> [-o <output.mq>] [--binary] [--semantic] [--std <id>]\n\
-  mq decompress <input.mq> [-o <output.md>]\n\
-  mq analyze <input.md>\n\
-  mq inspect <input.mq> [--show-tokens]\n\n\
-If <input> omitted, reads stdin. Writes to stdout if -o omitted.";
+  mq compress <input.md> [-o <output.mq>] [--binary] [--semantic] [--seekable] [--block-size <n>] [--std <id>]\n\
+  mq compress <files|dirs...> [--output-dir <dir>] [--force] [-j <n>] [flags]\n\
+  mq decompress <input> [-o <output.md>]  (format auto-detected)\n\
+  mq decompress <files|dirs...> [--output-dir <dir>] [--force] [-j <n>]\n\
+  mq watch <files|dirs...> [--output-dir <dir>] [--interval <secs>] [--once] [flags]\n\
+  mq aggregate <files|dirs...> [-o <bundle.mq>] [--binary] [--seekable] [--block-size <n>] [--std <id>]\n\
+      [--include <glob>] [--exclude <glob>] [--max-size <bytes>] [--no-ignore]\n\
+      [--from-tar <archive.tar[.gz]>] [--from-zip <archive.zip>]\n\
+  mq aggregate --update <bundle.mq> [files|dirs...] [--remove <path>] [-o <out.mq>]\n\
+  mq compact <bundle.mq> [-o <out.mq>]\n\
+  mq pack <files|dirs...> --budget <tokens> [--query <text>] [-o <bundle.mq>] [flags]\n\
+  mq ls <bundle.mq> [--json]\n\
+  mq extract <bundle.mq> [paths...] [-C <dir>] [-O] [--force] [--to-tar <out.tar[.gz]>] [--to-zip <out.zip>]\n\
+  mq search <bundle.mq> <pattern> [paths...] [grep options]\n\
+  mq m8 compress <files...> [-o <out.m8>] [--append] [--tree <dir>] [--waves <file>] [--section <type> <file>] [flags]\n\
+  mq m8 ls <file.m8> [--json]\n\
+  mq m8 extract <file.m8> [--markdown|--mq|--tree|--waves|--section <type>] [--index <n>] [-o <out>]\n\
+  mq summarize <project-dir> [-o <project.m8>] [--budget <tokens>] [--include <glob>] [--exclude <glob>] [flags]\n\
+  mq analyze <input.md> [--top <n>] [--json]\n\
+  mq inspect <input.mq> [--show-tokens] [--json]\n\
+  mq verify <files.mq...> [--json]\n\
+  mq diff <old.mq> <new.mq> [--json]\n\
+  mq cat <files...>  (format auto-detected)\n\
+  mq grep <pattern> [files|dirs...] [-i] [-F] [-l] [-c] [-A <n>] [-B <n>] [-C <n>]\n\
+  mq diff --git <path> <old-file> <old-hex> <old-mode> <new-file> <new-hex> <new-mode>\n\n\
+If <input> omitted, reads stdin. Writes to stdout if -o omitted.\n\
+With several inputs, a directory or --output-dir, files are written next to\n\
+their inputs (or mirrored under --output-dir); up-to-date outputs are skipped.\n\
+mq watch deletes outputs whose source is removed, including on startup ones\n\
+listed in <output-dir>/.mq-watch; other .mq files there are left alone.\n\
+Defaults come from the nearest .marqant.toml; --no-binary, --no-semantic and\n\
+--no-std turn them off, --no-config ignores it.";
     println!("{}", help);
     Ok(())
 }
@@ -0,0 +1,318 @@
+//! Project configuration from `.marqant.toml`, found by walking up from the
+//! working directory.
+//!
+//! ```toml
+//! [compression]
+//! default_binary = true            # -zlib
+//! default_semantic = true          # -semantic
+//! std = "std-static-v1"            # -std:<id>
+//!
+//! [dictionaries]
+//! resolvers = ["local", "dns"]     # lookup order for -std: ids
+//!
+//! [output]
+//! dir = ".mq-cache"                # relative to this file
+//! ignore = ["drafts/**", "*.tmp.md"]
+//!
+//! [performance]
+//! parallel_threads = 4
+//!
+//! [[override]]
+//! glob = "CHANGELOG.md"
+//! binary = false
+//! ```
+//!
+//! Globs are matched against paths relative to the config file's directory.
+//! `*` and `?` stay within one path segment, `**` spans any number of them; a
+//! glob without `/` matches any single segment, so `drafts` or `*.tmp.md`
+//! apply at every depth. A glob that matches a directory covers everything
+//! under it. Later overrides win over earlier ones.
+
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::dns::Resolver;
+use crate::DecodeOptions;
+
+pub const CONFIG_FILE: &str = ".marqant.toml";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The file this was read from; globs and `output.dir` are relative to
+    /// its directory.
+    pub path: PathBuf,
+    pub compression: Compression,
+    pub resolvers: Option<Vec<Resolver>>,
+    pub output_dir: Option<PathBuf>,
+    pub ignore: Vec<String>,
+    pub jobs: Option<usize>,
+    pub overrides: Vec<Override>,
+}
+
+/// Flags for one file, as they end up in the `MARQANT` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Compression {
+    pub binary: bool,
+    pub semantic: bool,
+    pub std: Option<String>,
+}
+
+impl Compression {
+    /// Header flags string (`-zlib -semantic -std:<id>`), or `None` for a
+    /// plain file.
+    pub fn flags(&self) -> Option<String> {
+        let mut flags = Vec::new();
+        if self.binary {
+            flags.push("-zlib".to_string());
+        }
+        if self.semantic {
+            flags.push("-semantic".to_string());
+        }
+        if let Some(id) = &self.std {
+            flags.push(format!("-std:{id}"));
+        }
+        (!flags.is_empty()).then(|| flags.join(" "))
+    }
+}
+
+/// Settings for files matching `glob`; unset fields keep the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct Override {
+    pub glob: String,
+    pub binary: Option<bool>,
+    pub semantic: Option<bool>,
+    /// `Some("")` turns the standard dictionary off.
+    pub std: Option<String>,
+}
+
+impl Config {
+    /// Look for `.marqant.toml` in `start` and each of its ancestors.
+    pub fn discover(start: &Path) -> Result<Option<Config>> {
+        for dir in start.ancestors() {
+            let path = dir.join(CONFIG_FILE);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed reading {}", path.display()))?;
+        Self::parse(&text, path).map_err(|e| anyhow!("invalid {}: {e:#}", path.display()))
+    }
+
+    /// Parse config text as if read from `path`.
+    #[cfg(feature = "config")]
+    pub fn parse(text: &str, path: &Path) -> Result<Config> {
+        let raw: raw::Config = toml::from_str(text)?;
+        let root = path.parent().unwrap_or(Path::new(""));
+        let resolvers = raw
+            .dictionaries
+            .resolvers
+            .map(|names| names.iter().map(|n| n.parse()).collect::<Result<Vec<_>>>())
+            .transpose()?;
+        if raw.performance.parallel_threads == Some(0) {
+            return Err(anyhow!("performance.parallel_threads must be positive"));
+        }
+        Ok(Config {
+            path: path.to_path_buf(),
+            compression: Compression {
+                binary: raw.compression.default_binary,
+                semantic: raw.compression.default_semantic,
+                std: raw.compression.std.filter(|s| !s.is_empty()),
+            },
+            resolvers,
+            output_dir: raw.output.dir.map(|d| root.join(d)),
+            ignore: raw.output.ignore,
+            jobs: raw.performance.parallel_threads,
+            overrides: raw
+                .overrides
+                .into_iter()
+                .map(|o| Override {
+                    glob: o.glob,
+                    binary: o.binary,
+                    semantic: o.semantic,
+                    std: o.std,
+                })
+                .collect(),
+        })
+    }
+
+    #[cfg(not(feature = "config"))]
+    pub fn parse(_text: &str, _path: &Path) -> Result<Config> {
+        Err(anyhow!("reading {CONFIG_FILE} needs the `config` feature"))
+    }
+
+    /// The directory globs are relative to.
+    pub fn root(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    /// Defaults with every matching override applied in order.
+    pub fn compression_for(&self, file: &Path) -> Compression {
+        let mut c = self.compression.clone();
+        let rel = self.relative(file);
+        for o in self
+            .overrides
+            .iter()
+            .filter(|o| path_matches(&o.glob, &rel))
+        {
+            c.binary = o.binary.unwrap_or(c.binary);
+            c.semantic = o.semantic.unwrap_or(c.semantic);
+            if let Some(std) = &o.std {
+                c.std = (!std.is_empty()).then(|| std.clone());
+            }
+        }
+        c
+    }
+
+    /// Decode options with this project's resolver order.
+    pub fn decode_options(&self) -> DecodeOptions {
+        DecodeOptions {
+            resolvers: self.resolvers.clone(),
+        }
+    }
+
+    pub fn is_ignored(&self, file: &Path) -> bool {
+        let rel = self.relative(file);
+        self.ignore.iter().any(|glob| path_matches(glob, &rel))
+    }
+
+    // `file` relative to the config root, `/`-separated; files outside the
+    // root keep their absolute path
+    fn relative(&self, file: &Path) -> String {
+        let abs = std::path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+        let root = std::path::absolute(self.root()).unwrap_or_else(|_| self.root().into());
+        let rel = abs.strip_prefix(&root).unwrap_or(&abs);
+        let parts: Vec<String> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        parts.join("/")
+    }
+}
+
+// `rel` or one of its parent directories matches `glob`
+pub(crate) fn path_matches(glob: &str, rel: &str) -> bool {
+    let glob = glob.trim_start_matches('/').trim_end_matches('/');
+    if !glob.contains('/') {
+        return rel.split('/').any(|segment| glob_match(glob, segment));
+    }
+    let mut prefix = rel;
+    loop {
+        if glob_match(glob, prefix) {
+            return true;
+        }
+        match prefix.rfind('/') {
+            Some(i) => prefix = &prefix[..i],
+            None => return false,
+        }
+    }
+}
+
+/// Match `text` against a glob with `*`, `?` (not crossing `/`) and `**`.
+pub fn glob_match(glob: &str, text: &str) -> bool {
+    fn go(g: &[u8], t: &[u8]) -> bool {
+        match g {
+            [] => t.is_empty(),
+            [b'*', b'*', rest @ ..] => {
+                // `**/` may also match nothing at all
+                let rest_slash = rest.strip_prefix(b"/");
+                rest_slash.is_some_and(|r| go(r, t)) || (0..=t.len()).any(|i| go(rest, &t[i..]))
+            }
+            [b'*', rest @ ..] => (0..=t.len())
+                .take_while(|&i| i == 0 || t[i - 1] != b'/')
+                .any(|i| go(rest, &t[i..])),
+            [b'?', rest @ ..] => t.first().is_some_and(|&c| c != b'/') && go(rest, &t[1..]),
+            [c, rest @ ..] => t.first() == Some(c) && go(rest, &t[1..]),
+        }
+    }
+    go(glob.as_bytes(), text.as_bytes())
+}
+
+#[cfg(feature = "config")]
+mod raw {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        #[serde(default)]
+        pub compression: Compression,
+        #[serde(default)]
+        pub dictionaries: Dictionaries,
+        #[serde(default)]
+        pub output: Output,
+        #[serde(default)]
+        pub performance: Performance,
+        #[serde(default, rename = "override")]
+        pub overrides: Vec<Override>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct Compression {
+        #[serde(default)]
+        pub default_binary: bool,
+        #[serde(default)]
+        pub default_semantic: bool,
+        pub std: Option<String>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct Dictionaries {
+        pub resolvers: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct Output {
+        pub dir: Option<String>,
+        #[serde(default)]
+        pub ignore: Vec<String>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct Performance {
+        pub parallel_threads: Option<usize>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Override {
+        pub glob: String,
+        pub binary: Option<bool>,
+        pub semantic: Option<bool>,
+        pub std: Option<String>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.md", "README.md"));
+        assert!(!glob_match("*.md", "docs/README.md"));
+        assert!(glob_match("docs/**/*.md", "docs/README.md"));
+        assert!(glob_match("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(glob_match("**", "a/b"));
+        assert!(glob_match("v?.md", "v1.md"));
+        assert!(!glob_match("v?.md", "v10.md"));
+
+        assert!(path_matches("CHANGELOG.md", "sub/CHANGELOG.md"));
+        assert!(path_matches("drafts", "docs/drafts/x.md"));
+        assert!(path_matches("docs/old", "docs/old/x/y.md"));
+        assert!(!path_matches("docs/old", "other/docs/old/y.md"));
+        assert!(path_matches("/docs/*.md", "docs/x.md"));
+    }
+}
@@ -0,0 +1,340 @@
+// Block-level deduplication for aggregates: paragraphs and fenced code
+// blocks that recur across the bundle are stored once in a shared segment
+// and referenced from each place they occur, ahead of phrase tokenizing.
+//
+// A reference starts with a reserved char that occurs in no document:
+//
+//   <R><n>\n                          block n verbatim
+//   <R><n>:<prefix>:<suffix>:<len>\n  block n with everything between its
+//   <replacement, len bytes>          first `prefix` and last `suffix` bytes
+//                                     replaced (a near-identical block)
+//
+// Every block ends with a newline, so a reference always covers whole
+// lines. The shared segment lists the blocks as `<len>\n<block>`.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+// Shorter blocks cost about as much to reference as to repeat
+const MIN_BLOCK_LEN: usize = 48;
+// A near-identical block shares at least this fraction with its base
+const NEAR_SHARED: f64 = 0.8;
+// Near-identical candidates compared per block
+const MAX_CANDIDATES: usize = 64;
+
+pub(crate) struct Deduped {
+    pub ref_char: char,
+    pub blocks: Vec<String>,
+    /// Each document with its repeated blocks replaced by references.
+    pub files: Vec<String>,
+    /// Bytes each document shrank by.
+    pub savings: Vec<u64>,
+}
+
+impl Deduped {
+    /// What the shared segment stores: `<len>\n<block>` for each block.
+    pub fn shared_segment(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|b| format!("{}\n{b}", b.len()))
+            .collect()
+    }
+
+    /// Document savings less the cost of the shared segment.
+    pub fn net_savings(&self) -> u64 {
+        let saved: u64 = self.savings.iter().sum();
+        saved.saturating_sub(self.shared_segment().len() as u64)
+    }
+}
+
+// Where a candidate block sits: document index and byte range
+struct Occurrence {
+    file: usize,
+    start: usize,
+    end: usize,
+}
+
+enum Reference {
+    Exact(usize),
+    Near {
+        block: usize,
+        prefix: usize,
+        suffix: usize,
+    },
+}
+
+// Deduplicate `files`; `None` when nothing recurs or no reference char is free
+pub(crate) fn dedup(files: &[&str]) -> Option<Deduped> {
+    // The end of the Private Use Area, away from the dynamic tokens at its start
+    let ref_char = ('\u{E000}'..='\u{F8FF}')
+        .rev()
+        .find(|&c| files.iter().all(|f| !f.contains(c)))?;
+
+    let mut occurrences: Vec<Occurrence> = Vec::new();
+    for (file, text) in files.iter().enumerate() {
+        for range in chunks(text) {
+            if range.len() >= MIN_BLOCK_LEN && text[range.clone()].ends_with('\n') {
+                occurrences.push(Occurrence {
+                    file,
+                    start: range.start,
+                    end: range.end,
+                });
+            }
+        }
+    }
+    let text_of = |o: &Occurrence| &files[o.file][o.start..o.end];
+
+    // Exact repeats first, in order of first occurrence
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, o) in occurrences.iter().enumerate() {
+        groups.entry(text_of(o)).or_default().push(i);
+    }
+    let mut blocks: Vec<&str> = Vec::new();
+    let mut refs: Vec<Option<Reference>> = (0..occurrences.len()).map(|_| None).collect();
+    for (i, o) in occurrences.iter().enumerate() {
+        let group = &groups[text_of(o)];
+        if group.len() > 1 && group[0] == i {
+            for &j in group {
+                refs[j] = Some(Reference::Exact(blocks.len()));
+            }
+            blocks.push(text_of(o));
+        }
+    }
+
+    // Then near-identical blocks, against shared blocks or earlier singletons
+    let mut singles: Vec<usize> = Vec::new();
+    for i in 0..occurrences.len() {
+        if refs[i].is_some() {
+            continue;
+        }
+        let text = text_of(&occurrences[i]);
+        let shared = (0..blocks.len()).map(|b| (Some(b), blocks[b]));
+        let earlier = singles
+            .iter()
+            .filter(|&&j| refs[j].is_none())
+            .map(|&j| (None::<usize>, text_of(&occurrences[j])));
+        let best = shared
+            .chain(earlier)
+            .zip(0..)
+            .filter(|((_, base), _)| similar_len(base.len(), text.len()))
+            .take(MAX_CANDIDATES)
+            .filter_map(|((block, base), k)| {
+                let (prefix, suffix) = common_ends(base, text);
+                let shared = (prefix + suffix) as f64;
+                (shared >= NEAR_SHARED * text.len() as f64).then_some((block, k, prefix, suffix))
+            })
+            .max_by_key(|&(_, _, p, s)| p + s);
+        let Some((block, k, prefix, suffix)) = best else {
+            singles.push(i);
+            continue;
+        };
+        let block = match block {
+            Some(block) => block,
+            None => {
+                // Promote the earlier singleton to a shared block
+                let j = singles
+                    .iter()
+                    .filter(|&&j| refs[j].is_none())
+                    .nth(k - blocks.len());
+                let j = *j.expect("candidate came from the singletons");
+                refs[j] = Some(Reference::Exact(blocks.len()));
+                blocks.push(text_of(&occurrences[j]));
+                blocks.len() - 1
+            }
+        };
+        refs[i] = Some(Reference::Near {
+            block,
+            prefix,
+            suffix,
+        });
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+
+    // Rewrite each document, keeping only references that pay for themselves
+    let mut out: Vec<String> = Vec::with_capacity(files.len());
+    let mut savings = vec![0u64; files.len()];
+    let mut cursor = vec![0usize; files.len()];
+    out.resize(files.len(), String::new());
+    for (o, reference) in occurrences.iter().zip(&refs) {
+        let Some(reference) = reference else {
+            continue;
+        };
+        let text = text_of(o);
+        let encoded = match *reference {
+            Reference::Exact(block) => format!("{ref_char}{block}\n"),
+            Reference::Near {
+                block,
+                prefix,
+                suffix,
+            } => {
+                let middle = &text[prefix..text.len() - suffix];
+                format!(
+                    "{ref_char}{block}:{prefix}:{suffix}:{}\n{middle}",
+                    middle.len()
+                )
+            }
+        };
+        if encoded.len() >= text.len() {
+            continue;
+        }
+        let source = files[o.file];
+        out[o.file].push_str(&source[cursor[o.file]..o.start]);
+        out[o.file].push_str(&encoded);
+        cursor[o.file] = o.end;
+        savings[o.file] += (text.len() - encoded.len()) as u64;
+    }
+    for (file, text) in files.iter().enumerate() {
+        out[file].push_str(&text[cursor[file]..]);
+    }
+
+    let deduped = Deduped {
+        ref_char,
+        blocks: blocks.into_iter().map(str::to_string).collect(),
+        files: out,
+        savings,
+    };
+    (deduped.net_savings() > 0).then_some(deduped)
+}
+
+// Expand the references in `text` against `blocks`
+pub(crate) fn resolve(text: &str, ref_char: char, blocks: &[String]) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(ref_char) {
+        out.push_str(&rest[..at]);
+        let after = &rest[at + ref_char.len_utf8()..];
+        let (line, mut next) = after
+            .split_once('\n')
+            .ok_or_else(|| anyhow!("unterminated shared block reference"))?;
+        let fields: Vec<usize> = line
+            .split(':')
+            .map(|f| f.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow!("invalid shared block reference `{line}`"))?;
+        let block = |n: usize| {
+            blocks
+                .get(n)
+                .ok_or_else(|| anyhow!("reference to missing shared block {n}"))
+        };
+        match fields[..] {
+            [n] => out.push_str(block(n)?),
+            [n, prefix, suffix, len] => {
+                let base = block(n)?;
+                let middle = next
+                    .get(..len)
+                    .ok_or_else(|| anyhow!("shared block reference runs past the end"))?;
+                let head = base.get(..prefix);
+                let tail = base.len().checked_sub(suffix).and_then(|at| base.get(at..));
+                let (Some(head), Some(tail)) = (head, tail) else {
+                    bail!("reference `{line}` does not fit shared block {n}");
+                };
+                out.push_str(head);
+                out.push_str(middle);
+                out.push_str(tail);
+                next = &next[len..];
+            }
+            _ => bail!("invalid shared block reference `{line}`"),
+        }
+        rest = next;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// Blocks of a decoded shared segment
+pub(crate) fn read_shared(mut segment: &str) -> Result<Vec<String>> {
+    let mut blocks = Vec::new();
+    while !segment.is_empty() {
+        let (len, rest) = segment
+            .split_once('\n')
+            .ok_or_else(|| anyhow!("truncated shared segment"))?;
+        let len: usize = len
+            .parse()
+            .map_err(|_| anyhow!("invalid shared block length `{len}`"))?;
+        let block = rest
+            .get(..len)
+            .ok_or_else(|| anyhow!("shared block {} runs past the segment", blocks.len()))?;
+        blocks.push(block.to_string());
+        segment = &rest[len..];
+    }
+    Ok(blocks)
+}
+
+// Paragraphs (runs of non-blank lines) and whole fenced code blocks
+pub(crate) fn chunks(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut chunks = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut in_fence = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let fence = line.trim_start().starts_with("```");
+        let end = offset + line.len();
+        if in_fence {
+            if fence {
+                chunks.extend(current.take().map(|s| s..end));
+                in_fence = false;
+            }
+        } else if fence {
+            chunks.extend(current.take().map(|s| s..offset));
+            current = Some(offset);
+            in_fence = true;
+        } else if line.trim().is_empty() {
+            chunks.extend(current.take().map(|s| s..offset));
+        } else {
+            current.get_or_insert(offset);
+        }
+        offset = end;
+    }
+    chunks.extend(current.map(|s| s..offset));
+    chunks
+}
+
+fn similar_len(a: usize, b: usize) -> bool {
+    a.min(b) * 4 >= a.max(b) * 3
+}
+
+// Lengths of the common prefix and (non-overlapping) suffix, on char boundaries
+fn common_ends(a: &str, b: &str) -> (usize, usize) {
+    let mut prefix = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !a.is_char_boundary(prefix) || !b.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = a.len().min(b.len()) - prefix;
+    let mut suffix = a
+        .bytes()
+        .rev()
+        .zip(b.bytes().rev())
+        .take(max_suffix)
+        .take_while(|(x, y)| x == y)
+        .count();
+    while !a.is_char_boundary(a.len() - suffix) || !b.is_char_boundary(b.len() - suffix) {
+        suffix -= 1;
+    }
+    (prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_split_paragraphs_and_fences() {
+        let text = "a\nb\n\n```\nx\n\ny\n```\nc\n\n\nd";
+        let parts: Vec<&str> = chunks(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(parts, ["a\nb\n", "```\nx\n\ny\n```\n", "c\n", "d"]);
+    }
+
+    #[test]
+    fn references_resolve() {
+        let blocks = vec!["The quick brown fox\n".to_string()];
+        let text = "x\n\u{F8FF}0\ny\n\u{F8FF}0:4:10:5\nslow z\n";
+        assert_eq!(
+            resolve(text, '\u{F8FF}', &blocks).unwrap(),
+            "x\nThe quick brown fox\ny\nThe slow brown fox\nz\n"
+        );
+        assert!(resolve("\u{F8FF}1\n", '\u{F8FF}', &blocks).is_err());
+    }
+}
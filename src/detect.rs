@@ -0,0 +1,109 @@
+// Format sniffing for everything `mq` can read, so callers need not know
+// which decoder a file wants.
+
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::semantic::{SemanticEncoder, UniversalRenderer};
+use crate::{decode_mq2, mq2_uni_decode, mq2_uni_encode, uni, DecodeOptions, Marqant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Format {
+    /// `MARQANT <ts> <orig> <comp> [flags]` text
+    Marqant,
+    /// `MQ2~UNI~...` header with a binary `~T` dictionary
+    Mq2,
+    /// Headerless MQ2-UNI text with `~XX` escapes (`mq uni-encode`)
+    Mq2UniBare,
+    /// `SMQ\x01` semantic binary
+    Semantic,
+    /// `MQB\x02` binary (specified, not implemented)
+    Mqb,
+    /// `MEM8` container
+    M8,
+    Unknown,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Marqant => "MARQANT",
+            Format::Mq2 => "MQ2-UNI",
+            Format::Mq2UniBare => "bare MQ2-UNI",
+            Format::Semantic => "SMQ semantic binary",
+            Format::Mqb => "MQB binary",
+            Format::M8 => "MEM|8 container",
+            Format::Unknown => "unknown",
+        })
+    }
+}
+
+/// Identify a compressed file by its magic bytes. Bare MQ2-UNI has none, so
+/// UTF-8 text is taken as MQ2-UNI only if it contains a UNI `~XX` escape and
+/// is exactly what `mq2_uni_encode` makes of its own decoding; markdown that
+/// merely mentions `~TB` keeps a raw `\n\n` or tab and stays [`Format::Unknown`].
+pub fn detect_format(input: &[u8]) -> Format {
+    const MAGIC: [(&[u8], Format); 5] = [
+        (b"MARQANT", Format::Marqant),
+        (b"MQ2~", Format::Mq2),
+        (b"SMQ\x01", Format::Semantic),
+        (b"MQB\x02", Format::Mqb),
+        (b"MEM8", Format::M8),
+    ];
+    if let Some((_, format)) = MAGIC.iter().find(|(magic, _)| input.starts_with(magic)) {
+        return *format;
+    }
+    if std::str::from_utf8(input).is_ok() && uni::has_uni_escapes(input) && is_canonical_uni(input)
+    {
+        return Format::Mq2UniBare;
+    }
+    Format::Unknown
+}
+
+// Decoding and re-encoding gives the input back byte for byte
+fn is_canonical_uni(input: &[u8]) -> bool {
+    mq2_uni_decode(input)
+        .and_then(|text| mq2_uni_encode(&text))
+        .is_ok_and(|again| again == input)
+}
+
+impl Marqant {
+    /// Decompress any supported format, chosen by [`detect_format`].
+    /// SMQ semantic binary is rendered back to English; `MEM8` containers
+    /// yield their Marqant Documents.
+    pub fn decompress_auto(input: &[u8]) -> Result<Vec<u8>> {
+        Self::decompress_auto_with_options(input, &DecodeOptions::default())
+    }
+
+    /// [`Marqant::decompress_auto`], resolving `-std:` dictionaries as
+    /// `options` say.
+    pub fn decompress_auto_with_options(input: &[u8], options: &DecodeOptions) -> Result<Vec<u8>> {
+        match detect_format(input) {
+            Format::Marqant => {
+                let text = std::str::from_utf8(input)
+                    .map_err(|e| anyhow!("MARQANT file is not valid UTF-8: {e}"))?;
+                Ok(Self::decompress_marqant_with_options(text, options)?.into_bytes())
+            }
+            Format::Mq2 => decode_mq2(input),
+            Format::Mq2UniBare => mq2_uni_decode(input),
+            Format::Semantic => {
+                let units = SemanticEncoder::from_bytes(input)?;
+                Ok(UniversalRenderer::to_english(&units).into_bytes())
+            }
+            Format::M8 => Ok(Self::decompress_m8_with_options(input, options)?.into_bytes()),
+            Format::Mqb => {
+                bail!("MQB binary input is recognized but has no decoder in this version")
+            }
+            Format::Unknown => {
+                let head = &input[..input.len().min(16)];
+                bail!(
+                    "unrecognized input (starts with \"{}\"): expected MARQANT, MQ2~, SMQ\\x01, \
+                     MQB\\x02 or MEM8 magic, or MQ2-UNI `~XX` escapes; plain markdown needs no decompression",
+                    head.escape_ascii()
+                )
+            }
+        }
+    }
+}
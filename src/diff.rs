@@ -0,0 +1,375 @@
+// Comparing two compressed documents: the decoded markdown as a unified
+// diff, plus what changed in the header and the inline dictionary. Token
+// ids are reassigned freely between runs, so dictionaries are matched by
+// pattern rather than by id.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use crate::{read_mq_metadata, DecodeOptions, Marqant, MqInfo};
+
+/// Differences between two compressed documents, from [`diff_mq`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MqDiff {
+    pub header: Vec<FieldChange>,
+    pub dictionary: Vec<DictChange>,
+    /// Unified diff of the decoded text; empty when it is unchanged.
+    pub text: String,
+}
+
+impl MqDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.dictionary.is_empty() && self.text.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DictChange {
+    Added {
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        id: Vec<u8>,
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        pattern: Vec<u8>,
+    },
+    Removed {
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        id: Vec<u8>,
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        pattern: Vec<u8>,
+    },
+    /// Same pattern under a different token id.
+    Reassigned {
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        pattern: Vec<u8>,
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        old_id: Vec<u8>,
+        #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+        new_id: Vec<u8>,
+    },
+}
+
+/// Compare two compressed documents. Either side may be empty (a file that
+/// was added or deleted); the labels head the unified diff.
+pub fn diff_mq(old: &[u8], new: &[u8], old_label: &str, new_label: &str) -> Result<MqDiff> {
+    diff_mq_with_options(old, new, old_label, new_label, &DecodeOptions::default())
+}
+
+/// [`diff_mq`], resolving `-std:` dictionaries as `options` say.
+pub fn diff_mq_with_options(
+    old: &[u8],
+    new: &[u8],
+    old_label: &str,
+    new_label: &str,
+    options: &DecodeOptions,
+) -> Result<MqDiff> {
+    let (old_info, old_text) = open(old, options)?;
+    let (new_info, new_text) = open(new, options)?;
+    Ok(MqDiff {
+        header: header_changes(old_info.as_ref(), new_info.as_ref()),
+        dictionary: dict_changes(old_info.as_ref(), new_info.as_ref()),
+        text: unified_diff(&old_text, &new_text, old_label, new_label, 3),
+    })
+}
+
+fn open(input: &[u8], options: &DecodeOptions) -> Result<(Option<MqInfo>, String)> {
+    if input.is_empty() {
+        return Ok((None, String::new()));
+    }
+    let text = Marqant::decompress_auto_with_options(input, options)?;
+    let info = read_mq_metadata(input).ok().filter(|i| i.kind != "UNKNOWN");
+    Ok((info, String::from_utf8_lossy(&text).into_owned()))
+}
+
+fn header_changes(old: Option<&MqInfo>, new: Option<&MqInfo>) -> Vec<FieldChange> {
+    fn fields(info: Option<&MqInfo>) -> [(&'static str, Option<String>); 7] {
+        let num = |v: Option<u64>| v.map(|v| v.to_string());
+        [
+            ("kind", info.map(|i| i.kind.clone())),
+            ("variant", info.and_then(|i| i.variant.clone())),
+            ("timestamp", info.and_then(|i| i.timestamp.clone())),
+            ("original_size", num(info.and_then(|i| i.original_size))),
+            ("compressed_size", num(info.and_then(|i| i.compressed_size))),
+            (
+                "token_count",
+                num(info.and_then(|i| i.token_count.map(u64::from))),
+            ),
+            ("flags", info.and_then(|i| i.level.clone())),
+        ]
+    }
+    fields(old)
+        .into_iter()
+        .zip(fields(new))
+        .filter(|((_, a), (_, b))| a != b)
+        .map(|((field, old), (_, new))| FieldChange {
+            field: field.to_string(),
+            old,
+            new,
+        })
+        .collect()
+}
+
+fn dict_changes(old: Option<&MqInfo>, new: Option<&MqInfo>) -> Vec<DictChange> {
+    let by_pattern = |info: Option<&MqInfo>| -> HashMap<Vec<u8>, Vec<u8>> {
+        info.map_or_else(HashMap::new, |i| {
+            i.tokens
+                .iter()
+                .map(|e| (e.pattern.clone(), e.id.clone()))
+                .collect()
+        })
+    };
+    let (old, new) = (by_pattern(old), by_pattern(new));
+    let mut changes = Vec::new();
+    for (pattern, old_id) in &old {
+        match new.get(pattern) {
+            None => changes.push(DictChange::Removed {
+                id: old_id.clone(),
+                pattern: pattern.clone(),
+            }),
+            Some(new_id) if new_id != old_id => changes.push(DictChange::Reassigned {
+                pattern: pattern.clone(),
+                old_id: old_id.clone(),
+                new_id: new_id.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (pattern, id) in &new {
+        if !old.contains_key(pattern) {
+            changes.push(DictChange::Added {
+                id: id.clone(),
+                pattern: pattern.clone(),
+            });
+        }
+    }
+    // Removals, then additions, then reassignments; by pattern within each
+    changes.sort_by(|a, b| {
+        let key = |c: &DictChange| match c {
+            DictChange::Removed { pattern, .. } => (0, pattern.clone()),
+            DictChange::Added { pattern, .. } => (1, pattern.clone()),
+            DictChange::Reassigned { pattern, .. } => (2, pattern.clone()),
+        };
+        key(a).cmp(&key(b))
+    });
+    changes
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Line-based unified diff (`diff -u` style) with `context` lines around
+/// each hunk. Returns an empty string when the texts are equal.
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_label: &str,
+    new_label: &str,
+    context: usize,
+) -> String {
+    let a: Vec<&str> = old.split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = edit_script(&a, &b);
+    if ops.iter().all(|&op| op == Op::Equal) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    // Positions in `a` and `b` before each op
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for &op in &ops {
+        pos.push((i, j));
+        match op {
+            Op::Equal => (i, j) = (i + 1, j + 1),
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    pos.push((i, j));
+
+    let changed: Vec<usize> = (0..ops.len()).filter(|&k| ops[k] != Op::Equal).collect();
+    let mut k = 0;
+    while k < changed.len() {
+        // Grow the hunk while the next change is within 2 * context lines
+        let start = changed[k].saturating_sub(context);
+        let mut last = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] - last <= 2 * context {
+            k += 1;
+            last = changed[k];
+        }
+        let end = (last + 1 + context).min(ops.len());
+        k += 1;
+
+        let ((a0, b0), (a1, b1)) = (pos[start], pos[end]);
+        let range = |from: usize, len: usize| match len {
+            0 => format!("{from},0"),
+            1 => format!("{}", from + 1),
+            _ => format!("{},{len}", from + 1),
+        };
+        let _ = writeln!(out, "@@ -{} +{} @@", range(a0, a1 - a0), range(b0, b1 - b0));
+        for (&op, &(i, j)) in ops[start..end].iter().zip(&pos[start..end]) {
+            let (sign, line) = match op {
+                Op::Equal => (' ', a[i]),
+                Op::Delete => ('-', a[i]),
+                Op::Insert => ('+', b[j]),
+            };
+            out.push(sign);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+// Shortest edit script between two line lists (Myers, O((N+M)D))
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops = vec![Op::Equal; prefix];
+    ops.extend(myers(a_mid, b_mid));
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    ops
+}
+
+fn myers(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // Step d only reads diagonals -d-1..=d+1 of the previous step, so that
+    // is all the backtrack needs: O(D²) memory instead of O(D·(N+M))
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        let lo = (offset - d - 1) as usize;
+        trace.push(v[lo..=lo + 2 * d as usize + 2].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk the trace back from (n, m)
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + d + 1) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + d + 1) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    ops.reverse();
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unified_diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+        let diff = unified_diff(old, new, "old", "new", 1);
+        assert_eq!(
+            diff,
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+             @@ -10 +10,2 @@\n j\n+k\n\\ No newline at end of file\n"
+        );
+        assert_eq!(unified_diff(old, old, "old", "new", 3), "");
+        assert_eq!(
+            unified_diff("", "x\n", "old", "new", 3),
+            "--- old\n+++ new\n@@ -0,0 +1 @@\n+x\n"
+        );
+    }
+
+    #[test]
+    fn edit_script_is_minimal() {
+        let a: Vec<&str> = "abcabba".split("").filter(|s| !s.is_empty()).collect();
+        let b: Vec<&str> = "cbabac".split("").filter(|s| !s.is_empty()).collect();
+        let ops = myers(&a, &b);
+        assert_eq!(ops.iter().filter(|&&op| op != Op::Equal).count(), 5);
+        let kept = ops.iter().filter(|&&op| op != Op::Insert).count();
+        assert_eq!(kept, a.len());
+
+        // Replaying the script turns `a` into `b`
+        for (a, b) in [
+            ("", ""),
+            ("", "xy"),
+            ("xy", ""),
+            ("abcabba", "cbabac"),
+            ("abc", "xbz"),
+        ] {
+            let a: Vec<&str> = a.split("").filter(|s| !s.is_empty()).collect();
+            let b: Vec<&str> = b.split("").filter(|s| !s.is_empty()).collect();
+            let (mut i, mut j, mut out) = (0, 0, Vec::new());
+            for op in myers(&a, &b) {
+                match op {
+                    Op::Equal => {
+                        out.push(a[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                    Op::Delete => i += 1,
+                    Op::Insert => {
+                        out.push(b[j]);
+                        j += 1;
+                    }
+                }
+            }
+            assert_eq!((i, out), (a.len(), b));
+        }
+    }
+}
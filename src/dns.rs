@@ -33,7 +33,7 @@ pub fn resolve_dns_dict(id: &str) -> Result<Option<HashMap<String, String>>> {
 
     let mut dict = HashMap::new();
     for pair in record.split_whitespace() {
-        let Some((k_b64, v_b64)) = pair.split_once('=') else {
+        let Some((k_b64, v_b64)) = split_b64_pair(pair) else {
             return Err(anyhow!("Invalid DNS dict pair: {}", pair));
         };
         let key_bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, k_b64)?;
@@ -49,3 +49,56 @@ pub fn resolve_dns_dict(id: &str) -> Result<Option<HashMap<String, String>>> {
         Ok(Some(dict))
     }
 }
+
+/// A place `-std:<id>` dictionaries are looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolver {
+    /// Dictionaries built into this crate
+    Local,
+    /// TXT records via [`resolve_dns_dict`]
+    Dns,
+}
+
+impl std::str::FromStr for Resolver {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "local" => Ok(Resolver::Local),
+            "dns" => Ok(Resolver::Dns),
+            other => Err(anyhow!(
+                "unknown resolver `{other}` (expected local or dns)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Resolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Resolver::Local => "local",
+            Resolver::Dns => "dns",
+        })
+    }
+}
+
+/// Lookup order for standard dictionaries: `MARQANT_RESOLVERS` as a comma
+/// separated list (e.g. `dns,local`), else `configured` (such as the project
+/// config's `[dictionaries] resolvers`), defaulting to local then DNS.
+pub fn resolver_order(configured: Option<&[Resolver]>) -> Result<Vec<Resolver>> {
+    if let Ok(list) = std::env::var("MARQANT_RESOLVERS") {
+        return list.split(',').map(str::parse).collect();
+    }
+    Ok(configured.map_or_else(|| vec![Resolver::Local, Resolver::Dns], <[_]>::to_vec))
+}
+
+/// Split `base64(key)=base64(value)` at the separator rather than at the key's
+/// own padding: padded base64 is always a multiple of 4 long, so the separator
+/// is the first `=` at such an offset.
+fn split_b64_pair(pair: &str) -> Option<(&str, &str)> {
+    let sep = pair
+        .char_indices()
+        .find(|&(i, c)| c == '=' && i > 0 && i % 4 == 0)?
+        .0;
+    Some((&pair[..sep], &pair[sep + 1..]))
+}
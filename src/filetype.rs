@@ -0,0 +1,111 @@
+// Static token tables for text that is not markdown, chosen by extension.
+// Markdown's own table (`STATIC_TOKENS`) is always tried first and phrases
+// next; these patterns then pick up what the phrases left, each getting a
+// token char only where it pays for itself. Longer patterns precede the
+// ones they contain.
+
+static RUST: &[&str] = &[
+    "#[derive(",
+    ".unwrap()",
+    "pub(crate) ",
+    "pub fn ",
+    "let mut ",
+    "Result<",
+    "Option<",
+    "String",
+    "return ",
+    "struct ",
+    "match ",
+    "impl ",
+    "&self",
+    "self.",
+    "Some(",
+    "None",
+    "Ok(",
+    "Err(",
+    "Vec<",
+    "&str",
+    "/// ",
+    "use ",
+    "let ",
+    "fn ",
+    " => ",
+    " -> ",
+    "::",
+];
+
+static TOML: &[&str] = &[
+    "[dependencies]",
+    "version = \"",
+    "features = [",
+    "optional = ",
+    "path = \"",
+    "name = \"",
+    "true",
+    "false",
+    "\", \"",
+    " = \"",
+    " = ",
+    "[[",
+    "]]",
+];
+
+static TEX: &[&str] = &[
+    "\\displaystyle",
+    "\\subsection{",
+    "\\section{",
+    "\\mathcal{",
+    "\\mathbf{",
+    "\\textbf{",
+    "\\begin{",
+    "\\label{",
+    "\\frac{",
+    "\\emph{",
+    "\\cite{",
+    "\\item ",
+    "\\left(",
+    "\\right)",
+    "\\end{",
+    "\\ref{",
+    "\\cdot",
+    "\\\\",
+    "$$",
+];
+
+// Patterns for the type of `path`; markdown and unknown types have none
+pub(crate) fn type_patterns(path: &str) -> &'static [&'static str] {
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("rs") => RUST,
+        Some("toml") => TOML,
+        Some("tex" | "sty" | "cls") => TEX,
+        _ => &[],
+    }
+}
+
+// The tables of every type among `paths`, each once, in a fixed order
+pub(crate) fn patterns_for<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<&'static str> {
+    let present: Vec<&'static [&'static str]> = paths.into_iter().map(type_patterns).collect();
+    [RUST, TOML, TEX]
+        .into_iter()
+        .filter(|table| present.iter().any(|p| std::ptr::eq(*p, *table)))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_survive_the_dictionary() {
+        // Dictionary lines escape newlines as `\n`, so no pattern may
+        // contain that sequence literally
+        for table in [RUST, TOML, TEX] {
+            assert!(table.iter().all(|p| !p.contains("\\n")));
+        }
+        assert_eq!(type_patterns("src/lib.rs"), RUST);
+        assert!(type_patterns("README.md").is_empty());
+        assert_eq!(patterns_for(["a.rs", "b.rs"]).len(), RUST.len());
+    }
+}
@@ -0,0 +1,76 @@
+// `mq grep`: search decoded text in memory and print grep-style
+// `file:line:match` rows, with `file-line-context` rows around them.
+
+use std::io::{self, Write};
+
+use regex::Regex;
+
+#[derive(Default)]
+pub struct Options {
+    pub before: usize,
+    pub after: usize,
+    /// `-l`: print only the names of matching files
+    pub files_only: bool,
+    /// `-c`: print a match count per file
+    pub count: bool,
+}
+
+/// Search state carried across files: whether a `--` group separator is
+/// due before the next context group.
+#[derive(Default)]
+pub struct Searcher {
+    printed_group: bool,
+}
+
+impl Searcher {
+    /// Print the matches of `re` in `text`; returns the number of matching lines.
+    pub fn search(
+        &mut self,
+        name: &str,
+        text: &str,
+        re: &Regex,
+        opts: &Options,
+        out: &mut impl Write,
+    ) -> io::Result<usize> {
+        let lines: Vec<&str> = text.lines().collect();
+        let hits: Vec<usize> = (0..lines.len())
+            .filter(|&i| re.is_match(lines[i]))
+            .collect();
+        if opts.files_only {
+            if !hits.is_empty() {
+                writeln!(out, "{name}")?;
+            }
+            return Ok(hits.len());
+        }
+        if opts.count {
+            writeln!(out, "{name}:{}", hits.len())?;
+            return Ok(hits.len());
+        }
+
+        // Windows around each hit, merged where they touch
+        let mut groups: Vec<(usize, usize)> = Vec::new();
+        for &hit in &hits {
+            let (from, to) = (hit.saturating_sub(opts.before), hit + 1 + opts.after);
+            match groups.last_mut() {
+                Some(last) if from <= last.1 => last.1 = to,
+                _ => groups.push((from, to)),
+            }
+        }
+        let context = opts.before > 0 || opts.after > 0;
+        for (from, to) in groups {
+            if context && self.printed_group {
+                writeln!(out, "--")?;
+            }
+            self.printed_group = true;
+            for (i, line) in lines.iter().enumerate().take(to).skip(from) {
+                let sep = if hits.binary_search(&i).is_ok() {
+                    ':'
+                } else {
+                    '-'
+                };
+                writeln!(out, "{name}{sep}{}{sep}{line}", i + 1)?;
+            }
+        }
+        Ok(hits.len())
+    }
+}
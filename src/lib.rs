@@ -16,9 +16,8 @@
 //! ```rust,no_run
 //! use marqant::Marqant;
 //!
-//! let compressor = Marqant::default();
-//! let compressed = compressor.compress("# Hello World\n\nThis is markdown content");
-//! let decompressed = compressor.decompress(&compressed).unwrap();
+//! let compressed = Marqant::compress_markdown("# Hello World\n\nThis is markdown content").unwrap();
+//! let decompressed = Marqant::decompress_marqant(&compressed).unwrap();
 //! ```
 //!
 //! ## Binary Format
@@ -34,16 +33,46 @@ use chrono::Utc;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::io::{Read, Write};
-
+use std::ops::{Range, RangeInclusive};
+
+mod aggregate;
+pub use aggregate::{Aggregate, AggregateEntry, AggregateFile, Packing, SharedBlocks};
+mod analyze;
+pub use analyze::{analyze_markdown, Analysis, Contribution, ModeSize, PhraseSavings, SectionSize};
+mod archive;
+pub use archive::ArchiveFormat;
+#[cfg(feature = "serde")]
+mod bytes_repr;
+mod dedup;
+mod detect;
+pub use detect::{detect_format, Format};
+mod diff;
+mod filetype;
+pub use diff::{diff_mq, diff_mq_with_options, unified_diff, DictChange, FieldChange, MqDiff};
+pub mod config;
 pub mod dns;
+pub mod m8;
 pub mod novelty;
 pub mod semantic;
 
+mod pack;
+pub use pack::{estimate_tokens, PackedFile};
+mod seek;
+pub use seek::{SeekEntry, SEEK_BLOCK_SIZE};
+mod select;
+pub use select::Selection;
+mod summary;
+pub use summary::ProjectIdentity;
 mod uni;
-pub use uni::{mq2_uni_decode, mq2_uni_encode, MQ2_UNI_DICT_ID};
+pub use uni::{
+    mq2_uni_decode, mq2_uni_decode_chunks, mq2_uni_encode, Mq2UniChunks, MQ2_UNI_DICT_ID,
+};
+mod verify;
+pub use verify::{verify_mq, verify_mq_with_options, Check, Diagnostic, Severity, VerifyReport};
 
 #[derive(Debug, Eq)]
 struct PhraseFreq {
@@ -82,31 +111,73 @@ impl Default for Marqant {
     }
 }
 
+/// Options for reading compressed files; the default is what the plain
+/// decode functions use.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Lookup order for `-std:<id>` dictionaries, such as the project
+    /// config's `[dictionaries] resolvers`; `None` is local then DNS.
+    /// `MARQANT_RESOLVERS` wins over either.
+    pub resolvers: Option<Vec<dns::Resolver>>,
+}
+
 const STD_STATIC_V1_ID: &str = "std-static-v1";
 
+// Markdown syntax tokenized in every document; the first
+// `STD_STATIC_V1_LEN` entries make up the `std-static-v1` dictionary
+const STATIC_TOKENS: &[(&str, &str)] = &[
+    ("\x01", "# "),
+    ("\x02", "## "),
+    ("\x03", "### "),
+    ("\x04", "#### "),
+    ("\x05", "```"),
+    ("\x06", "\n\n"),
+    ("\x07", "- "),
+    ("\x0B", "* "),
+    ("\x0C", "**"),
+    ("\x0E", "__"),
+    ("\x0F", "> "),
+    ("\x10", "| "),
+    ("\x11", "---"),
+    ("\x12", "***"),
+    ("\x13", "["),
+    ("\x14", "]("),
+    ("\x15", "```bash"),
+    ("\x16", "```rust"),
+    ("\x17", "```javascript"),
+    ("\x18", "```python"),
+    ("\x19", "\n```\n"),
+    ("\x1A", "    "),
+];
+const STD_STATIC_V1_LEN: usize = 17;
+
+// Upper bound on phrase tokens per document
+const MAX_DYNAMIC_TOKENS: usize = 256;
+
+// Dynamic tokens are drawn from these, in order: the control chars after the
+// static tokens, DEL, then the Private Use Area. Printable ASCII is off limits.
+const DYNAMIC_TOKEN_CHARS: [RangeInclusive<u32>; 3] = [0x1B..=0x1F, 0x7F..=0x7F, 0xE000..=0xF8FF];
+
+fn dynamic_token_chars() -> impl Iterator<Item = char> {
+    DYNAMIC_TOKEN_CHARS
+        .into_iter()
+        .flatten()
+        .filter_map(char::from_u32)
+}
+
+pub(crate) fn is_dynamic_token_char(c: char) -> bool {
+    DYNAMIC_TOKEN_CHARS.iter().any(|r| r.contains(&(c as u32)))
+}
+
+// Any char the tokenizer may hand out, static or dynamic
+pub(crate) fn is_token_char(c: char) -> bool {
+    is_dynamic_token_char(c) || STATIC_TOKENS.iter().any(|(t, _)| t.starts_with(c))
+}
+
 fn get_standard_tokens(id: &str) -> Option<HashMap<String, String>> {
     if id == STD_STATIC_V1_ID {
-        let pairs: [(&str, &str); 17] = [
-            ("\x01", "# "),
-            ("\x02", "## "),
-            ("\x03", "### "),
-            ("\x04", "#### "),
-            ("\x05", "```"),
-            ("\x06", "\n\n"),
-            ("\x07", "- "),
-            ("\x0B", "* "),
-            ("\x0C", "**"),
-            ("\x0E", "__"),
-            ("\x0F", "> "),
-            ("\x10", "| "),
-            ("\x11", "---"),
-            ("\x12", "***"),
-            ("\x13", "["),
-            ("\x14", "]("),
-            ("\x15", "```bash"),
-        ];
         let mut m = HashMap::new();
-        for (t, p) in pairs {
+        for (t, p) in &STATIC_TOKENS[..STD_STATIC_V1_LEN] {
             m.insert(t.to_string(), p.to_string());
         }
         Some(m)
@@ -115,11 +186,67 @@ fn get_standard_tokens(id: &str) -> Option<HashMap<String, String>> {
     }
 }
 
-fn parse_std_flag(flags: Option<&str>) -> Option<String> {
-    let Some(f) = flags else {
-        return None;
+// Standard dictionary `id` from the first resolver that knows it
+fn resolve_std_dict(id: &str, options: &DecodeOptions) -> Result<Option<HashMap<String, String>>> {
+    for resolver in dns::resolver_order(options.resolvers.as_deref())? {
+        let map = match resolver {
+            dns::Resolver::Local => get_standard_tokens(id),
+            dns::Resolver::Dns => dns::resolve_dns_dict(id)?,
+        };
+        if map.is_some() {
+            return Ok(map);
+        }
+    }
+    Ok(None)
+}
+
+// Standard dictionary named by a `-std:<id>` flag, or an empty map
+fn std_tokens(flags: &str, options: &DecodeOptions) -> Result<HashMap<String, String>> {
+    let Some(id) = parse_std_flag(Some(flags)) else {
+        return Ok(HashMap::new());
     };
-    for part in f.split_whitespace() {
+    resolve_std_dict(&id, options)?
+        .ok_or_else(|| anyhow::anyhow!(format!("Unknown or unresolvable standard dict id: {}", id)))
+}
+
+// `token=pattern` lines, sorted for determinism, omitting entries the
+// `std_id` dictionary already supplies; then the `---` separator
+fn write_dictionary(output: &mut String, tokens: &HashMap<String, String>, std_id: Option<&str>) {
+    let std_map = std_id.and_then(get_standard_tokens);
+    let mut token_vec: Vec<(&String, &String)> = tokens.iter().collect();
+    token_vec.sort_by(|a, b| a.0.cmp(b.0));
+    for (token, pattern) in token_vec {
+        if let Some(ref sm) = std_map {
+            if sm.get(token).is_some_and(|p| p == pattern) {
+                continue;
+            }
+        }
+        let escaped_pattern = pattern.replace('\n', "\\n");
+        output.push_str(&format!("{}={}\n", token, escaped_pattern));
+    }
+    output.push_str("---\n");
+}
+
+// Dictionary lines up to `---` into `tokens`; returns the body after it, or
+// all of `input` if there is no separator (as older readers did)
+fn read_dictionary<'a>(input: &'a str, tokens: &mut HashMap<String, String>) -> &'a str {
+    let mut rest = input;
+    while !rest.is_empty() {
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        rest = next;
+        if line == "---" {
+            return rest;
+        }
+        if let Some((token, pattern)) = line.split_once('=') {
+            tokens.insert(token.to_string(), pattern.replace("\\n", "\n"));
+        }
+    }
+    input
+}
+
+fn parse_std_flag(flags: Option<&str>) -> Option<String> {
+    for part in flags?.split_whitespace() {
         if let Some(rest) = part.strip_prefix("-std:") {
             return Some(rest.to_string());
         }
@@ -146,7 +273,14 @@ impl Marqant {
         let (tokens, tokenized_content) = Self::tokenize_content(&processed_content);
 
         let use_zlib = flags.is_some_and(|f| f.contains("-zlib"));
-        let final_content = if use_zlib {
+        let block_size = flags.and_then(seek::block_size);
+        let final_content = if let Some(block_size) = block_size {
+            let blocks = seek::split_blocks(&tokenized_content, &[], block_size);
+            let table = TokenTable::new(&tokens);
+            seek::write_blocks(&tokenized_content, &blocks, use_zlib, |text| {
+                decoded_len(&table, use_sections, text)
+            })?
+        } else if use_zlib {
             let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
             encoder.write_all(tokenized_content.as_bytes())?;
             let compressed = encoder.finish()?;
@@ -172,23 +306,7 @@ impl Marqant {
             ));
         }
 
-        // Write token dictionary (sorted for determinism), possibly omitting standard entries
-        let std_id = parse_std_flag(flags);
-        let std_map = std_id.as_deref().and_then(get_standard_tokens);
-
-        let mut token_vec: Vec<(&String, &String)> = tokens.iter().collect();
-        token_vec.sort_by(|a, b| a.0.cmp(b.0));
-        for (token, pattern) in token_vec {
-            // If part of standard map and matches exactly, omit from on-wire dictionary
-            if let Some(ref sm) = std_map {
-                if sm.get(token).is_some_and(|p| p == pattern) {
-                    continue;
-                }
-            }
-            let escaped_pattern = pattern.replace('\n', "\\n");
-            output.push_str(&format!("{}={}\n", token, escaped_pattern));
-        }
-        output.push_str("---\n");
+        write_dictionary(&mut output, &tokens, parse_std_flag(flags).as_deref());
 
         output.push_str(&final_content);
 
@@ -222,39 +340,50 @@ impl Marqant {
     }
 
     pub fn tokenize_content(content: &str) -> (HashMap<String, String>, String) {
-        let mut tokens = HashMap::new();
-        let mut tokenized = content.to_string();
+        let (tokens, tokenized) = Self::tokenize_ordered(content);
+        (tokens.into_iter().collect(), tokenized)
+    }
+
+    // `tokenize_content` with the entries in the order they were applied;
+    // `apply_tokens` with the same list reproduces the substitution
+    pub(crate) fn tokenize_ordered(content: &str) -> (Vec<(String, String)>, String) {
+        Self::extend_tokens(content, Vec::new(), &HashSet::new(), &[])
+    }
 
-        let static_tokens: Vec<(&str, &str)> = vec![
-            ("\x01", "# "),
-            ("\x02", "## "),
-            ("\x03", "### "),
-            ("\x04", "#### "),
-            ("\x05", "```"),
-            ("\x06", "\n\n"),
-            ("\x07", "- "),
-            ("\x0B", "* "),
-            ("\x0C", "**"),
-            ("\x0E", "__"),
-            ("\x0F", "> "),
-            ("\x10", "| "),
-            ("\x11", "---"),
-            ("\x12", "***"),
-            ("\x13", "["),
-            ("\x14", "]("),
-            ("\x15", "```bash"),
-            ("\x16", "```rust"),
-            ("\x17", "```javascript"),
-            ("\x18", "```python"),
-            ("\x19", "\n```\n"),
-            ("\x1A", "    "),
-        ];
-
-        for (token, pattern) in static_tokens {
+    // `tokenize_ordered` on top of an existing dictionary: `tokens` are
+    // applied first, and new entries only added where they pay for
+    // themselves in `content`, with chars other than `tokens` and `reserved`.
+    // `patterns` (typical of the content's file types) are tried after the
+    // phrases, on what they left.
+    pub(crate) fn extend_tokens(
+        content: &str,
+        mut tokens: Vec<(String, String)>,
+        reserved: &HashSet<char>,
+        patterns: &[&str],
+    ) -> (Vec<(String, String)>, String) {
+        let mut tokenized = Self::apply_tokens(content, &tokens);
+        let taken = |c: char| {
+            content.contains(c)
+                || reserved.contains(&c)
+                || tokens.iter().any(|(t, _)| t.starts_with(c))
+        };
+        let taken: HashSet<char> = STATIC_TOKENS
+            .iter()
+            .flat_map(|(t, _)| t.chars())
+            .chain(dynamic_token_chars())
+            .filter(|&c| taken(c))
+            .collect();
+
+        for &(token, pattern) in STATIC_TOKENS {
+            // A token char already in the text would be expanded on decode
+            if token.chars().any(|c| taken.contains(&c)) || tokens.iter().any(|(_, p)| p == pattern)
+            {
+                continue;
+            }
             if tokenized.contains(pattern) {
                 let count = tokenized.matches(pattern).count();
                 if count * pattern.len() > count + pattern.len() + 3 {
-                    tokens.insert(token.to_string(), pattern.to_string());
+                    tokens.push((token.to_string(), pattern.to_string()));
                     tokenized = tokenized.replace(pattern, token);
                 }
             }
@@ -283,17 +412,17 @@ impl Marqant {
             }
         }
 
-        let mut token_counter = 0x1Bu8;
-        let mut assigned_phrases: Vec<String> = Vec::new();
+        // Dynamic tokens must never occur in the text
+        let mut free_tokens = dynamic_token_chars()
+            .filter(|c| !taken.contains(c))
+            .take(MAX_DYNAMIC_TOKENS.saturating_sub(tokens.len()))
+            .peekable();
+        let mut assigned_phrases: Vec<String> = tokens.iter().map(|(_, p)| p.clone()).collect();
 
         while let Some(phrase_freq) = phrase_heap.pop() {
-            if token_counter >= 0x7F {
+            let Some(&token_char) = free_tokens.peek() else {
                 break;
-            }
-            if token_counter == 0x0A || token_counter == 0x0D {
-                token_counter += 1;
-                continue;
-            }
+            };
 
             let mut overlaps = false;
             for assigned in &assigned_phrases {
@@ -302,113 +431,438 @@ impl Marqant {
                     break;
                 }
             }
+            // Dictionary lines only escape newlines, so a literal `\n` in a
+            // pattern would not survive the trip
+            if overlaps || phrase_freq.phrase.contains('\\') {
+                continue;
+            }
 
-            if !overlaps && tokenized.contains(&phrase_freq.phrase) {
-                let token = char::from(token_counter).to_string();
-                tokens.insert(token.clone(), phrase_freq.phrase.clone());
-                tokenized = tokenized.replace(&phrase_freq.phrase, &token);
-                assigned_phrases.push(phrase_freq.phrase);
-                token_counter += 1;
+            // Net savings with the real token width (PUA chars are 3 bytes)
+            let count = tokenized.matches(&phrase_freq.phrase).count();
+            let token_len = token_char.len_utf8();
+            let phrase_len = phrase_freq.phrase.len();
+            if count * phrase_len <= count * token_len + token_len + phrase_len + 2 {
+                continue;
             }
+
+            let token = token_char.to_string();
+            free_tokens.next();
+            tokens.push((token.clone(), phrase_freq.phrase.clone()));
+            tokenized = tokenized.replace(&phrase_freq.phrase, &token);
+            assigned_phrases.push(phrase_freq.phrase);
+        }
+
+        for &pattern in patterns {
+            let Some(&token_char) = free_tokens.peek() else {
+                break;
+            };
+            if tokens.iter().any(|(_, p)| p == pattern) {
+                continue;
+            }
+            let count = tokenized.matches(pattern).count();
+            let token_len = token_char.len_utf8();
+            if count * pattern.len() <= count * token_len + token_len + pattern.len() + 2 {
+                continue;
+            }
+            free_tokens.next();
+            tokens.push((token_char.to_string(), pattern.to_string()));
+            tokenized = tokenized.replace(pattern, &token_char.to_string());
         }
 
         (tokens, tokenized)
     }
 
+    // Substitute each pattern in turn. Patterns never contain token chars, so
+    // any text that holds none of the tokens decodes back exactly
+    pub(crate) fn apply_tokens(content: &str, tokens: &[(String, String)]) -> String {
+        let mut tokenized = content.to_string();
+        for (token, pattern) in tokens {
+            tokenized = tokenized.replace(pattern.as_str(), token);
+        }
+        tokenized
+    }
+
     pub fn decompress_marqant(compressed: &str) -> Result<String> {
-        let lines: Vec<&str> = compressed.lines().collect();
-        if lines.is_empty() || !lines[0].starts_with("MARQANT") {
-            return Err(anyhow::anyhow!("Invalid marqant format"));
+        Self::decompress_marqant_with_options(compressed, &DecodeOptions::default())
+    }
+
+    /// [`Marqant::decompress_marqant`], resolving `-std:` dictionaries as
+    /// `options` say.
+    pub fn decompress_marqant_with_options(
+        compressed: &str,
+        options: &DecodeOptions,
+    ) -> Result<String> {
+        let mut out = Vec::with_capacity(compressed.len() * 2);
+        let has_sections = Self::decode_visit(compressed, options, |chunk| {
+            out.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        let mut decompressed = String::from_utf8(out)?;
+        if has_sections {
+            decompressed.truncate(decompressed.trim_end().len());
         }
+        Ok(decompressed)
+    }
+
+    /// Streaming decompression: `visit` receives the document in order as
+    /// borrowed slices (literal runs of the body and dictionary patterns)
+    /// without the whole document being built. `-semantic` section markers
+    /// are dropped as in [`Marqant::decompress_marqant`]; only `-zlib` bodies
+    /// are inflated, in fixed-size blocks. Returns whether `-semantic` was set.
+    pub fn decompress_marqant_with(
+        compressed: &str,
+        visit: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<bool> {
+        Self::decode_visit(compressed, &DecodeOptions::default(), visit)
+    }
 
-        let header_parts: Vec<&str> = lines[0].split_whitespace().collect();
-        if header_parts.len() < 4 {
-            return Err(anyhow::anyhow!("Invalid marqant header"));
+    fn decode_visit(
+        compressed: &str,
+        options: &DecodeOptions,
+        mut visit: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<bool> {
+        let header = compressed.lines().next().unwrap_or("");
+        if !header.starts_with("MARQANT") {
+            return Err(anyhow::anyhow!("Invalid marqant format"));
+        }
+        if header.starts_with(aggregate::AGGREGATE_MAGIC) {
+            // Each document behind its `::file:` marker, in manifest order
+            visit(
+                Aggregate::parse_with_options(compressed, options)?
+                    .render()?
+                    .as_bytes(),
+            )?;
+            return Ok(false);
         }
 
-        let flags_joined = if header_parts.len() > 4 {
-            header_parts[4..].join(" ")
-        } else {
-            String::new()
-        };
-        let has_zlib = flags_joined.split_whitespace().any(|f| f == "-zlib");
-        let has_sections = flags_joined.split_whitespace().any(|f| f == "-semantic");
-        let std_id = flags_joined
-            .split_whitespace()
-            .find_map(|f| f.strip_prefix("-std:"))
-            .map(|s| s.to_string());
-
-        let mut tokens = HashMap::new();
-        // Preload standard tokens if requested
-        if let Some(id) = std_id {
-            // First try local...
-            if let Some(map) = get_standard_tokens(&id) {
-                tokens.extend(map);
-            } else {
-                // ...then fall back to DNS
-                let dns_map = dns::resolve_dns_dict(&id)?;
-                if let Some(map) = dns_map {
-                    tokens.extend(map);
+        let (flags, tokens, body) = split_container(compressed, Some(options))?;
+        let has_flag = |flag: &str| flags.split_whitespace().any(|f| f == flag);
+        let (has_zlib, has_sections) = (has_flag("-zlib"), has_flag("-semantic"));
+
+        let table = TokenTable::new(&tokens);
+        let mut filter = SectionFilter::new(has_sections);
+        let mut emit = |chunk: &[u8]| filter.push(chunk, &mut visit);
+
+        if seek::block_size(&flags).is_some() {
+            let (blocks, entries) = seek::read_table(body)?;
+            for entry in &entries {
+                table.detokenize(&seek::block_text(blocks, entry, has_zlib)?, &mut emit)?;
+            }
+        } else if has_zlib {
+            let decoded = base64::Engine::decode(
+                &base64::engine::general_purpose::STANDARD,
+                body.split_whitespace().collect::<String>(),
+            )?;
+            let mut decoder = ZlibDecoder::new(&decoded[..]);
+            // Inflate in blocks; an incomplete char (or token prefix) at the
+            // end of a block is carried into the next one
+            let mut buf = vec![0u8; 16 * 1024];
+            let mut filled = 0;
+            loop {
+                if filled == buf.len() {
+                    buf.resize(buf.len() * 2, 0);
+                }
+                let n = decoder.read(&mut buf[filled..])?;
+                filled += n;
+                let text = match std::str::from_utf8(&buf[..filled]) {
+                    Ok(text) => text,
+                    Err(e) if e.error_len().is_none() && n > 0 => {
+                        std::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or_default()
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let done = if n == 0 {
+                    table.detokenize(text, &mut emit)?;
+                    text.len()
                 } else {
-                    return Err(anyhow::anyhow!(format!(
-                        "Unknown or unresolvable standard dict id: {}",
-                        id
-                    )));
+                    table.detokenize_prefix(text, &mut emit)?
+                };
+                buf.copy_within(done..filled, 0);
+                filled -= done;
+                if n == 0 {
+                    break;
                 }
             }
+        } else {
+            table.detokenize(body, &mut emit)?;
         }
+        filter.finish(&mut visit)?;
 
-        let mut content_start = 1;
-        for (i, line) in lines.iter().enumerate().skip(1) {
-            if *line == "---" {
-                content_start = i + 1;
-                break;
-            }
-            if let Some((token, pattern)) = line.split_once('=') {
-                let unescaped_pattern = pattern.replace("\\n", "\n");
-                tokens.insert(token.to_string(), unescaped_pattern);
+        Ok(has_sections)
+    }
+
+    /// Bytes `range` of the decoded document. With a seek table
+    /// (`-seekable`) only the blocks overlapping `range` are inflated and
+    /// decoded; otherwise the whole body is. Aggregates are refused: their
+    /// body is not one document, so use [`Aggregate::extract`] instead.
+    pub fn decode_range(compressed: &str, range: Range<usize>) -> Result<Vec<u8>> {
+        let (flags, tokens, body) = split_container(compressed, Some(&DecodeOptions::default()))?;
+        let has_flag = |flag: &str| flags.split_whitespace().any(|f| f == flag);
+        if has_flag("-aggregate") {
+            return Err(anyhow::anyhow!(
+                "decode_range does not apply to aggregates; use Aggregate::extract"
+            ));
+        }
+        let (has_zlib, has_sections) = (has_flag("-zlib"), has_flag("-semantic"));
+        let table = TokenTable::new(&tokens);
+        let mut out = Vec::new();
+        let mut keep = |decoded: &[u8], start: usize| {
+            let from = range.start.clamp(start, start + decoded.len()) - start;
+            let to = range.end.clamp(start, start + decoded.len()) - start;
+            out.extend_from_slice(&decoded[from..to.max(from)]);
+        };
+        if seek::block_size(&flags).is_some() {
+            let (blocks, entries) = seek::read_table(body)?;
+            for entry in seek::overlapping(&entries, &range, SeekEntry::original) {
+                let text = seek::block_text(blocks, entry, has_zlib)?;
+                keep(
+                    &decode_block(&table, has_sections, &text)?,
+                    entry.original_start,
+                );
             }
+        } else {
+            let text = if has_zlib {
+                Cow::Owned(seek::inflate(body)?)
+            } else {
+                Cow::Borrowed(body)
+            };
+            keep(&decode_block(&table, has_sections, &text)?, 0);
         }
+        Ok(out)
+    }
 
-        let compressed_content = lines[content_start..].join("\n");
+    /// The seek table of a `-seekable` file, or `None` for other files.
+    pub fn seek_table(compressed: &str) -> Result<Option<Vec<SeekEntry>>> {
+        // Where the body starts does not depend on the standard dictionary
+        let (flags, _, body) = split_container(compressed, None)?;
+        if seek::block_size(&flags).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(seek::read_table(body)?.1))
+    }
+}
 
-        let tokenized_content = if has_zlib {
-            let decoded = base64::Engine::decode(
-                &base64::engine::general_purpose::STANDARD,
-                &compressed_content,
-            )?;
-            let mut decoder = ZlibDecoder::new(&decoded[..]);
-            let mut decompressed_bytes = String::new();
-            decoder.read_to_string(&mut decompressed_bytes)?;
-            decompressed_bytes
-        } else {
-            compressed_content
+// Header flags, dictionary (standard entries included, unless `options` is
+// `None`) and stored body of a MARQANT file; an aggregate's manifest is skipped
+fn split_container<'a>(
+    compressed: &'a str,
+    options: Option<&DecodeOptions>,
+) -> Result<(String, HashMap<String, String>, &'a str)> {
+    let (header, rest) = compressed.split_once('\n').unwrap_or((compressed, ""));
+    let header_parts: Vec<&str> = header.split_whitespace().collect();
+    if !header.starts_with("MARQANT") || header_parts.len() < 4 {
+        return Err(anyhow::anyhow!("Invalid marqant header"));
+    }
+    let flags = header_parts[4..].join(" ");
+    let mut tokens = match options {
+        Some(options) => std_tokens(&flags, options)?,
+        None => HashMap::new(),
+    };
+    let rest = match rest.strip_prefix("::manifest::\n") {
+        Some(manifest) => manifest
+            .split_once("::end-manifest::\n")
+            .map(|(_, rest)| rest)
+            .ok_or_else(|| anyhow::anyhow!("missing ::end-manifest::"))?,
+        None => rest,
+    };
+    // Dictionary lines run from after the header to the `---` separator;
+    // the body is everything after it, byte for byte
+    let body = read_dictionary(rest, &mut tokens);
+    Ok((flags, tokens, body))
+}
+
+// One block (or a whole body) of tokenized text, decoded
+fn decode_block(table: &TokenTable, sections: bool, text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 2);
+    let mut filter = SectionFilter::new(sections);
+    let mut visit = |chunk: &[u8]| {
+        out.extend_from_slice(chunk);
+        Ok(())
+    };
+    table.detokenize(text, &mut |chunk| filter.push(chunk, &mut visit))?;
+    filter.finish(&mut visit)?;
+    Ok(out)
+}
+
+fn decoded_len(table: &TokenTable, sections: bool, text: &str) -> Result<usize> {
+    Ok(decode_block(table, sections, text)?.len())
+}
+
+// Token lookup for single-pass detokenizing. Patterns never contain tokens,
+// so each token is expanded exactly once, longest token first.
+struct TokenTable<'a> {
+    map: HashMap<&'a str, &'a str>,
+    max_chars: usize,
+}
+
+impl<'a> TokenTable<'a> {
+    fn new(tokens: &'a HashMap<String, String>) -> Self {
+        let map: HashMap<&str, &str> = tokens
+            .iter()
+            .filter(|(t, _)| !t.is_empty())
+            .map(|(t, p)| (t.as_str(), p.as_str()))
+            .collect();
+        let max_chars = map.keys().map(|t| t.chars().count()).max().unwrap_or(0);
+        Self { map, max_chars }
+    }
+
+    // Token starting at byte `i`, as (token byte length, pattern). Candidate
+    // ends are probed longest first, stepping back one char at a time.
+    fn match_at(&self, text: &str, i: usize) -> Option<(usize, &'a str)> {
+        let rest = &text[i..];
+        let end = rest
+            .char_indices()
+            .nth(self.max_chars)
+            .map_or(rest.len(), |(j, _)| j);
+        let window = &rest[..end];
+        std::iter::once(end)
+            .chain(window.char_indices().rev().map(|(j, _)| j))
+            .filter(|&len| len > 0)
+            .find_map(|len| self.map.get(&window[..len]).map(|p| (len, *p)))
+    }
+
+    fn detokenize(&self, text: &str, emit: &mut impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        let done = self.detokenize_upto(text, text.len(), emit)?;
+        if done < text.len() {
+            emit(&text.as_bytes()[done..])?;
+        }
+        Ok(())
+    }
+
+    // Like `detokenize`, but stops short of the last `max_chars - 1` chars, which
+    // may begin a token continued in the next block. Returns bytes consumed.
+    fn detokenize_prefix(
+        &self,
+        text: &str,
+        emit: &mut impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<usize> {
+        let limit = match self.max_chars.saturating_sub(1) {
+            0 => text.len(),
+            hold => text
+                .char_indices()
+                .rev()
+                .nth(hold - 1)
+                .map_or(0, |(j, _)| j),
         };
+        let done = self.detokenize_upto(text, limit, emit)?;
+        if done < limit {
+            emit(&text.as_bytes()[done..limit])?;
+        }
+        // A token starting before `limit` may end past it
+        Ok(done.max(limit))
+    }
 
-        let mut token_list: Vec<(String, String)> = tokens.into_iter().collect();
-        token_list.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+    // Emits literal runs and patterns for tokens starting before `limit`;
+    // returns where the trailing literal run begins (past `limit` if the
+    // last token ends beyond it)
+    fn detokenize_upto(
+        &self,
+        text: &str,
+        limit: usize,
+        emit: &mut impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<usize> {
+        let mut literal = 0;
+        let mut i = 0;
+        while i < limit {
+            if let Some((len, pattern)) = self.match_at(text, i) {
+                if literal < i {
+                    emit(&text.as_bytes()[literal..i])?;
+                }
+                emit(pattern.as_bytes())?;
+                i += len;
+                literal = i;
+            } else {
+                i += text[i..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        Ok(literal)
+    }
+}
+
+// Drops `::section:...::` marker lines from a chunk stream. Only lines that
+// could still be markers are buffered; everything else passes straight through.
+struct SectionFilter {
+    enabled: bool,
+    at_line_start: bool,
+    pending: Option<Vec<u8>>,
+}
+
+const SECTION_PREFIX: &[u8] = b"::section:";
 
-        let mut decompressed = tokenized_content;
-        for (token, pattern) in token_list {
-            decompressed = decompressed.replace(&token, &pattern);
+impl SectionFilter {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            at_line_start: true,
+            pending: None,
         }
+    }
 
-        if has_sections {
-            let lines: Vec<&str> = decompressed.lines().collect();
-            let mut result = String::new();
-            for line in lines {
-                if !line.starts_with("::section:") || !line.ends_with("::") {
-                    result.push_str(line);
-                    result.push('\n');
+    fn push(
+        &mut self,
+        mut chunk: &[u8],
+        visit: &mut impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        if !self.enabled {
+            return visit(chunk);
+        }
+        while !chunk.is_empty() {
+            if let Some(mut line) = self.pending.take() {
+                match chunk.iter().position(|&b| b == b'\n') {
+                    Some(p) => {
+                        line.extend_from_slice(&chunk[..=p]);
+                        chunk = &chunk[p + 1..];
+                        if !is_section_marker(&line) {
+                            visit(&line)?;
+                        }
+                        self.at_line_start = true;
+                    }
+                    None => {
+                        line.extend_from_slice(chunk);
+                        chunk = &[];
+                        let n = line.len().min(SECTION_PREFIX.len());
+                        if line[..n] == SECTION_PREFIX[..n] {
+                            self.pending = Some(line);
+                        } else {
+                            visit(&line)?;
+                            self.at_line_start = false;
+                        }
+                    }
+                }
+            } else if self.at_line_start && chunk[0] == b':' {
+                self.pending = Some(Vec::new());
+            } else {
+                match chunk.iter().position(|&b| b == b'\n') {
+                    Some(p) => {
+                        visit(&chunk[..=p])?;
+                        chunk = &chunk[p + 1..];
+                        self.at_line_start = true;
+                    }
+                    None => {
+                        visit(chunk)?;
+                        self.at_line_start = false;
+                        chunk = &[];
+                    }
                 }
             }
-            decompressed = result.trim_end().to_string();
         }
+        Ok(())
+    }
 
-        Ok(decompressed)
+    fn finish(&mut self, visit: &mut impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        match self.pending.take() {
+            Some(line) if !is_section_marker(&line) => visit(&line),
+            _ => Ok(()),
+        }
     }
 }
 
+fn is_section_marker(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    line.starts_with(SECTION_PREFIX) && line.ends_with(b"::")
+}
+
 fn now_timestamp() -> String {
     if let Ok(v) = std::env::var("MARQANT_TEST_TS") {
         v
@@ -547,11 +1001,11 @@ fn escape_xml(s: &str) -> String {
         .replace('>', "&gt;")
 }
 
-fn fnv1a64(s: &str) -> u64 {
+fn fnv1a64(bytes: impl AsRef<[u8]>) -> u64 {
     const FNV_OFFSET: u64 = 0xcbf29ce484222325;
     const FNV_PRIME: u64 = 0x00000100000001B3;
     let mut h = FNV_OFFSET;
-    for b in s.as_bytes() {
+    for b in bytes.as_ref() {
         h ^= *b as u64;
         h = h.wrapping_mul(FNV_PRIME);
     }
@@ -563,6 +1017,7 @@ fn fnv1a64(s: &str) -> u64 {
 // --------------------
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MqInfo {
     pub kind: String,            // "MQ2" | "MARQANT_V1" | "UNKNOWN"
     pub variant: Option<String>, // e.g., "UNI", "mq"/"mqb" level, or None
@@ -571,28 +1026,70 @@ pub struct MqInfo {
     pub compressed_size: Option<u64>,
     pub token_count: Option<u32>,
     pub level: Option<String>,
-    pub dict_t: Option<String>,  // raw ~T payload
-    pub dict_s: Option<String>,  // raw ~S payload
+    #[cfg_attr(feature = "serde", serde(with = "bytes_repr::option"))]
+    pub dict_t: Option<Vec<u8>>, // raw ~T payload
+    #[cfg_attr(feature = "serde", serde(with = "bytes_repr::option"))]
+    pub dict_s: Option<Vec<u8>>, // raw ~S payload
     pub dict_id: Option<String>, // fnv1a64(~T||~S) hex
+    /// Inline dictionary: MQ2 `~T` entries or MARQANT `token=pattern` lines
+    pub tokens: Vec<TokenEntry>,
 }
 
-pub fn read_mq_metadata(input: &str) -> anyhow::Result<MqInfo> {
-    let mut lines = input.lines();
-    let first = lines.next().unwrap_or("");
-    // Collect header-adjacent lines until separator
-    let mut t_line: Option<String> = None;
-    let mut s_line: Option<String> = None;
-    let mut _sep = None;
-    for line in lines.by_ref() {
-        if line == "~~~~" || line == "---" {
-            _sep = Some(line.to_string());
-            break;
+/// One dictionary entry: a one-byte MQ2 token id or a MARQANT token string,
+/// and the bytes it expands to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenEntry {
+    #[cfg_attr(feature = "serde", serde(with = "bytes_repr"))]
+    pub id: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "bytes_repr"))]
+    pub pattern: Vec<u8>,
+}
+
+pub fn read_mq_metadata(input: impl AsRef<[u8]>) -> anyhow::Result<MqInfo> {
+    let input = input.as_ref();
+    let (first, mut rest) = split_line(input);
+    let first = String::from_utf8_lossy(first);
+    let first = first.as_ref();
+
+    // Sections between the header and the `~~~~` / `---` separator
+    let mut t_section: Option<Vec<u8>> = None;
+    let mut s_section: Option<Vec<u8>> = None;
+    let mut tokens = Vec::new();
+    let mut in_manifest = false;
+    while !rest.is_empty() {
+        // ~T is binary (tok, u16 BE length, bytes) and may contain newlines
+        if let Some((entries, len)) = rest.strip_prefix(b"~T").and_then(parse_t_entries) {
+            t_section = Some(rest[2..2 + len].to_vec());
+            tokens = entries;
+            rest = rest[2 + len..]
+                .strip_prefix(b"\n")
+                .unwrap_or(&rest[2 + len..]);
+            continue;
+        }
+        let (line, next) = split_line(rest);
+        rest = next;
+        // Aggregate manifests precede the dictionary
+        if in_manifest || line == b"::manifest::" {
+            in_manifest = line != b"::end-manifest::";
+            continue;
         }
-        if let Some(rest) = line.strip_prefix("~T") {
-            t_line = Some(rest.to_string());
+        if line == b"~~~~" || line == b"---" {
+            break;
         }
-        if let Some(rest) = line.strip_prefix("~S") {
-            s_line = Some(rest.to_string());
+        if let Some(t) = line.strip_prefix(b"~T") {
+            // Not a well-formed binary section; keep the raw line
+            t_section = Some(t.to_vec());
+        } else if let Some(s) = line.strip_prefix(b"~S") {
+            s_section = Some(s.to_vec());
+        } else if first.starts_with("MARQANT") {
+            if let Some(eq) = line.iter().position(|&b| b == b'=') {
+                let pattern = String::from_utf8_lossy(&line[eq + 1..]).replace("\\n", "\n");
+                tokens.push(TokenEntry {
+                    id: line[..eq].to_vec(),
+                    pattern: pattern.into_bytes(),
+                });
+            }
         }
     }
 
@@ -626,28 +1123,29 @@ pub fn read_mq_metadata(input: &str) -> anyhow::Result<MqInfo> {
         let ts = parts.get(1).map(|s| s.to_string());
         let orig = parts.get(2).and_then(|s| s.parse::<u64>().ok());
         let comp = parts.get(3).and_then(|s| s.parse::<u64>().ok());
+        let aggregate = parts[0] == aggregate::AGGREGATE_MAGIC && parts.contains(&"-aggregate");
         (
             "MARQANT".to_string(),
-            None,
+            aggregate.then(|| "aggregate".to_string()),
             ts,
             orig,
             comp,
             None,
-            parts.get(4).map(|s| s.to_string()),
+            (parts.len() > 4).then(|| parts[4..].join(" ")),
         )
     } else {
         ("UNKNOWN".to_string(), None, None, None, None, None, None)
     };
 
     let mut dict_id = None;
-    if t_line.is_some() || s_line.is_some() {
-        let mut concat = String::new();
-        if let Some(t) = &t_line {
-            concat.push_str(t);
+    if t_section.is_some() || s_section.is_some() {
+        let mut concat = Vec::new();
+        if let Some(t) = &t_section {
+            concat.extend_from_slice(t);
         }
-        if let Some(s) = &s_line {
-            concat.push('|');
-            concat.push_str(s);
+        if let Some(s) = &s_section {
+            concat.push(b'|');
+            concat.extend_from_slice(s);
         }
         let h = fnv1a64(&concat);
         dict_id = Some(format!("fnv1a64:{:016x}", h));
@@ -661,8 +1159,58 @@ pub fn read_mq_metadata(input: &str) -> anyhow::Result<MqInfo> {
         compressed_size: comp,
         token_count: tokc,
         level,
-        dict_t: t_line,
-        dict_s: s_line,
+        dict_t: t_section,
+        dict_s: s_section,
         dict_id,
+        tokens,
     })
 }
+
+// Line up to `\n` (without a trailing `\r`) and the bytes after it
+fn split_line(input: &[u8]) -> (&[u8], &[u8]) {
+    let (line, rest) = match input.iter().position(|&b| b == b'\n') {
+        Some(nl) => (&input[..nl], &input[nl + 1..]),
+        None => (input, &[][..]),
+    };
+    (line.strip_suffix(b"\r").unwrap_or(line), rest)
+}
+
+// Binary ~T entries up to the `\n~~~~` or `\n~S` that ends the section, with
+// the section length. None if an entry runs past the input.
+fn parse_t_entries(section: &[u8]) -> Option<(Vec<TokenEntry>, usize)> {
+    let mut entries = Vec::new();
+    let mut i = 0;
+    loop {
+        let rest = &section[i..];
+        if rest.starts_with(b"\n~~~~") || rest.starts_with(b"\n~S") {
+            return Some((entries, i));
+        }
+        let (&id, len) = rest.split_first().filter(|(_, r)| r.len() >= 2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let pattern = rest.get(3..3 + len)?;
+        entries.push(TokenEntry {
+            id: vec![id],
+            pattern: pattern.to_vec(),
+        });
+        i += 3 + len;
+    }
+}
+
+/// Decode an `MQ2~UNI~` payload with its inline binary `~T` dictionary,
+/// through the reference decoder in the `mq2-uni` crate.
+pub fn decode_mq2(input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (header, _) = split_line(input);
+    let fields: Vec<&[u8]> = header.split(|&b| b == b'~').collect();
+    if fields.len() != 7 || fields[0] != b"MQ2" || fields[1] != b"UNI" {
+        return Err(anyhow::anyhow!("Invalid MQ2-UNI header"));
+    }
+    if !matches!(fields[6], b"esc" | b"text") {
+        return Err(anyhow::anyhow!(
+            "Unknown MQ2-UNI format: {}",
+            String::from_utf8_lossy(fields[6])
+        ));
+    }
+    let mut out = vec![0; mq2_uni::decoded_len(input)?];
+    mq2_uni::decode_into(input, &mut out)?;
+    Ok(out)
+}
@@ -0,0 +1,409 @@
+//! `.m8` containers (`example-md/M8_UNIFIED_FORMAT.md`): typed sections
+//! behind a 16-byte `MEM8` header and a section table. Integers are
+//! little-endian.
+//!
+//! ```text
+//! [0-3]    magic `MEM8`
+//! [4]      container version (1)
+//! [5]      reserved (0)
+//! [6-7]    section count: u16
+//! [8-11]   timestamp: u32, Unix seconds
+//! [12-15]  CRC-32 of everything after the header
+//! [16-]    section table, 9 bytes per section:
+//!            type: u8, offset from the start of the file: u32, length: u32
+//!          section data, in table order
+//! ```
+//!
+//! A Marqant Document section ([`MARQANT_DOCUMENT`], `0x09`) starts with the
+//! `BinaryMarqant` fields of `example-md/MEM8_MARQANT_INTEGRATION.md`; its
+//! type and length live in the table. This deviates from the spec, whose
+//! version 1 follows the fields with `token_count` `TokenEntry { id: u8,
+//! len: u8, bytes }` records and the tokenized content: one-byte ids and
+//! patterns can't hold MARQANT's multi-byte token chars, `-std:`
+//! dictionaries, seek tables or aggregate manifests. The payload here is the
+//! `.mq` document itself, so every Marqant flag survives, and the version
+//! byte is [`MARQANT_TEXT_VERSION`] (2) so that spec readers don't take it
+//! for token entries. Version 1 sections are refused.
+//!
+//! ```text
+//! [0]      Marqant version: u8 (2, `.mq` payload)
+//! [1]      flags: u8, bit 0 sections, bit 1 semantic tags, bit 2 zlib,
+//!          bit 3 delta encoded
+//! [2-3]    token count: u16
+//! [4-7]    original markdown size: u32
+//! [8-11]   compressed size (of the `.mq` payload): u32
+//! [12-15]  timestamp: u32
+//! [16-]    the `.mq` document
+//! ```
+//!
+//! Other sections are stored as given.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{read_mq_metadata, DecodeOptions, Marqant, Selection};
+
+pub const MAGIC: &[u8; 4] = b"MEM8";
+pub const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+const TABLE_ENTRY_LEN: usize = 9;
+const MARQANT_HEADER_LEN: usize = 16;
+/// `BinaryMarqant` version of a Marqant Document whose payload is `.mq`
+/// text rather than the spec's token entries and content.
+pub const MARQANT_TEXT_VERSION: u8 = 2;
+
+pub const IDENTITY: u8 = 0x01;
+pub const MARQANT_DOCUMENT: u8 = 0x09;
+pub const QUANTUM_TREE: u8 = 0x0A;
+pub const WAVE_MEMORY_BLOB: u8 = 0x0F;
+
+/// Marqant Document flag: the document carries `::section:` markers.
+pub const FLAG_SECTIONS: u8 = 1 << 0;
+/// Marqant Document flag: semantic tags; `-semantic` sets it along with
+/// [`FLAG_SECTIONS`], its section markers being the tags.
+pub const FLAG_SEMANTIC: u8 = 1 << 1;
+pub const FLAG_ZLIB: u8 = 1 << 2;
+/// Marqant Document flag: delta encoded (read, never written).
+pub const FLAG_DELTA: u8 = 1 << 3;
+
+/// Name of a section type from the unified format's table.
+pub fn section_name(kind: u8) -> Option<&'static str> {
+    Some(match kind {
+        0x01 => "Identity",
+        0x02 => "Context",
+        0x03 => "Structure",
+        0x04 => "Compilation",
+        0x05 => "Cache",
+        0x06 => "AI Context",
+        0x07 => "Relationships",
+        0x08 => "Sensor Arbitration",
+        0x09 => "Marqant Document",
+        0x0A => "Quantum Tree",
+        0x0B => "Code Relations",
+        0x0C => "Build Artifacts",
+        0x0D => "Temporal Index",
+        0x0E => "Collective Emotion",
+        0x0F => "WaveMemoryBlob",
+        0x10 => "ReactiveStateDump",
+        0x11 => "CustodianNotes",
+        _ => return None,
+    })
+}
+
+/// A `MEM8` container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct M8 {
+    pub timestamp: u32,
+    pub sections: Vec<M8Section>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct M8Section {
+    pub kind: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::bytes_repr"))]
+    pub data: Vec<u8>,
+}
+
+/// The fixed fields in front of a Marqant Document section's payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarqantHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub token_count: u16,
+    pub original_size: u32,
+    pub compressed_size: u32,
+    pub timestamp: u32,
+}
+
+/// A decoded Marqant Document section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarqantSection {
+    pub header: MarqantHeader,
+    /// The `.mq` document.
+    pub mq: String,
+}
+
+/// One row of [`M8::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectionInfo {
+    pub kind: u8,
+    pub name: Option<String>,
+    pub len: usize,
+    /// Set for Marqant Document sections.
+    pub marqant: Option<MarqantHeader>,
+}
+
+impl Default for M8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl M8 {
+    /// An empty container stamped with the current time.
+    pub fn new() -> Self {
+        Self {
+            timestamp: unix_now(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Parse a container, checking its CRC and section table.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            bail!("not a MEM8 container");
+        }
+        if bytes[4] != VERSION {
+            bail!("unsupported MEM8 version {}", bytes[4]);
+        }
+        let count = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+        let timestamp = u32_at(bytes, 8);
+        let crc = crc32(&bytes[HEADER_LEN..]);
+        if crc != u32_at(bytes, 12) {
+            bail!(
+                "MEM8 checksum mismatch (stored {:08x}, computed {crc:08x})",
+                u32_at(bytes, 12)
+            );
+        }
+        let table_end = HEADER_LEN + count * TABLE_ENTRY_LEN;
+        if bytes.len() < table_end {
+            bail!("MEM8 section table is truncated");
+        }
+        let mut sections = Vec::with_capacity(count);
+        for i in 0..count {
+            let at = HEADER_LEN + i * TABLE_ENTRY_LEN;
+            let (offset, len) = (
+                u32_at(bytes, at + 1) as usize,
+                u32_at(bytes, at + 5) as usize,
+            );
+            if offset < table_end || offset.checked_add(len).is_none_or(|end| end > bytes.len()) {
+                bail!("MEM8 section {i} lies outside the container");
+            }
+            sections.push(M8Section {
+                kind: bytes[at],
+                data: bytes[offset..offset + len].to_vec(),
+            });
+        }
+        Ok(Self {
+            timestamp,
+            sections,
+        })
+    }
+
+    /// The container's bytes: header, table and sections back to back.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let count = u16::try_from(self.sections.len())
+            .map_err(|_| anyhow!("a MEM8 container holds at most {} sections", u16::MAX))?;
+        let mut table = Vec::with_capacity(self.sections.len() * TABLE_ENTRY_LEN);
+        let mut offset = HEADER_LEN + self.sections.len() * TABLE_ENTRY_LEN;
+        for s in &self.sections {
+            let too_big = || anyhow!("MEM8 containers are limited to 4 GiB");
+            table.push(s.kind);
+            table.extend(u32::try_from(offset).map_err(|_| too_big())?.to_le_bytes());
+            table.extend(
+                u32::try_from(s.data.len())
+                    .map_err(|_| too_big())?
+                    .to_le_bytes(),
+            );
+            offset += s.data.len();
+        }
+        let mut body = table;
+        for s in &self.sections {
+            body.extend_from_slice(&s.data);
+        }
+        let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+        out.extend_from_slice(MAGIC);
+        out.extend([VERSION, 0]);
+        out.extend(count.to_le_bytes());
+        out.extend(self.timestamp.to_le_bytes());
+        out.extend(crc32(&body).to_le_bytes());
+        out.extend(body);
+        Ok(out)
+    }
+
+    pub fn push(&mut self, kind: u8, data: Vec<u8>) {
+        self.sections.push(M8Section { kind, data });
+    }
+
+    /// Compress `markdown` with `flags` and add it as a Marqant Document.
+    pub fn push_markdown(&mut self, markdown: &str, flags: Option<&str>) -> Result<()> {
+        self.push_marqant(&Marqant::compress_markdown_with_flags(markdown, flags)?)
+    }
+
+    /// Add an already compressed `.mq` document (or aggregate).
+    pub fn push_marqant(&mut self, mq: &str) -> Result<()> {
+        let data = MarqantSection::from_mq(mq)?.to_bytes();
+        self.push(MARQANT_DOCUMENT, data);
+        Ok(())
+    }
+
+    /// The Marqant Document sections, in order.
+    pub fn documents(&self) -> Result<Vec<MarqantSection>> {
+        self.sections
+            .iter()
+            .filter(|s| s.kind == MARQANT_DOCUMENT)
+            .map(|s| MarqantSection::parse(&s.data))
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<SectionInfo> {
+        self.sections
+            .iter()
+            .map(|s| SectionInfo {
+                kind: s.kind,
+                name: section_name(s.kind).map(str::to_string),
+                len: s.data.len(),
+                marqant: (s.kind == MARQANT_DOCUMENT)
+                    .then(|| MarqantSection::parse(&s.data).ok().map(|d| d.header))
+                    .flatten(),
+            })
+            .collect()
+    }
+}
+
+impl MarqantSection {
+    /// Wrap a `.mq` document, taking the header fields from it.
+    pub fn from_mq(mq: &str) -> Result<Self> {
+        let info = read_mq_metadata(mq)?;
+        if info.kind != "MARQANT" {
+            bail!("a Marqant Document section holds MARQANT text");
+        }
+        let flags = info.level.unwrap_or_default();
+        let has = |flag: &str| flags.split_whitespace().any(|f| f == flag);
+        let mut bits = 0;
+        if has("-semantic") {
+            bits |= FLAG_SECTIONS | FLAG_SEMANTIC;
+        }
+        if has("-zlib") {
+            bits |= FLAG_ZLIB;
+        }
+        let clamp = |n: u64| u32::try_from(n).unwrap_or(u32::MAX);
+        Ok(Self {
+            header: MarqantHeader {
+                version: MARQANT_TEXT_VERSION,
+                flags: bits,
+                token_count: u16::try_from(info.tokens.len()).unwrap_or(u16::MAX),
+                original_size: clamp(info.original_size.unwrap_or(0)),
+                compressed_size: clamp(mq.len() as u64),
+                timestamp: info
+                    .timestamp
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or_else(unix_now),
+            },
+            mq: mq.to_string(),
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < MARQANT_HEADER_LEN {
+            bail!("Marqant Document section is truncated");
+        }
+        let header = MarqantHeader {
+            version: data[0],
+            flags: data[1],
+            token_count: u16::from_le_bytes([data[2], data[3]]),
+            original_size: u32_at(data, 4),
+            compressed_size: u32_at(data, 8),
+            timestamp: u32_at(data, 12),
+        };
+        match header.version {
+            MARQANT_TEXT_VERSION => {}
+            1 => bail!("Marqant Document version 1 (token entries) is not supported, only `.mq` payloads (version 2)"),
+            v => bail!("unsupported Marqant Document version {v}"),
+        }
+        let mq = std::str::from_utf8(&data[MARQANT_HEADER_LEN..])
+            .context("Marqant Document payload is not UTF-8")?;
+        Ok(Self {
+            header,
+            mq: mq.to_string(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let h = &self.header;
+        let mut out = Vec::with_capacity(MARQANT_HEADER_LEN + self.mq.len());
+        out.extend([h.version, h.flags]);
+        out.extend(h.token_count.to_le_bytes());
+        out.extend(h.original_size.to_le_bytes());
+        out.extend(h.compressed_size.to_le_bytes());
+        out.extend(h.timestamp.to_le_bytes());
+        out.extend_from_slice(self.mq.as_bytes());
+        out
+    }
+
+    /// The markdown, decompressed.
+    pub fn decompress(&self) -> Result<String> {
+        self.decompress_with_options(&DecodeOptions::default())
+    }
+
+    /// [`MarqantSection::decompress`], resolving `-std:` dictionaries as
+    /// `options` say.
+    pub fn decompress_with_options(&self, options: &DecodeOptions) -> Result<String> {
+        if self.header.flags & FLAG_DELTA != 0 {
+            bail!("delta encoded Marqant Documents are not supported");
+        }
+        Marqant::decompress_marqant_with_options(&self.mq, options)
+    }
+}
+
+/// A directory snapshot for a Quantum Tree section: one line per entry,
+/// sorted, `path/` for directories and `path<TAB>size` for files, with
+/// `/`-separated paths relative to `dir`. Hidden entries and whatever
+/// `.gitignore` / `.ignore` files rule out are left out.
+pub fn tree_snapshot(dir: &Path) -> Result<String> {
+    let mut entries = Selection::default().entries(dir)?;
+    // Depth first, siblings by name
+    entries.sort_by(|a, b| a.1.split('/').cmp(b.1.split('/')));
+    let mut out = String::new();
+    for (path, rel) in entries {
+        if path.is_dir() {
+            out.push_str(&format!("{rel}/\n"));
+        } else {
+            out.push_str(&format!("{rel}\t{}\n", fs::metadata(&path)?.len()));
+        }
+    }
+    Ok(out)
+}
+
+impl Marqant {
+    /// Every Marqant Document in a `MEM8` container, decompressed and
+    /// concatenated in section order.
+    pub fn decompress_m8(bytes: &[u8]) -> Result<String> {
+        Self::decompress_m8_with_options(bytes, &DecodeOptions::default())
+    }
+
+    /// [`Marqant::decompress_m8`], resolving `-std:` dictionaries as
+    /// `options` say.
+    pub fn decompress_m8_with_options(bytes: &[u8], options: &DecodeOptions) -> Result<String> {
+        let documents = M8::parse(bytes)?.documents()?;
+        if documents.is_empty() {
+            bail!("MEM8 container holds no Marqant Document");
+        }
+        let mut out = String::new();
+        for d in documents {
+            out.push_str(&d.decompress_with_options(options)?);
+        }
+        Ok(out)
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+fn unix_now() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| u32::try_from(d.as_secs()).unwrap_or(u32::MAX))
+}
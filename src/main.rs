@@ -1,8 +1,11 @@
+mod batch;
 mod cli;
+mod grep;
+mod watch;
 
 fn main() {
     if let Err(err) = cli::run_cli() {
-        eprintln!("error: {}", err);
+        eprintln!("error: {err:#}");
         std::process::exit(1);
     }
 }
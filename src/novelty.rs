@@ -148,6 +148,7 @@ impl NoveltyTracker {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoveltyScore {
     pub value: f32,        // 0.0 to 1.0
     pub is_novel: bool,    // First time seen?
@@ -157,6 +158,7 @@ pub struct NoveltyScore {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NoveltyClass {
     Revolutionary,   // Never seen before (1.0)
     Fresh,           // Still new and exciting (0.8-1.0)
@@ -210,23 +212,23 @@ mod tests {
         };
 
         // First time - should be revolutionary
-        let score1 = tracker.calculate_novelty(&[unit.clone()]);
+        let score1 = tracker.calculate_novelty(std::slice::from_ref(&unit));
         assert_eq!(score1.classification, NoveltyClass::Revolutionary);
         assert_eq!(score1.value, 1.0);
         assert!(score1.is_novel);
 
         // Second time - should decay
-        let score2 = tracker.calculate_novelty(&[unit.clone()]);
+        let score2 = tracker.calculate_novelty(std::slice::from_ref(&unit));
         assert!(!score2.is_novel);
         assert!(score2.value < 1.0);
         assert_eq!(score2.occurrence, 2);
 
         // Many times - should become stale
         for _ in 0..100 {
-            tracker.calculate_novelty(&[unit.clone()]);
+            tracker.calculate_novelty(std::slice::from_ref(&unit));
         }
 
-        let score_final = tracker.calculate_novelty(&[unit.clone()]);
+        let score_final = tracker.calculate_novelty(std::slice::from_ref(&unit));
         assert!(score_final.value < 0.2);
         assert!(score_final.occurrence > 100);
     }
@@ -0,0 +1,154 @@
+// Packing a context window: documents are ranked by query relevance and
+// novelty, then taken greedily while their estimated tokens fit the budget;
+// the aggregate's manifest records the budget and what was left out.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::dedup;
+use crate::novelty::NoveltyTracker;
+use crate::semantic::SemanticEncoder;
+use crate::{AggregateFile, Marqant, Packing};
+
+/// How one candidate document ranked in [`Marqant::pack`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PackedFile {
+    pub path: String,
+    /// Estimated LLM tokens of the document compressed on its own.
+    pub tokens: usize,
+    /// Query term matches (log-scaled; 0 without a query).
+    pub relevance: f32,
+    /// Mean novelty of the document's paragraphs, 0.0 to 1.0.
+    pub novelty: f32,
+    pub score: f32,
+    pub packed: bool,
+}
+
+/// Rough LLM token count of `text`: about four bytes per token for ASCII,
+/// one token per other char (dictionary tokens, emoji, accents).
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.bytes().filter(u8::is_ascii).count();
+    ascii.div_ceil(4) + text.chars().filter(|c| !c.is_ascii()).count()
+}
+
+impl Marqant {
+    /// Bundle the best ranked of `files` that fit within `budget`
+    /// estimated LLM tokens into one aggregate, with `flags` as for
+    /// [`Marqant::aggregate_documents`]. Returns the bundle and every
+    /// candidate's ranking, best first.
+    pub fn pack(
+        files: &[AggregateFile],
+        budget: usize,
+        query: Option<&str>,
+        flags: Option<&str>,
+    ) -> Result<(String, Vec<PackedFile>)> {
+        let terms: Vec<String> = query
+            .unwrap_or("")
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|t| t.chars().count() > 1)
+            .map(str::to_lowercase)
+            .collect();
+        let mut ranked = Vec::with_capacity(files.len());
+        for (i, file) in files.iter().enumerate() {
+            let compressed = Self::compress_markdown_with_flags(&file.content, flags)?;
+            ranked.push((i, estimate_tokens(&compressed), relevance(file, &terms)));
+        }
+        // Novelty is judged in relevance order: what a better match already
+        // said counts as seen
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+        let mut tracker = NoveltyTracker::new();
+        let mut seen = HashSet::new();
+        let mut ranking: Vec<PackedFile> = ranked
+            .into_iter()
+            .map(|(i, tokens, relevance)| {
+                let novelty = novelty(&mut tracker, &mut seen, &files[i].content);
+                PackedFile {
+                    path: files[i].path.clone(),
+                    tokens,
+                    relevance,
+                    novelty,
+                    score: (1.0 + relevance) * novelty,
+                    packed: false,
+                }
+            })
+            .collect();
+        ranking.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.tokens.cmp(&b.tokens))
+                .then(a.path.cmp(&b.path))
+        });
+
+        let mut left = budget;
+        for file in &mut ranking {
+            if file.tokens <= left {
+                left -= file.tokens;
+                file.packed = true;
+            }
+        }
+        // Bundle overhead can push past the estimate: drop the lowest ranked
+        // packed document and rebuild until it fits
+        loop {
+            let packing = Packing {
+                budget,
+                dropped: ranking
+                    .iter()
+                    .filter(|f| !f.packed)
+                    .map(|f| (f.path.clone(), f.tokens))
+                    .collect(),
+            };
+            // Keep the input order in the bundle
+            let chosen: Vec<AggregateFile> = files
+                .iter()
+                .filter(|f| ranking.iter().any(|r| r.packed && r.path == f.path))
+                .cloned()
+                .collect();
+            let bundle = Self::build_aggregate(&chosen, flags, Some(&packing))?;
+            let last = ranking.iter_mut().rev().find(|f| f.packed);
+            match last {
+                Some(last) if estimate_tokens(&bundle) > budget => last.packed = false,
+                _ => return Ok((bundle, ranking)),
+            }
+        }
+    }
+}
+
+// Log-scaled query term counts, with a bonus for terms in the path
+fn relevance(file: &AggregateFile, terms: &[String]) -> f32 {
+    let content = file.content.to_lowercase();
+    let path = file.path.to_lowercase();
+    terms
+        .iter()
+        .map(|t| {
+            let hits = content.matches(t.as_str()).count() as f32;
+            hits.ln_1p() + if path.contains(t.as_str()) { 2.0 } else { 0.0 }
+        })
+        .fold(0.0, |sum, r| sum + r)
+}
+
+// Mean novelty of the document's paragraphs: a paragraph a better ranked
+// document already holds (up to whitespace) adds nothing, one the semantic
+// encoder recognizes scores as the tracker says, any other is new
+fn novelty(tracker: &mut NoveltyTracker, seen: &mut HashSet<String>, content: &str) -> f32 {
+    let scores: Vec<f32> = dedup::chunks(content)
+        .into_iter()
+        .map(|range| {
+            let paragraph = &content[range];
+            let normalized = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !seen.insert(normalized) {
+                return 0.0;
+            }
+            let units = SemanticEncoder::encode(paragraph);
+            if units.iter().all(|u| u.tokens.is_empty()) {
+                return 1.0;
+            }
+            tracker.calculate_novelty(&units).value
+        })
+        .collect();
+    if scores.is_empty() {
+        return 1.0;
+    }
+    scores.iter().sum::<f32>() / scores.len() as f32
+}
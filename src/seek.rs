@@ -0,0 +1,266 @@
+// Seek tables (`-seekable`): the body is stored as blocks that decode on
+// their own against the shared dictionary, indexed by a table at the end
+// of the container so a range of the document (or one file of an
+// aggregate) can be decoded without touching the blocks before it.
+//
+//   <block><block>...
+//   ::seek::
+//   <stored_start>:<stored_len>:<tokenized_start>:<tokenized_len>:<original_start>:<original_len>
+//   ::end-seek:<table bytes>::
+//
+// Stored offsets index the body after `---`; under `-zlib` every block is
+// deflated separately and base64-encoded on its own line. Tokenized
+// offsets index the body before deflating (the coordinates of an
+// aggregate manifest), original offsets the decoded document. Blocks end
+// at line boundaries, and aggregates start a block at every file.
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+/// Tokenized bytes per block before a block is closed at the next line
+/// end, unless the flag names another size (`-seekable:<bytes>`).
+pub const SEEK_BLOCK_SIZE: usize = 64 * 1024;
+
+const TABLE_START: &str = "::seek::\n";
+const TABLE_END: &str = "::end-seek:";
+
+// Block size requested by a `-seekable` or `-seekable:<bytes>` flag in `flags`
+pub(crate) fn block_size(flags: &str) -> Option<usize> {
+    flags
+        .split_whitespace()
+        .find_map(|f| match f.strip_prefix("-seekable")? {
+            "" => Some(SEEK_BLOCK_SIZE),
+            size => size.strip_prefix(':')?.parse().ok().filter(|&n| n > 0),
+        })
+}
+
+/// One independently decodable block of a `-seekable` body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeekEntry {
+    pub stored_start: usize,
+    pub stored_len: usize,
+    pub tokenized_start: usize,
+    pub tokenized_len: usize,
+    pub original_start: usize,
+    pub original_len: usize,
+}
+
+// Entries read from a file never overflow (`read_table` rejects those); the
+// accessors saturate for hand-built ones instead of panicking
+impl SeekEntry {
+    pub fn tokenized(&self) -> Range<usize> {
+        self.tokenized_start..self.tokenized_start.saturating_add(self.tokenized_len)
+    }
+
+    pub fn original(&self) -> Range<usize> {
+        self.original_start..self.original_start.saturating_add(self.original_len)
+    }
+}
+
+// Block ranges over `tokenized`: closed at the first line end past
+// `block_size`, and always at each offset in `cuts`
+pub(crate) fn split_blocks(
+    tokenized: &str,
+    cuts: &[usize],
+    block_size: usize,
+) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut cuts = cuts.iter().copied().filter(|&c| c > 0).peekable();
+    for (i, _) in tokenized.match_indices('\n') {
+        while let Some(cut) = cuts.next_if(|&c| c <= i) {
+            if cut > start {
+                blocks.push(start..cut);
+                start = cut;
+            }
+        }
+        if i + 1 - start >= block_size {
+            blocks.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+    for cut in cuts.chain([tokenized.len()]) {
+        if cut > start {
+            blocks.push(start..cut);
+            start = cut;
+        }
+    }
+    blocks
+}
+
+// The stored body: every block (deflated under `zlib`), then the table.
+// `decoded_len` measures what a block decodes to.
+pub(crate) fn write_blocks(
+    tokenized: &str,
+    blocks: &[Range<usize>],
+    zlib: bool,
+    decoded_len: impl FnMut(&str) -> Result<usize>,
+) -> Result<String> {
+    extend_blocks("", &[], tokenized, blocks, zlib, decoded_len)
+}
+
+// `write_blocks` after the blocks already `stored` and indexed by `index`:
+// `tokenized` (split into `blocks`) continues the body where they end
+pub(crate) fn extend_blocks(
+    stored: &str,
+    index: &[SeekEntry],
+    tokenized: &str,
+    blocks: &[Range<usize>],
+    zlib: bool,
+    mut decoded_len: impl FnMut(&str) -> Result<usize>,
+) -> Result<String> {
+    let mut body = stored.to_string();
+    let mut table = String::from(TABLE_START);
+    for e in index {
+        table.push_str(&format!(
+            "{}:{}:{}:{}:{}:{}\n",
+            e.stored_start,
+            e.stored_len,
+            e.tokenized_start,
+            e.tokenized_len,
+            e.original_start,
+            e.original_len
+        ));
+    }
+    let tokenized_base = index.last().map_or(0, |e| e.tokenized().end);
+    let mut original_start = index.last().map_or(0, |e| e.original().end);
+    for block in blocks {
+        let text = &tokenized[block.clone()];
+        let stored_start = body.len();
+        if zlib {
+            body.push_str(&deflate(text)?);
+            body.push('\n');
+        } else {
+            body.push_str(text);
+        }
+        let original_len = decoded_len(text)?;
+        table.push_str(&format!(
+            "{stored_start}:{}:{}:{}:{original_start}:{original_len}\n",
+            body.len() - stored_start,
+            tokenized_base + block.start,
+            block.len()
+        ));
+        original_start += original_len;
+    }
+    body.push_str(&format!("{table}{TABLE_END}{}::\n", table.len()));
+    Ok(body)
+}
+
+// Split a `-seekable` body into its blocks and seek table
+pub(crate) fn read_table(body: &str) -> Result<(&str, Vec<SeekEntry>)> {
+    let trimmed = body.strip_suffix('\n').unwrap_or(body);
+    let footer_at = trimmed
+        .rfind(TABLE_END)
+        .ok_or_else(|| anyhow!("-seekable body has no seek table"))?;
+    let table_len: usize = trimmed[footer_at + TABLE_END.len()..]
+        .strip_suffix("::")
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| anyhow!("invalid seek table footer"))?;
+    let table_at = footer_at
+        .checked_sub(table_len)
+        .filter(|&at| body.get(at..).is_some_and(|t| t.starts_with(TABLE_START)))
+        .ok_or_else(|| anyhow!("seek table footer does not point at `::seek::`"))?;
+    let blocks = &body[..table_at];
+    let mut entries = Vec::new();
+    for line in body[table_at + TABLE_START.len()..footer_at].lines() {
+        let fields: Vec<usize> = line
+            .split(':')
+            .map(|f| f.parse())
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("invalid seek table line `{line}`"))?;
+        let [stored_start, stored_len, tokenized_start, tokenized_len, original_start, original_len] =
+            fields[..]
+        else {
+            bail!("invalid seek table line `{line}`");
+        };
+        if tokenized_start.checked_add(tokenized_len).is_none()
+            || original_start.checked_add(original_len).is_none()
+        {
+            bail!("seek table line `{line}` overflows");
+        }
+        if stored_start
+            .checked_add(stored_len)
+            .and_then(|end| blocks.get(stored_start..end))
+            .is_none()
+        {
+            bail!("seek table block at {stored_start} lies outside the body");
+        }
+        entries.push(SeekEntry {
+            stored_start,
+            stored_len,
+            tokenized_start,
+            tokenized_len,
+            original_start,
+            original_len,
+        });
+    }
+    Ok((blocks, entries))
+}
+
+// The tokenized text of one block
+pub(crate) fn block_text<'a>(
+    blocks: &'a str,
+    entry: &SeekEntry,
+    zlib: bool,
+) -> Result<Cow<'a, str>> {
+    let stored = &blocks[entry.stored_start..entry.stored_start + entry.stored_len];
+    if !zlib {
+        return Ok(Cow::Borrowed(stored));
+    }
+    let compressed = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        stored.trim_end(),
+    )?;
+    let mut text = String::new();
+    ZlibDecoder::new(&compressed[..]).read_to_string(&mut text)?;
+    if text.len() != entry.tokenized_len {
+        bail!(
+            "block at {} inflates to {} bytes, seek table says {}",
+            entry.stored_start,
+            text.len(),
+            entry.tokenized_len
+        );
+    }
+    Ok(Cow::Owned(text))
+}
+
+// `text` as one deflate stream, base64-encoded
+pub(crate) fn deflate(text: &str) -> Result<String> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(text.as_bytes())?;
+    Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        encoder.finish()?,
+    ))
+}
+
+// A whole `-zlib` body: base64, possibly wrapped, of one deflate stream
+pub(crate) fn inflate(body: &str) -> Result<String> {
+    let compressed = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        body.split_whitespace().collect::<String>(),
+    )?;
+    let mut out = String::new();
+    ZlibDecoder::new(&compressed[..]).read_to_string(&mut out)?;
+    Ok(out)
+}
+
+// Entries whose ranges (as picked by `range_of`) overlap `want`
+pub(crate) fn overlapping<'a>(
+    entries: &'a [SeekEntry],
+    want: &Range<usize>,
+    range_of: impl Fn(&SeekEntry) -> Range<usize> + 'a,
+) -> impl Iterator<Item = &'a SeekEntry> + 'a {
+    let want = want.clone();
+    entries.iter().filter(move |e| {
+        let r = range_of(e);
+        r.start < want.end && want.start < r.end
+    })
+}
@@ -0,0 +1,259 @@
+// Which files a directory walk for an aggregate picks up: markdown by
+// default, or whatever the include globs name, minus hidden entries,
+// `.gitignore` / `.ignore` rules, exclude globs and oversized files.
+//
+// Ignore files are read in every walked directory and in its ancestors up
+// to the repository root (the nearest directory holding `.git`), with the
+// usual gitignore syntax: `#` comments, `!` re-includes, a trailing `/` for
+// directories only, and patterns containing `/` anchored to the directory
+// of their ignore file. The last matching rule wins; an ignored directory
+// is not entered. Include and exclude globs follow `.marqant.toml` globs
+// and are relative to the walked directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config::{glob_match, path_matches};
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// File selection for directory walks; the default takes every `.md` file
+/// that no ignore file rules out.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Globs a file must match, in place of the `.md` default.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Files larger than this many bytes are left out.
+    pub max_size: Option<u64>,
+    /// Disregard `.gitignore` and `.ignore` files.
+    pub no_ignore: bool,
+}
+
+struct Rule {
+    // Directory of the ignore file, relative to the repository root
+    base: String,
+    pattern: String,
+    negate: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str, base: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        (!pattern.is_empty()).then(|| Rule {
+            base: base.to_string(),
+            pattern,
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+
+    // `path` is relative to the repository root
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(rel) => rel,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            glob_match(&self.pattern, rel)
+        } else {
+            glob_match(&self.pattern, rel.rsplit('/').next().unwrap_or(rel))
+        }
+    }
+}
+
+impl Selection {
+    /// Selected files under `dir`, sorted, each with its `/`-separated path
+    /// relative to `dir`.
+    pub fn walk(&self, dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+        self.walk_entries(dir, false)
+    }
+
+    // Every file and directory under `dir` the ignore files, hidden entries
+    // and exclude globs leave in, whatever the include globs and size
+    // limit; directories are listed before their contents
+    pub(crate) fn entries(&self, dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+        self.walk_entries(dir, true)
+    }
+
+    fn walk_entries(&self, dir: &Path, all: bool) -> Result<Vec<(PathBuf, String)>> {
+        let dir_abs = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+        let mut rules = Vec::new();
+        // The walked directory's place in its repository
+        let mut prefix = String::new();
+        if !self.no_ignore {
+            let top = dir_abs
+                .ancestors()
+                .find(|a| a.join(".git").exists())
+                .unwrap_or(&dir_abs);
+            let mut ancestors: Vec<&Path> = dir_abs
+                .ancestors()
+                .skip(1)
+                .take_while(|a| a.starts_with(top))
+                .collect();
+            ancestors.reverse();
+            for a in ancestors {
+                self.read_rules(a, &relative(a, top), &mut rules);
+            }
+            prefix = relative(&dir_abs, top);
+        }
+        let mut found = Vec::new();
+        self.visit(dir, "", &prefix, &mut rules, all, &mut found)?;
+        found.sort();
+        Ok(found)
+    }
+
+    fn visit(
+        &self,
+        dir: &Path,
+        rel: &str,
+        prefix: &str,
+        rules: &mut Vec<Rule>,
+        all: bool,
+        found: &mut Vec<(PathBuf, String)>,
+    ) -> Result<()> {
+        let inherited = rules.len();
+        let join = |a: &str, b: &str| match (a, b) {
+            ("", _) => b.to_string(),
+            (_, "") => a.to_string(),
+            _ => format!("{a}/{b}"),
+        };
+        if !self.no_ignore {
+            self.read_rules(dir, &join(prefix, rel), rules);
+        }
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("failed reading {}", dir.display()))?
+        {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let child = join(rel, &name);
+            let is_dir = path.is_dir();
+            let ignored = rules
+                .iter()
+                .rev()
+                .find(|r| r.matches(&join(prefix, &child), is_dir))
+                .is_some_and(|r| !r.negate);
+            if ignored || self.exclude.iter().any(|g| path_matches(g, &child)) {
+                continue;
+            }
+            if is_dir {
+                if all {
+                    found.push((path.clone(), child.clone()));
+                }
+                self.visit(&path, &child, prefix, rules, all, found)?;
+            } else if all || self.takes(&path, &child)? {
+                found.push((path, child));
+            }
+        }
+        rules.truncate(inherited);
+        Ok(())
+    }
+
+    // Whether an archive member at `rel` passes: ignore files do not apply,
+    // but hidden components, the globs and the size limit do
+    pub(crate) fn takes_member(&self, rel: &str, size: u64) -> bool {
+        let mut prefix = String::new();
+        for part in rel.split('/') {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(part);
+            if part.starts_with('.') || self.exclude.iter().any(|g| path_matches(g, &prefix)) {
+                return false;
+            }
+        }
+        self.wants(rel) && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    // Include globs, or the `.md` default
+    fn wants(&self, rel: &str) -> bool {
+        if self.include.is_empty() {
+            rel.ends_with(".md")
+        } else {
+            self.include.iter().any(|g| path_matches(g, rel))
+        }
+    }
+
+    fn takes(&self, path: &Path, rel: &str) -> Result<bool> {
+        if !self.wants(rel) {
+            return Ok(false);
+        }
+        let size = fs::metadata(path)
+            .with_context(|| format!("failed reading {}", path.display()))?
+            .len();
+        if self.max_size.is_some_and(|max| size > max) {
+            return Ok(false);
+        }
+        // Globs may name binaries; only text can be bundled
+        Ok(self.include.is_empty() || std::str::from_utf8(&fs::read(path)?).is_ok())
+    }
+
+    fn read_rules(&self, dir: &Path, base: &str, rules: &mut Vec<Rule>) {
+        for name in IGNORE_FILES {
+            if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                rules.extend(text.lines().filter_map(|l| Rule::parse(l, base)));
+            }
+        }
+    }
+}
+
+// `path` below `root`, `/`-separated
+fn relative(path: &Path, root: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignore_rules() {
+        let rule = |line: &str| Rule::parse(line, "").unwrap();
+        assert!(rule("target/").matches("a/target", true));
+        assert!(!rule("target/").matches("a/target", false));
+        assert!(rule("*.log").matches("a/b/x.log", false));
+        assert!(rule("/docs/*.md").matches("docs/a.md", false));
+        assert!(!rule("/docs/*.md").matches("x/docs/a.md", false));
+        assert!(rule("**/build").matches("x/y/build", true));
+        assert!(rule("!keep.md").negate);
+        assert!(Rule::parse("# comment", "").is_none());
+        let nested = Rule::parse("draft.md", "docs").unwrap();
+        assert!(nested.matches("docs/sub/draft.md", false));
+        assert!(!nested.matches("other/draft.md", false));
+    }
+}
@@ -5,6 +5,7 @@ use std::collections::HashMap;
 
 /// Semantic tokens - pure meaning, no language
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SemanticToken {
     // Entities (0x00-0x1F)
@@ -48,8 +49,45 @@ pub enum SemanticToken {
     QualifierLow = 0xE2,
 }
 
+impl SemanticToken {
+    const ALL: [SemanticToken; 26] = [
+        Self::EntityHuman,
+        Self::EntityAI,
+        Self::EntitySystem,
+        Self::ActionLearning,
+        Self::ActionCoding,
+        Self::ActionTeaching,
+        Self::ActionCreating,
+        Self::ActionOptimizing,
+        Self::RelPartnership,
+        Self::RelMentorship,
+        Self::RelCollaboration,
+        Self::EmotionExcited,
+        Self::EmotionFrustrated,
+        Self::EmotionCurious,
+        Self::EmotionProud,
+        Self::EmotionJoy,
+        Self::ContextProgramming,
+        Self::ContextRust,
+        Self::ContextAI,
+        Self::ContextLearning,
+        Self::ProcessActive,
+        Self::ProcessComplete,
+        Self::ProcessIterative,
+        Self::QualifierHigh,
+        Self::QualifierMedium,
+        Self::QualifierLow,
+    ];
+
+    /// Token for a wire byte, if it names one
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| *t as u8 == byte)
+    }
+}
+
 /// A semantic unit - a complete thought
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SemanticUnit {
     pub tokens: Vec<SemanticToken>,
     pub metadata: HashMap<String, String>, // For names, values, etc.
@@ -148,6 +186,50 @@ impl SemanticEncoder {
 
         bytes
     }
+
+    /// Parse the `SMQ\x01` binary format written by [`Self::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Vec<SemanticUnit>> {
+        let mut rest = bytes
+            .strip_prefix(b"SMQ\x01")
+            .ok_or_else(|| anyhow::anyhow!("not SMQ v1 data"))?;
+
+        let mut units = Vec::new();
+        while !rest.is_empty() {
+            let count = take(&mut rest, 1)?[0] as usize;
+            let tokens = take(&mut rest, count)?
+                .iter()
+                .map(|&b| {
+                    SemanticToken::from_u8(b)
+                        .ok_or_else(|| anyhow::anyhow!("unknown semantic token 0x{b:02X}"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let intensity = take(&mut rest, 1)?[0] as f32 / 255.0;
+            let mut metadata = HashMap::new();
+            for _ in 0..take(&mut rest, 1)?[0] {
+                let klen = take(&mut rest, 1)?[0] as usize;
+                let key = String::from_utf8(take(&mut rest, klen)?.to_vec())?;
+                let vlen = take(&mut rest, 1)?[0] as usize;
+                let value = String::from_utf8(take(&mut rest, vlen)?.to_vec())?;
+                metadata.insert(key, value);
+            }
+            units.push(SemanticUnit {
+                tokens,
+                metadata,
+                intensity,
+            });
+        }
+        Ok(units)
+    }
+}
+
+// Next `n` bytes of `rest`, advancing it
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> anyhow::Result<&'a [u8]> {
+    if rest.len() < n {
+        anyhow::bail!("truncated SMQ data");
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
 }
 
 /// Universal renderer - semantic to any format
@@ -333,6 +415,29 @@ mod tests {
         // Real compression happens with larger documents
     }
 
+    #[test]
+    fn test_semantic_binary_decoding() {
+        let text = "Alexandra is learning Rust with Claude!";
+        let units = SemanticEncoder::encode(text);
+        let bytes = SemanticEncoder::to_bytes(&units);
+
+        let decoded = SemanticEncoder::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), units.len());
+        assert_eq!(decoded[0].tokens, units[0].tokens);
+        assert_eq!(decoded[0].metadata, units[0].metadata);
+        assert_eq!(
+            UniversalRenderer::to_english(&decoded),
+            UniversalRenderer::to_english(&units)
+        );
+
+        // Wrong magic or version, truncation, and unknown token bytes
+        assert!(SemanticEncoder::from_bytes(b"MQB\x01").is_err());
+        assert!(SemanticEncoder::from_bytes(b"SMQ\x02").is_err());
+        assert!(SemanticEncoder::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(SemanticEncoder::from_bytes(b"SMQ\x01\x01\xFF\x80\x00").is_err());
+        assert!(SemanticEncoder::from_bytes(b"SMQ\x01").unwrap().is_empty());
+    }
+
     #[test]
     fn test_wave_encoding() {
         let text = "Alexandra is learning Rust with Claude!";
@@ -344,7 +449,7 @@ mod tests {
         // Each semantic token should have a unique frequency
         for (freq, amp, _phase) in waves {
             assert!(freq > 0.0 && freq < 1000.0);
-            assert!(amp >= 0.0 && amp <= 1.0);
+            assert!((0.0..=1.0).contains(&amp));
         }
     }
 }
@@ -0,0 +1,275 @@
+// Project summaries as `.m8` containers (`example-md/MEM8_MARQANT_INTEGRATION.md`):
+// an Identity section with the project's name, version and description
+// (from `Cargo.toml`'s `[package]` or `[workspace.package]` table, else
+// `package.json`, else the directory name), the README as a Marqant
+// Document, the other markdown files a `Selection` takes as one aggregate
+// (packed to a token budget, if given, shared with the README) and a
+// Quantum Tree snapshot of the directory. The manifests are read with
+// small parsers of their own so summaries need neither the `config` nor
+// the `serde` feature.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::m8::{self, M8};
+use crate::{estimate_tokens, Aggregate, AggregateFile, Marqant, Selection};
+
+/// Who a project is, as an Identity section records it: one `key: value`
+/// line per known field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProjectIdentity {
+    pub name: String,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    /// The manifest the fields came from, e.g. `Cargo.toml`.
+    pub source: Option<String>,
+}
+
+impl ProjectIdentity {
+    /// The identity of the project in `dir`, from `Cargo.toml` or
+    /// `package.json`; the directory name stands in for a missing name.
+    pub fn read(dir: &Path) -> Self {
+        let mut identity = Self::default();
+        for (source, parse) in [
+            (
+                "Cargo.toml",
+                cargo_fields as fn(&str) -> Vec<(String, String)>,
+            ),
+            ("package.json", json_fields),
+        ] {
+            let Ok(text) = fs::read_to_string(dir.join(source)) else {
+                continue;
+            };
+            for (key, value) in parse(&text) {
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                match key.as_str() {
+                    "name" if identity.name.is_empty() => identity.name = value,
+                    "version" if identity.version.is_none() => identity.version = Some(value),
+                    "description" if identity.description.is_none() => {
+                        identity.description = Some(value)
+                    }
+                    _ => {}
+                }
+            }
+            if !identity.name.is_empty() || identity.version.is_some() {
+                identity.source = Some(source.to_string());
+                break;
+            }
+        }
+        if identity.name.is_empty() {
+            let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+            identity.name = dir.file_name().map_or_else(
+                || "project".to_string(),
+                |n| n.to_string_lossy().into_owned(),
+            );
+        }
+        identity
+    }
+
+    /// Read an Identity section back; unknown keys are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut identity = Self::default();
+        for (key, value) in text.lines().filter_map(|l| l.split_once(": ")) {
+            let value = value.to_string();
+            match key {
+                "name" => identity.name = value,
+                "version" => identity.version = Some(value),
+                "description" => identity.description = Some(value),
+                "source" => identity.source = Some(value),
+                _ => {}
+            }
+        }
+        identity
+    }
+
+    /// The Identity section's text.
+    pub fn to_text(&self) -> String {
+        let mut out = format!("name: {}\n", self.name);
+        for (key, value) in [
+            ("version", &self.version),
+            ("description", &self.description),
+            ("source", &self.source),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!("{key}: {value}\n"));
+            }
+        }
+        out
+    }
+}
+
+impl Marqant {
+    /// A `.m8` overview of the project in `dir`: its identity, its README,
+    /// the other markdown files `selection` takes (bundled with `flags`
+    /// minus `-semantic`, and packed to fit `budget` estimated LLM tokens
+    /// along with the README when given) and its directory tree.
+    pub fn summarize_project(
+        dir: &Path,
+        selection: &Selection,
+        budget: Option<usize>,
+        flags: Option<&str>,
+    ) -> Result<M8> {
+        let mut container = M8::new();
+        container.push(
+            m8::IDENTITY,
+            ProjectIdentity::read(dir).to_text().into_bytes(),
+        );
+
+        let mut readme = None;
+        let mut docs = Vec::new();
+        for (src, rel) in selection.walk(dir)? {
+            let file = AggregateFile::read(&src, rel)?;
+            if readme.is_none() && file.path.eq_ignore_ascii_case("README.md") {
+                readme = Some(file);
+            } else {
+                docs.push(file);
+            }
+        }
+        let mut left = budget;
+        if let Some(readme) = readme {
+            let mq = Self::compress_markdown_with_flags(&readme.content, flags)?;
+            left = left.map(|b| b.saturating_sub(estimate_tokens(&mq)));
+            container.push_marqant(&mq)?;
+        }
+        if !docs.is_empty() {
+            let flags = Aggregate::flags_for(flags);
+            let bundle = match left {
+                Some(left) => {
+                    let (bundle, ranking) = Self::pack(&docs, left, None, flags.as_deref())?;
+                    ranking.iter().any(|f| f.packed).then_some(bundle)
+                }
+                None => Some(Self::aggregate_documents(&docs, flags.as_deref())?),
+            };
+            if let Some(bundle) = bundle {
+                container.push_marqant(&bundle)?;
+            }
+        }
+
+        container.push(m8::QUANTUM_TREE, m8::tree_snapshot(dir)?.into_bytes());
+        Ok(container)
+    }
+}
+
+// `key = "value"` lines of the `[package]` (or `[workspace.package]`)
+// table; inherited (`version.workspace = true`) and multi-line values are
+// skipped
+fn cargo_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut in_package = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = matches!(line, "[package]" | "[workspace.package]");
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_package) else {
+            continue;
+        };
+        let value = value.trim();
+        let string = if value.starts_with("\"\"\"") || value.starts_with("'''") {
+            None
+        } else if let Some(rest) = value.strip_prefix('\'') {
+            rest.split_once('\'').map(|(s, _)| s.to_string())
+        } else if let Some(rest) = value.strip_prefix('"') {
+            let mut chars = rest.chars();
+            json_string(&mut chars)
+        } else {
+            None
+        };
+        if let Some(string) = string {
+            fields.push((key.trim().to_string(), string));
+        }
+    }
+    fields
+}
+
+// The top-level string members of a JSON object
+fn json_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut chars = text.chars();
+    let mut depth = 0;
+    // A key waiting for its `:`, then for its value
+    let mut key = None;
+    let mut value_of = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let Some(s) = json_string(&mut chars) else {
+                    break;
+                };
+                if depth == 1 {
+                    match value_of.take() {
+                        Some(k) => fields.push((k, s)),
+                        None => key = Some(s),
+                    }
+                }
+            }
+            ':' if depth == 1 => value_of = key.take(),
+            '{' | '[' => {
+                depth += 1;
+                value_of = None;
+            }
+            '}' | ']' => depth -= 1,
+            ',' => {
+                key = None;
+                value_of = None;
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+// The rest of a double-quoted string, its opening quote consumed; TOML
+// basic strings share JSON's escapes
+fn json_string(chars: &mut std::str::Chars) -> Option<String> {
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).ok()?;
+                    out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_yield_identity_fields() {
+        let cargo = "[workspace]\nmembers = [\"a\"]\n\n[package]\nname = \"demo\" # crate\n\
+                     version.workspace = true\ndescription = 'Say \"hi\"'\n\n[dependencies]\n\
+                     name = \"other\"\n";
+        assert_eq!(
+            cargo_fields(cargo),
+            [
+                ("name".to_string(), "demo".to_string()),
+                ("description".to_string(), "Say \"hi\"".to_string())
+            ]
+        );
+        let json = r#"{"scripts": {"name": "x"}, "name": "webé", "private": true,
+                       "version": "1.2.0", "description": "A \"quoted\" app"}"#;
+        assert_eq!(
+            json_fields(json),
+            [
+                ("name".to_string(), "webé".to_string()),
+                ("version".to_string(), "1.2.0".to_string()),
+                ("description".to_string(), "A \"quoted\" app".to_string())
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use anyhow::Result;
 
 // MQ2-UNI: UTF-8 safe encoding with ASCII escape sequences
@@ -8,26 +10,24 @@ pub const MQ2_UNI_DICT_ID: &str = "mq2-uni-v2-utf8safe";
 const ESC: u8 = b'~'; // The marqant sigil for escape sequences
 
 /// ASCII-safe token mappings using escape sequences
-fn get_token_map() -> Vec<(&'static [u8], &'static [u8])> {
-    vec![
-        // Common markdown patterns -> ASCII escape codes
-        (b"\n\n", b"~PP"),   // Paragraph break
-        (b"  ", b"~SP"),     // Double space
-        (b"\n- ", b"~LI"),   // List item
-        (b"## ", b"~H2"),    // Header 2
-        (b"# ", b"~H1"),     // Header 1
-        (b"```\n", b"~CB"),  // Code block start
-        (b"```", b"~CE"),    // Code block end
-        (b"{\n", b"~OB"),    // Open brace newline
-        (b"}\n", b"~CL"),    // Close brace newline
-        (b"[\n", b"~OS"),    // Open square newline
-        (b"\n]", b"~CS"),    // Close square
-        (b": ", b"~CO"),     // Colon space
-        (b", ", b"~CM"),     // Comma space
-        (b"    ", b"~IN"),   // Indent (4 spaces)
-        (b"\n\n\n", b"~TB"), // Triple break
-    ]
-}
+const TOKEN_MAP: &[(&[u8], &[u8])] = &[
+    // Common markdown patterns -> ASCII escape codes
+    (b"\n\n", b"~PP"),   // Paragraph break
+    (b"  ", b"~SP"),     // Double space
+    (b"\n- ", b"~LI"),   // List item
+    (b"## ", b"~H2"),    // Header 2
+    (b"# ", b"~H1"),     // Header 1
+    (b"```\n", b"~CB"),  // Code block start
+    (b"```", b"~CE"),    // Code block end
+    (b"{\n", b"~OB"),    // Open brace newline
+    (b"}\n", b"~CL"),    // Close brace newline
+    (b"[\n", b"~OS"),    // Open square newline
+    (b"\n]", b"~CS"),    // Close square
+    (b": ", b"~CO"),     // Colon space
+    (b", ", b"~CM"),     // Comma space
+    (b"    ", b"~IN"),   // Indent (4 spaces)
+    (b"\n\n\n", b"~TB"), // Triple break
+];
 
 /// Skip to the next UTF-8 character boundary
 fn skip_utf8_char(bytes: &[u8], i: usize) -> usize {
@@ -60,28 +60,37 @@ fn skip_utf8_char(bytes: &[u8], i: usize) -> usize {
     (i + len).min(bytes.len())
 }
 
+/// The token map as an `mq2_uni` matcher whose token ids index
+/// `TOKEN_MAP`, so the crate's longest-match automaton drives the encoder
+/// (`"\n\n\n"` wins over `"\n\n"` regardless of table order). Built once
+/// per process.
+fn uni_matcher() -> &'static mq2_uni::Matcher<'static> {
+    static DICT: OnceLock<mq2_uni::Dictionary> = OnceLock::new();
+    static MATCHER: OnceLock<mq2_uni::Matcher<'static>> = OnceLock::new();
+    MATCHER.get_or_init(|| {
+        DICT.get_or_init(|| {
+            let tokens = TOKEN_MAP
+                .iter()
+                .enumerate()
+                .map(|(i, (pattern, _))| (i as u8, pattern.to_vec()))
+                .collect();
+            mq2_uni::Dictionary::new(tokens).expect("UNI token ids are distinct and below 0xFF")
+        })
+        .matcher()
+    })
+}
+
 pub fn mq2_uni_encode(input: &[u8]) -> Result<Vec<u8>> {
-    let token_map = get_token_map();
+    let matcher = uni_matcher();
     let mut out = Vec::with_capacity(input.len());
     let mut i = 0;
 
     while i < input.len() {
         // Try to match patterns (only in ASCII range)
-        if input[i] < 0x80 {
-            let mut matched = false;
-
-            for (pattern, token) in &token_map {
-                if i + pattern.len() <= input.len() && &input[i..i + pattern.len()] == *pattern {
-                    out.extend_from_slice(token);
-                    i += pattern.len();
-                    matched = true;
-                    break;
-                }
-            }
-
-            if matched {
-                continue;
-            }
+        if let Some((id, len)) = matcher.longest_match(&input[i..]) {
+            out.extend_from_slice(TOKEN_MAP[id as usize].1);
+            i += len;
+            continue;
         }
 
         // Handle UTF-8 properly - copy entire character
@@ -94,40 +103,68 @@ pub fn mq2_uni_encode(input: &[u8]) -> Result<Vec<u8>> {
 }
 
 pub fn mq2_uni_decode(input: &[u8]) -> Result<Vec<u8>> {
-    let token_map = get_token_map();
     let mut out = Vec::with_capacity(input.len() * 2);
-    let mut i = 0;
+    for chunk in mq2_uni_decode_chunks(input) {
+        out.extend_from_slice(chunk);
+    }
+    Ok(out)
+}
 
-    while i < input.len() {
-        // Check for escape sequences
-        if i + 2 < input.len() && input[i] == ESC {
-            let mut decoded = false;
-
-            // Try 3-byte tokens first (~XX)
-            if i + 3 <= input.len() {
-                let token = &input[i..i + 3];
-                for (pattern, tok) in &token_map {
-                    if *tok == token {
-                        out.extend_from_slice(pattern);
-                        i += 3;
-                        decoded = true;
-                        break;
-                    }
-                }
-            }
-
-            if decoded {
-                continue;
-            }
-        }
+/// Whether `input` holds at least one `~XX` escape from the UNI dictionary,
+/// the only sign of bare MQ2-UNI text (it has no header)
+pub(crate) fn has_uni_escapes(input: &[u8]) -> bool {
+    input
+        .windows(3)
+        .any(|w| w[0] == ESC && TOKEN_MAP.iter().any(|(_, tok)| *tok == w))
+}
 
-        // Not a token - copy UTF-8 character
-        let next_i = skip_utf8_char(input, i);
-        out.extend_from_slice(&input[i..next_i]);
-        i = next_i;
+/// Zero-copy incremental decode: yields the output in order as slices, each
+/// a literal run borrowed from `input` or a static token pattern.
+pub fn mq2_uni_decode_chunks(input: &[u8]) -> Mq2UniChunks<'_> {
+    Mq2UniChunks { input, pos: 0 }
+}
+
+/// Iterator returned by [`mq2_uni_decode_chunks`].
+pub struct Mq2UniChunks<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Mq2UniChunks<'_> {
+    // Pattern for the escape sequence (~XX) at `i`, if it is one
+    fn token_at(&self, i: usize) -> Option<&'static [u8]> {
+        let token = self.input.get(i..i + 3)?;
+        if token[0] != ESC {
+            return None;
+        }
+        TOKEN_MAP
+            .iter()
+            .find(|(_, tok)| *tok == token)
+            .map(|(pattern, _)| *pattern)
     }
+}
 
-    Ok(out)
+impl<'a> Iterator for Mq2UniChunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let start = self.pos;
+        if start >= self.input.len() {
+            return None;
+        }
+        if let Some(pattern) = self.token_at(start) {
+            self.pos += 3;
+            return Some(pattern);
+        }
+        // Literal run up to the next token, copied whole UTF-8 characters
+        // at a time so a run never ends inside a multibyte sequence
+        let mut i = skip_utf8_char(self.input, start);
+        while i < self.input.len() && self.token_at(i).is_none() {
+            i = skip_utf8_char(self.input, i);
+        }
+        self.pos = i;
+        Some(&self.input[start..i])
+    }
 }
 
 #[cfg(test)]
@@ -186,4 +223,24 @@ mod tests {
 
         assert_eq!(bytes, decoded.as_slice());
     }
+
+    #[test]
+    fn test_decode_chunks_are_zero_copy() {
+        let encoded = mq2_uni_encode("# Title\n\nこんにちは 👋".as_bytes()).unwrap();
+        let chunks: Vec<&[u8]> = mq2_uni_decode_chunks(&encoded).collect();
+        assert_eq!(
+            chunks,
+            [&b"# "[..], b"Title", b"\n\n", "こんにちは 👋".as_bytes()]
+        );
+        let range = encoded.as_ptr_range();
+        assert!(range.contains(&chunks[3].as_ptr()));
+    }
+
+    #[test]
+    fn test_longest_match_wins() {
+        // "\n\n\n" must become ~TB even though "\n\n" is listed first
+        let encoded = mq2_uni_encode(b"a\n\n\nb").unwrap();
+        assert_eq!(encoded, b"a~TBb");
+        assert_eq!(mq2_uni_decode(&encoded).unwrap(), b"a\n\n\nb");
+    }
 }
@@ -0,0 +1,708 @@
+// Integrity checks for compressed files, without trusting the decoder to
+// notice problems on its own: header, declared sizes, dictionary shape,
+// token coverage, `-std:` resolvability and a full round trip.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::m8;
+use crate::{
+    aggregate::AGGREGATE_MAGIC, is_token_char, mq2_uni_decode, mq2_uni_encode, parse_t_entries,
+    resolve_std_dict, seek, split_line, uni, Aggregate, DecodeOptions, Marqant, TokenEntry,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Which check produced a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Check {
+    Header,
+    Sizes,
+    Dictionary,
+    Body,
+    Tokens,
+    StdDict,
+    RoundTrip,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Check::Header => "header",
+            Check::Sizes => "sizes",
+            Check::Dictionary => "dictionary",
+            Check::Body => "body",
+            Check::Tokens => "tokens",
+            Check::StdDict => "std",
+            Check::RoundTrip => "roundtrip",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub check: Check,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VerifyReport {
+    pub kind: String, // "MARQANT" | "MQ2" | "MQ2-UNI" | "M8" | "UNKNOWN"
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl VerifyReport {
+    /// True when no check reported an error (warnings are allowed).
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .all(|d| d.severity != Severity::Error)
+    }
+
+    fn error(&mut self, check: Check, message: impl Into<String>) {
+        self.push(Severity::Error, check, message);
+    }
+
+    fn warning(&mut self, check: Check, message: impl Into<String>) {
+        self.push(Severity::Warning, check, message);
+    }
+
+    fn push(&mut self, severity: Severity, check: Check, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            check,
+            message: message.into(),
+        });
+    }
+}
+
+/// Verify a MARQANT, MQ2, bare MQ2-UNI or MEM8 file. Never fails: every
+/// problem is a diagnostic. MQ2 payloads are checked by decoding them (bare
+/// MQ2-UNI also by encoding the result again); MARQANT files are
+/// additionally re-compressed with their own flags and decoded again, as is
+/// every Marqant Document in a MEM8 container.
+pub fn verify_mq(input: impl AsRef<[u8]>) -> VerifyReport {
+    verify_mq_with_options(input, &DecodeOptions::default())
+}
+
+/// [`verify_mq`], resolving `-std:` dictionaries as `options` say.
+pub fn verify_mq_with_options(input: impl AsRef<[u8]>, options: &DecodeOptions) -> VerifyReport {
+    let input = input.as_ref();
+    let mut report = VerifyReport {
+        kind: "UNKNOWN".to_string(),
+        diagnostics: Vec::new(),
+    };
+    if input.starts_with(AGGREGATE_MAGIC.as_bytes()) {
+        report.kind = "MARQANT".to_string();
+        verify_aggregate(input, options, &mut report);
+    } else if input.starts_with(b"MARQANT") {
+        report.kind = "MARQANT".to_string();
+        verify_marqant(input, options, &mut report);
+    } else if input.starts_with(b"MQ2~") {
+        report.kind = "MQ2".to_string();
+        verify_mq2(input, &mut report);
+    } else if input.starts_with(m8::MAGIC) {
+        report.kind = "M8".to_string();
+        verify_m8(input, options, &mut report);
+    } else if std::str::from_utf8(input).is_ok() && uni::has_uni_escapes(input) {
+        // Damaged bare MQ2-UNI still gets diagnosed, so any `~XX` escape will do
+        report.kind = "MQ2-UNI".to_string();
+        verify_uni_bare(input, &mut report);
+    } else {
+        report.error(Check::Header, "not a MARQANT, MQ2, MQ2-UNI or MEM8 file");
+    }
+    report
+}
+
+// MEM8 containers: checksum and section table, then each Marqant Document's
+// recorded fields against its payload, which is verified like a file
+fn verify_m8(input: &[u8], options: &DecodeOptions, report: &mut VerifyReport) {
+    let container = match m8::M8::parse(input) {
+        Ok(container) => container,
+        Err(e) => {
+            report.error(Check::Header, e.to_string());
+            return;
+        }
+    };
+    for (i, section) in container.sections.iter().enumerate() {
+        if section.kind != m8::MARQANT_DOCUMENT {
+            continue;
+        }
+        let doc = match m8::MarqantSection::parse(&section.data) {
+            Ok(doc) => doc,
+            Err(e) => {
+                report.error(Check::Header, format!("section {i}: {e}"));
+                continue;
+            }
+        };
+        if doc.header.compressed_size as usize != doc.mq.len() {
+            report.error(
+                Check::Sizes,
+                format!(
+                    "section {i}: compressed size {} recorded, payload is {} bytes",
+                    doc.header.compressed_size,
+                    doc.mq.len()
+                ),
+            );
+        }
+        if let Ok(expected) = m8::MarqantSection::from_mq(&doc.mq) {
+            let (got, want) = (&doc.header, &expected.header);
+            if (got.flags, got.token_count, got.original_size)
+                != (want.flags, want.token_count, want.original_size)
+            {
+                report.error(
+                    Check::Header,
+                    format!("section {i}: recorded flags, token count or size disagree with the document"),
+                );
+            }
+        }
+        for d in verify_mq_with_options(doc.mq.as_bytes(), options).diagnostics {
+            report.push(d.severity, d.check, format!("section {i}: {}", d.message));
+        }
+    }
+}
+
+// Aggregates: manifest offsets, declared sizes, every document decoding, and
+// a rebuild from the decoded documents
+fn verify_aggregate(input: &[u8], options: &DecodeOptions, report: &mut VerifyReport) {
+    let Ok(text) = std::str::from_utf8(input) else {
+        report.error(Check::Header, "not valid UTF-8");
+        return;
+    };
+    let aggregate = match Aggregate::parse_with_options(text, options) {
+        Ok(aggregate) => aggregate,
+        Err(e) => {
+            report.error(Check::Header, format!("{e:#}"));
+            return;
+        }
+    };
+    let after_header = text.split_once('\n').map_or(0, |(_, rest)| rest.len());
+    if aggregate.compressed_size != after_header as u64 {
+        report.error(
+            Check::Sizes,
+            format!(
+                "header declares {} compressed bytes, found {after_header}",
+                aggregate.compressed_size
+            ),
+        );
+    }
+    let mut files = Vec::new();
+    for entry in &aggregate.entries {
+        match aggregate.extract(&entry.path) {
+            Ok(content) => files.push((entry.path.clone(), content)),
+            Err(e) => report.error(Check::Body, format!("{e:#}")),
+        }
+    }
+    if files.len() < aggregate.entries.len() {
+        return;
+    }
+    let original: usize = files.iter().map(|(_, c)| c.len()).sum();
+    if aggregate.original_size != original as u64 {
+        report.error(
+            Check::Sizes,
+            format!(
+                "header declares {} original bytes, documents decode to {original}",
+                aggregate.original_size
+            ),
+        );
+    }
+    let rebuilt = Marqant::aggregate_files(&files, Some(&aggregate.bundle_flags()))
+        .and_then(|out| Aggregate::parse_with_options(&out, options));
+    let same = rebuilt.as_ref().is_ok_and(|r| {
+        files
+            .iter()
+            .all(|(path, content)| r.extract(path).is_ok_and(|c| &c == content))
+    });
+    if !same {
+        report.error(Check::RoundTrip, "documents do not survive a rebuild");
+    }
+}
+
+// Headerless `mq uni-encode` output: any bytes decode, so the text must
+// decode to UTF-8 and be exactly what the encoder writes for the result
+fn verify_uni_bare(input: &[u8], report: &mut VerifyReport) {
+    let decoded = match mq2_uni_decode(input) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            report.error(Check::Body, e.to_string());
+            return;
+        }
+    };
+    if let Err(e) = std::str::from_utf8(&decoded) {
+        report.error(Check::Body, format!("decodes to invalid UTF-8: {e}"));
+    }
+    match mq2_uni_encode(&decoded) {
+        Ok(again) if again == input => {}
+        Ok(_) => report.error(
+            Check::RoundTrip,
+            "re-encoding the decoded text does not reproduce the file",
+        ),
+        Err(e) => report.error(Check::RoundTrip, e.to_string()),
+    }
+}
+
+fn verify_marqant(input: &[u8], options: &DecodeOptions, report: &mut VerifyReport) {
+    let text = match std::str::from_utf8(input) {
+        Ok(text) => text,
+        Err(e) => {
+            report.error(
+                Check::Header,
+                format!("not valid UTF-8 (byte {})", e.valid_up_to()),
+            );
+            return;
+        }
+    };
+
+    // Header: MARQANT <ts> <orig> <comp> [flags]
+    let (header, mut rest) = text.split_once('\n').unwrap_or((text, ""));
+    let parts: Vec<&str> = header.split_whitespace().collect();
+    if parts.len() < 4 || parts[0] != "MARQANT" {
+        report.error(
+            Check::Header,
+            "expected `MARQANT <timestamp> <original> <compressed> [flags]`",
+        );
+        return;
+    }
+    if parts[1].parse::<i64>().is_err() {
+        report.error(
+            Check::Header,
+            format!("timestamp `{}` is not a number", parts[1]),
+        );
+    }
+    let mut declared = |name: &str, value: &str| match value.parse::<usize>() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            report.error(
+                Check::Header,
+                format!("{name} size `{value}` is not a number"),
+            );
+            None
+        }
+    };
+    let orig = declared("original", parts[2]);
+    let comp = declared("compressed", parts[3]);
+    let flags = &parts[4..];
+    let has_zlib = flags.contains(&"-zlib");
+    let has_sections = flags.contains(&"-semantic");
+    let mut std_id = None;
+    for flag in flags {
+        match flag.strip_prefix("-std:") {
+            Some(id) => std_id = Some(id),
+            None if matches!(*flag, "-zlib" | "-semantic") => {}
+            None if seek::block_size(flag).is_some() => {}
+            None => report.warning(Check::Header, format!("unknown flag `{flag}`")),
+        }
+    }
+
+    // Standard dictionary, in the decoder's resolver order
+    let mut std_map = HashMap::new();
+    let mut std_ok = true;
+    if let Some(id) = std_id {
+        match resolve_std_dict(id, options).transpose() {
+            Some(Ok(map)) => std_map = map,
+            Some(Err(e)) => {
+                std_ok = false;
+                report.error(Check::StdDict, format!("`{id}` failed to resolve: {e}"));
+            }
+            None => {
+                std_ok = false;
+                report.error(
+                    Check::StdDict,
+                    format!("`{id}` is not a known or resolvable dictionary"),
+                );
+            }
+        }
+    }
+
+    // Dictionary lines up to `---`
+    let mut inline: Vec<(String, String)> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut line_no = 1;
+    let body = loop {
+        if rest.is_empty() {
+            report.error(Check::Dictionary, "missing `---` separator before the body");
+            return;
+        }
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        rest = next;
+        line_no += 1;
+        if line == "---" {
+            break rest;
+        }
+        let Some((token, pattern)) = line.split_once('=') else {
+            report.error(
+                Check::Dictionary,
+                format!("line {line_no}: expected `token=pattern`"),
+            );
+            continue;
+        };
+        let shown = token.escape_debug();
+        if token.is_empty() {
+            report.error(Check::Dictionary, format!("line {line_no}: empty token"));
+            continue;
+        }
+        if pattern.is_empty() {
+            report.error(
+                Check::Dictionary,
+                format!("line {line_no}: token `{shown}` has an empty pattern"),
+            );
+        }
+        if !seen.insert(token) {
+            report.error(
+                Check::Dictionary,
+                format!("line {line_no}: token `{shown}` is defined twice"),
+            );
+        }
+        if !token.chars().all(is_token_char) {
+            report.error(
+                Check::Dictionary,
+                format!("line {line_no}: token `{shown}` is ordinary text and would also match literal text"),
+            );
+        }
+        inline.push((token.to_string(), pattern.replace("\\n", "\n")));
+    };
+    for (token, pattern) in &inline {
+        if let Some((other, _)) = inline
+            .iter()
+            .find(|(t, _)| t != token && pattern.contains(t.as_str()))
+        {
+            report.warning(
+                Check::Dictionary,
+                format!(
+                    "pattern for `{}` contains token `{}`, which is not expanded again",
+                    token.escape_debug(),
+                    other.escape_debug()
+                ),
+            );
+        }
+    }
+
+    // Tokenized text, inflating a zlib body
+    let tokenized = if seek::block_size(&flags.join(" ")).is_some() {
+        match seekable_body(body, has_zlib) {
+            Ok(tokenized) => tokenized,
+            Err(e) => {
+                report.error(Check::Body, format!("{e:#}"));
+                return;
+            }
+        }
+    } else if has_zlib {
+        let raw = body.split_whitespace().collect::<String>();
+        let decoded = match base64::Engine::decode(&base64::engine::general_purpose::STANDARD, raw)
+        {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report.error(Check::Body, format!("-zlib body is not valid base64: {e}"));
+                return;
+            }
+        };
+        let mut inflated = String::new();
+        if let Err(e) = ZlibDecoder::new(&decoded[..]).read_to_string(&mut inflated) {
+            report.error(
+                Check::Body,
+                format!("-zlib body does not inflate to UTF-8 text: {e}"),
+            );
+            return;
+        }
+        inflated
+    } else {
+        body.to_string()
+    };
+
+    // The remaining checks need the full dictionary
+    if !std_ok {
+        return;
+    }
+
+    // Every token-like char in the body needs a definition
+    let defined: HashSet<char> = inline
+        .iter()
+        .map(|(t, _)| t)
+        .chain(std_map.keys())
+        .flat_map(|t| t.chars())
+        .collect();
+    let mut undefined: Vec<(char, usize, usize)> = Vec::new(); // (char, first offset, count)
+    for (i, c) in tokenized.char_indices() {
+        if is_token_char(c) && !defined.contains(&c) {
+            match undefined.iter_mut().find(|u| u.0 == c) {
+                Some(u) => u.2 += 1,
+                None => undefined.push((c, i, 1)),
+            }
+        }
+    }
+    for (c, first, count) in undefined {
+        let message = format!(
+            "U+{:04X} appears {count} time(s) (first at byte {first} of the body) with no definition",
+            c as u32
+        );
+        if c.is_control() {
+            report.error(Check::Tokens, message);
+        } else {
+            // Private-use chars may be literal text (icon fonts)
+            report.warning(
+                Check::Tokens,
+                format!("{message}; literal text or a missing token"),
+            );
+        }
+    }
+
+    // Compressed size as the writer computes it: body plus every token it
+    // used, including standard entries left off the wire
+    if let Some(comp) = comp {
+        let dict_size: usize = inline
+            .iter()
+            .map(|(t, p)| (t.as_str(), p.as_str()))
+            .chain(
+                std_map
+                    .iter()
+                    .filter(|(t, _)| !seen.contains(t.as_str()) && tokenized.contains(t.as_str()))
+                    .map(|(t, p)| (t.as_str(), p.as_str())),
+            )
+            .map(|(t, p)| t.len() + p.len() + 3)
+            .sum();
+        let actual = body.len() + dict_size + 4;
+        if comp != actual {
+            report.error(
+                Check::Sizes,
+                format!("header declares {comp} compressed bytes, file has {actual}"),
+            );
+        }
+    }
+
+    // Decode: declared original size, then a full round trip
+    let mut decoded = Vec::new();
+    let result = Marqant::decode_visit(text, options, |chunk| {
+        decoded.extend_from_slice(chunk);
+        Ok(())
+    });
+    if let Err(e) = result {
+        report.error(Check::RoundTrip, format!("decode failed: {e}"));
+        return;
+    }
+    if let Ok(Some(index)) = Marqant::seek_table(text) {
+        let indexed: usize = index.iter().map(|e| e.original_len).sum();
+        if indexed != decoded.len() {
+            report.error(
+                Check::Sizes,
+                format!(
+                    "seek table covers {indexed} decoded bytes, decoded {}",
+                    decoded.len()
+                ),
+            );
+        }
+    }
+    if let Some(orig) = orig {
+        // -semantic ends the last line with a newline the source may not have had
+        let added_newline = has_sections && decoded.len() == orig + 1 && decoded.ends_with(b"\n");
+        if decoded.len() != orig && !added_newline {
+            report.error(
+                Check::Sizes,
+                format!(
+                    "header declares {orig} original bytes, decoded {}",
+                    decoded.len()
+                ),
+            );
+        }
+    }
+    let roundtrip = (|| {
+        let first = Marqant::decompress_marqant_with_options(text, options)?;
+        let flags = flags.join(" ");
+        let flags = (!flags.is_empty()).then_some(flags.as_str());
+        let again = Marqant::compress_markdown_with_flags(&first, flags)?;
+        anyhow::Ok((
+            Marqant::decompress_marqant_with_options(&again, options)?,
+            first,
+        ))
+    })();
+    match roundtrip {
+        Ok((second, first)) if second != first => {
+            let at = first
+                .bytes()
+                .zip(second.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            report.error(
+                Check::RoundTrip,
+                format!(
+                    "re-compressing the decoded text changes it (first difference at byte {at})"
+                ),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => report.error(Check::RoundTrip, format!("re-compressing failed: {e}")),
+    }
+}
+
+// Every block of a `-seekable` body, checked to tile the tokenized text
+fn seekable_body(body: &str, zlib: bool) -> anyhow::Result<String> {
+    let (blocks, index) = seek::read_table(body)?;
+    let mut tokenized = String::new();
+    let mut original = 0;
+    for entry in &index {
+        if entry.tokenized_start != tokenized.len() || entry.original_start != original {
+            anyhow::bail!(
+                "seek table block at {} does not follow the one before it",
+                entry.stored_start
+            );
+        }
+        tokenized.push_str(&seek::block_text(blocks, entry, zlib)?);
+        original += entry.original_len;
+    }
+    Ok(tokenized)
+}
+
+fn verify_mq2(input: &[u8], report: &mut VerifyReport) {
+    // Header: MQ2~UNI~<ts_hex>~<orig_hex>~<comp_hex>~<tokc_hex>~<format>
+    let (header, rest) = split_line(input);
+    let header = String::from_utf8_lossy(header);
+    let parts: Vec<&str> = header.split('~').collect();
+    if parts.len() != 7 {
+        report.error(
+            Check::Header,
+            "expected `MQ2~UNI~<ts>~<original>~<compressed>~<tokens>~<format>`",
+        );
+        return;
+    }
+    if parts[1] != "UNI" {
+        report.error(Check::Header, format!("unsupported variant `{}`", parts[1]));
+        return;
+    }
+    let mut hex = |name: &str, value: &str| match usize::from_str_radix(value, 16) {
+        Ok(n) => Some(n),
+        Err(_) => {
+            report.error(Check::Header, format!("{name} `{value}` is not hex"));
+            None
+        }
+    };
+    hex("timestamp", parts[2]);
+    let orig = hex("original size", parts[3]);
+    let comp = hex("compressed size", parts[4]);
+    let tokc = hex("token count", parts[5]);
+    let escaped = match parts[6] {
+        "esc" => true,
+        "text" => false,
+        other => {
+            report.error(Check::Header, format!("unknown format `{other}`"));
+            return;
+        }
+    };
+
+    // Binary ~T section, then `\n~~~~\n`
+    let Some(section) = rest.strip_prefix(b"~T") else {
+        report.error(Check::Dictionary, "missing ~T section");
+        return;
+    };
+    let Some((entries, len)) = parse_t_entries(section) else {
+        report.error(Check::Dictionary, "~T entry runs past the end of the file");
+        return;
+    };
+    let after = &section[len..];
+    if after.starts_with(b"\n~S") {
+        report.error(
+            Check::Dictionary,
+            "~S section is not read by the MQ2-UNI decoder",
+        );
+        return;
+    }
+    let Some(stream) = after.strip_prefix(b"\n~~~~\n") else {
+        report.error(Check::Dictionary, "~T section is not terminated by `~~~~`");
+        return;
+    };
+    check_mq2_entries(&entries, tokc, escaped, report);
+
+    let Some(decoded) = mq2_uni::decode(input) else {
+        report.error(Check::Body, "stream ends with a dangling escape byte");
+        return;
+    };
+    let decoded_len = decoded.len();
+
+    if let Some(comp) = comp {
+        if comp != stream.len() {
+            report.error(
+                Check::Sizes,
+                format!(
+                    "header declares {comp} stream bytes, file has {}",
+                    stream.len()
+                ),
+            );
+        }
+    }
+    if let Some(orig) = orig {
+        if orig != decoded_len {
+            report.error(
+                Check::Sizes,
+                format!("header declares {orig} original bytes, decoded {decoded_len}"),
+            );
+        }
+    }
+
+    // Re-encode with the file's own dictionary; a dictionary the encoder
+    // refuses has already been reported above
+    let tokens = entries
+        .iter()
+        .map(|e| (e.id[0], e.pattern.clone()))
+        .collect();
+    if let Ok(dict) = mq2_uni::Dictionary::new(tokens) {
+        let again = mq2_uni::encode(&decoded, &dict, parts[2]);
+        if mq2_uni::decode(&again).as_deref() != Some(&decoded[..]) {
+            report.error(
+                Check::RoundTrip,
+                "re-encoding the decoded text with the file's dictionary changes it",
+            );
+        }
+    }
+}
+
+fn check_mq2_entries(
+    entries: &[TokenEntry],
+    tokc: Option<usize>,
+    escaped: bool,
+    report: &mut VerifyReport,
+) {
+    if let Some(tokc) = tokc {
+        if tokc != entries.len() {
+            report.error(
+                Check::Dictionary,
+                format!("header declares {tokc} tokens, ~T has {}", entries.len()),
+            );
+        }
+    }
+    let mut seen = [false; 256];
+    for entry in entries {
+        let id = entry.id[0];
+        if std::mem::replace(&mut seen[id as usize], true) {
+            report.error(
+                Check::Dictionary,
+                format!("token 0x{id:02X} is defined twice"),
+            );
+        }
+        if escaped && id == mq2_uni::ESC {
+            report.error(
+                Check::Dictionary,
+                "token 0xFF collides with the escape byte",
+            );
+        }
+        if entry.pattern.is_empty() {
+            report.error(
+                Check::Dictionary,
+                format!("token 0x{id:02X} has an empty pattern"),
+            );
+        }
+    }
+    if !entries.windows(2).all(|w| w[0].id <= w[1].id) {
+        report.warning(Check::Dictionary, "~T entries are not sorted by token id");
+    }
+}
@@ -0,0 +1,176 @@
+// `mq watch`: keep compressed mirrors of a docs tree current by polling.
+// Each pass reuses the batch planner, so outputs land where `mq compress
+// <dirs> --output-dir` would put them and unchanged files are skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::batch::{self, Outcome};
+
+pub struct Options {
+    pub interval: Duration,
+    /// Run a single pass and return, for scripts and cron jobs
+    pub once: bool,
+}
+
+/// Compresses changed `.md` files under `inputs` until interrupted, and
+/// deletes outputs whose source was removed. On startup, outputs recorded in
+/// `<output-dir>/.mq-watch` whose source under a watched directory is gone are
+/// removed too; other `.mq` files in the output directory are left alone.
+pub fn watch(
+    inputs: &[PathBuf],
+    batch_opts: &batch::Options,
+    opts: &Options,
+    convert: impl Fn(&Path, &[u8]) -> Result<Vec<u8>> + Sync,
+) -> Result<()> {
+    let mut batch_opts = batch_opts.clone();
+    // Outputs written or seen this session, by source
+    let mut known: HashMap<PathBuf, PathBuf> = HashMap::new();
+    // Sources that failed, with the mtime they failed at: retried once changed
+    let mut failed: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+    let modified = |p: &PathBuf| fs::metadata(p).and_then(|m| m.modified()).ok();
+
+    if !opts.once {
+        log(&format!(
+            "watching {} (every {:?})",
+            inputs
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            opts.interval
+        ));
+    }
+    // Outputs whose sources went away while nothing was watching. Only files
+    // recorded in the state file are candidates, and only for sources under
+    // an input directory: a file input that is gone may just be misspelled.
+    let state = batch_opts.output_dir.as_deref().map(|d| d.join(STATE_FILE));
+    // State entries for sources outside this run's input directories
+    let mut others: HashMap<PathBuf, PathBuf> = HashMap::new();
+    if let Some(path) = &state {
+        let dirs: Vec<PathBuf> = inputs
+            .iter()
+            .filter(|p| p.is_dir())
+            .map(|p| absolute(p))
+            .collect();
+        for (src, dst) in read_state(path) {
+            if !dirs.iter().any(|d| src.starts_with(d)) {
+                others.insert(src, dst);
+            } else if src.exists() {
+                known.insert(src, dst);
+            } else {
+                remove_orphan(&dst);
+            }
+        }
+    }
+    let mut saved = None;
+    loop {
+        let jobs: Vec<batch::Job> = batch::plan(inputs, "md", "mq", &batch_opts)?
+            .into_iter()
+            .filter(|job| failed.get(&job.src) != Some(&modified(&job.src)))
+            .collect();
+        let outcomes = batch::run(&jobs, &batch_opts, &convert);
+        for (job, outcome) in jobs.iter().zip(&outcomes) {
+            match outcome {
+                Outcome::Written { input, output } => log(&format!(
+                    "compressed {} -> {}  {input} -> {output} bytes ({})",
+                    job.src.display(),
+                    job.dst.display(),
+                    batch::ratio(*input, *output)
+                )),
+                Outcome::Skipped => {}
+                Outcome::Failed(err) => {
+                    log(&format!("error: {}: {err}", job.src.display()));
+                    failed.insert(job.src.clone(), modified(&job.src));
+                    continue;
+                }
+            }
+            failed.remove(&job.src);
+        }
+
+        // Sources gone since the last pass take their outputs with them
+        let current: HashSet<PathBuf> = jobs.iter().map(|j| absolute(&j.src)).collect();
+        known.retain(|src, dst| {
+            if current.contains(src) || src.exists() {
+                return true;
+            }
+            remove_orphan(dst);
+            false
+        });
+        failed.retain(|src, _| src.exists());
+        known.extend(jobs.into_iter().map(|j| (absolute(&j.src), j.dst)));
+
+        if let Some(path) = &state {
+            others.retain(|src, _| !known.contains_key(src));
+            let text = render_state(path, others.iter().chain(&known));
+            if saved.as_ref() != Some(&text) {
+                let written = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::write(path, &text));
+                if let Err(e) = written {
+                    log(&format!("error: writing {}: {e}", path.display()));
+                }
+                saved = Some(text);
+            }
+        }
+
+        if opts.once {
+            return Ok(());
+        }
+        // --force applies to the first pass only
+        batch_opts.force = false;
+        std::thread::sleep(opts.interval);
+    }
+}
+
+// Lists the outputs watch has written, so a restart only removes its own
+const STATE_FILE: &str = ".mq-watch";
+
+// `<absolute source>\t<output relative to the output dir>` lines
+fn read_state(path: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(src, dst)| (PathBuf::from(src), dir.join(dst)))
+        .collect()
+}
+
+fn render_state<'a>(
+    path: &Path,
+    entries: impl Iterator<Item = (&'a PathBuf, &'a PathBuf)>,
+) -> String {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut lines: Vec<String> = entries
+        .map(|(src, dst)| {
+            let rel = dst.strip_prefix(dir).unwrap_or(dst);
+            format!("{}\t{}\n", src.display(), rel.display())
+        })
+        .collect();
+    lines.sort();
+    lines.concat()
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn remove_orphan(dst: &Path) {
+    match fs::remove_file(dst) {
+        Ok(()) => log(&format!("removed {} (source deleted)", dst.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log(&format!("error: removing {}: {e}", dst.display())),
+    }
+}
+
+fn log(message: &str) {
+    println!("[{}] {message}", chrono::Local::now().format("%H:%M:%S"));
+}
@@ -0,0 +1,796 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use marqant::{
+    estimate_tokens, read_mq_metadata, verify_mq, Aggregate, AggregateFile, ArchiveFormat, Marqant,
+    Selection,
+};
+
+mod common;
+
+fn fixture(name: &str) -> PathBuf {
+    let dir = common::scratch(name);
+    fs::create_dir_all(dir.join("docs/sub")).unwrap();
+    fs::create_dir_all(dir.join("docs/.hidden")).unwrap();
+    fs::copy("example-md/QUICK_REFERENCE.md", dir.join("docs/a.md")).unwrap();
+    fs::copy("example-md/ARCHITECTURE.md", dir.join("docs/sub/b.md")).unwrap();
+    fs::write(dir.join("docs/.hidden/c.md"), "hidden").unwrap();
+    fs::write(dir.join("docs/notes.txt"), "not markdown").unwrap();
+    dir
+}
+
+#[test]
+fn aggregate_roundtrips_every_document() {
+    let files = vec![
+        (
+            "README.md".to_string(),
+            "# Smart Tree\n\nSmart Tree installation guide. Smart Tree installation is easy.\n"
+                .to_string(),
+        ),
+        (
+            "docs/INSTALL.md".to_string(),
+            "## Smart Tree installation\n\nRun the Smart Tree installation script. 🌳".to_string(),
+        ),
+        ("empty.md".to_string(), String::new()),
+    ];
+    for flags in [None, Some("-zlib"), Some("-zlib -std:std-static-v1")] {
+        let mq = Marqant::aggregate_files(&files, flags).unwrap();
+        let header = mq.lines().next().unwrap();
+        assert!(header.starts_with("MARQANT_V2 "), "{header}");
+        assert!(header.contains(" -aggregate"), "{header}");
+
+        let bundle = Aggregate::parse(&mq).unwrap();
+        let paths: Vec<&str> = bundle.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["README.md", "docs/INSTALL.md", "empty.md"]);
+        for (path, content) in &files {
+            assert_eq!(&bundle.extract(path).unwrap(), content, "{path} {flags:?}");
+        }
+        assert_eq!(
+            bundle.original_size,
+            files.iter().map(|(_, c)| c.len() as u64).sum::<u64>()
+        );
+        let report = verify_mq(&mq);
+        assert!(report.is_ok(), "{flags:?}: {:?}", report.diagnostics);
+    }
+
+    // One dictionary serves both files
+    let mq = Marqant::aggregate_files(&files, None).unwrap();
+    let info = read_mq_metadata(&mq).unwrap();
+    assert_eq!(info.variant.as_deref(), Some("aggregate"));
+    assert!(info
+        .tokens
+        .iter()
+        .any(|t| t.pattern == b"Smart Tree installation"));
+    let text = Marqant::decompress_marqant(&mq).unwrap();
+    assert!(text.starts_with("::file:README.md::\n# Smart Tree\n"));
+    assert!(text.contains("::file:docs/INSTALL.md::\n## Smart Tree installation\n"));
+}
+
+#[test]
+fn aggregate_rejects_bad_input() {
+    let doc = |p: &str| (p.to_string(), "x".to_string());
+    assert!(Marqant::aggregate_files(&[doc("a.md"), doc("a.md")], None).is_err());
+    assert!(Marqant::aggregate_files(&[doc("a\nb.md")], None).is_err());
+    assert!(Marqant::aggregate_files(&[doc("a.md")], Some("-semantic")).is_err());
+    assert_eq!(
+        Aggregate::flags_for(Some("-zlib -semantic -std:std-static-v1")).as_deref(),
+        Some("-zlib -std:std-static-v1")
+    );
+    assert_eq!(Aggregate::flags_for(Some("-semantic")), None);
+
+    let mq = Marqant::aggregate_files(&[doc("a.md"), doc("b.md")], None).unwrap();
+    let tampered = mq.replace("b.md:", "b.md:9");
+    assert!(Aggregate::parse(&tampered).is_err());
+    assert!(!verify_mq(&tampered).is_ok());
+    assert!(Aggregate::parse(&mq).unwrap().extract("c.md").is_err());
+}
+
+#[test]
+fn aggregate_walks_directories() {
+    let dir = fixture("aggregate-lib");
+    let mq = Marqant::aggregate(&[dir.join("docs")]).unwrap();
+    let bundle = Aggregate::parse(&mq).unwrap();
+    let paths: Vec<&str> = bundle.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["a.md", "sub/b.md"]);
+    assert_eq!(
+        bundle.extract("sub/b.md").unwrap(),
+        fs::read_to_string("example-md/ARCHITECTURE.md").unwrap()
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cli_aggregate_writes_bundle() {
+    let dir = fixture("aggregate-cli");
+    let out = mq(&dir, &["aggregate", "docs", "-o", "project.mq", "--binary"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("aggregated 2 files"));
+
+    let text = fs::read_to_string(dir.join("project.mq")).unwrap();
+    assert!(text.lines().next().unwrap().ends_with("-aggregate -zlib"));
+    let bundle = Aggregate::parse(&text).unwrap();
+    assert_eq!(
+        bundle.extract("a.md").unwrap(),
+        fs::read_to_string("example-md/QUICK_REFERENCE.md").unwrap()
+    );
+
+    let out = mq(&dir, &["decompress", "project.mq"]);
+    assert!(out.status.success());
+    let text = String::from_utf8(out.stdout).unwrap();
+    assert!(text.starts_with("::file:a.md::\n"));
+    assert!(text.contains("::file:sub/b.md::\n"));
+
+    let out = mq(&dir, &["aggregate", "docs", "--semantic"]);
+    assert!(!out.status.success());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// Run `mq` in `dir` without picking up a `.marqant.toml` from above it.
+fn mq(dir: &Path, args: &[&str]) -> Output {
+    common::mq_command(dir)
+        .arg("--no-config")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn manifest_lists_without_decoding() {
+    let files = [
+        AggregateFile {
+            path: "a:b/c.md".to_string(),
+            content: "hello".to_string(),
+            mtime: Some(1_700_000_000),
+            mode: Some(0o640),
+        },
+        AggregateFile {
+            path: "d.md".to_string(),
+            content: "world!".to_string(),
+            mtime: None,
+            mode: None,
+        },
+    ];
+    let mq = Marqant::aggregate_documents(&files, Some("-zlib")).unwrap();
+    // A body that cannot be inflated does not matter to the listing
+    let (head, _) = mq.split_once("---\n").unwrap();
+    let entries = Aggregate::list(&format!("{head}---\n!!not zlib!!")).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].path, "a:b/c.md");
+    assert_eq!(entries[0].size, Some(5));
+    assert_eq!(entries[0].mtime, Some(1_700_000_000));
+    assert_eq!(entries[0].mode, Some(0o640));
+    assert_eq!(entries[1].mode, None);
+    assert_eq!(entries[1].path, "d.md");
+    assert_eq!(entries[1].mtime, None);
+    assert_eq!(
+        Aggregate::parse(&mq).unwrap().extract("a:b/c.md").unwrap(),
+        "hello"
+    );
+
+    // Bundles without the tagged fields still parse
+    let bare = "MARQANT_V2 0 1 30 -aggregate\n::manifest::\nx.md:0:15\n::end-manifest::\n---\n::file:x.md::\ny";
+    let entries = Aggregate::list(bare).unwrap();
+    assert_eq!((entries[0].size, entries[0].mtime), (None, None));
+    assert_eq!(
+        Aggregate::parse(bare).unwrap().extract("x.md").unwrap(),
+        "y"
+    );
+}
+
+#[test]
+fn corrupt_offsets_are_errors_not_panics() {
+    let files = [AggregateFile {
+        path: "j.md".to_string(),
+        content: "# 日本語のテキスト\n\n本文です。\n".to_string(),
+        mtime: None,
+        mode: None,
+    }];
+    let text = Marqant::aggregate_documents(&files, None).unwrap();
+    assert!(text.contains("\nj.md:0:58:"), "{text}");
+    // Ends one byte into `本`
+    let corrupt = text.replace("\nj.md:0:58:", "\nj.md:0:43:");
+    let bundle = Aggregate::parse(&corrupt).unwrap();
+    let err = bundle.extract("j.md").unwrap_err().to_string();
+    assert!(err.contains("splits a character"), "{err}");
+    assert!(!verify_mq(&corrupt).is_ok());
+
+    // Offsets whose end overflows usize, in the manifest and the seek table
+    let overflow = text.replace("\nj.md:0:58:", "\nj.md:18446744073709551615:23:");
+    let err = Aggregate::parse(&overflow).unwrap_err().to_string();
+    assert!(err.contains("outside the body"), "{err}");
+    assert!(!verify_mq(&overflow).is_ok());
+    let seekable = Marqant::aggregate_documents(&files, Some("-seekable")).unwrap();
+    let (blocks, table) = seekable.split_once("::seek::\n").unwrap();
+    let (line, rest) = table.split_once('\n').unwrap();
+    let f: Vec<&str> = line.split(':').collect();
+    let rows = rest.split_once("::end-seek:").unwrap().0;
+    // The first block's tokenized offset replaced by `start`
+    let with_start = |start: &str| {
+        let line = format!("{}:{}:{start}:{}:{}:{}", f[0], f[1], f[3], f[4], f[5]);
+        let table = format!("::seek::\n{line}\n{rows}");
+        format!("{blocks}{table}::end-seek:{}::\n", table.len())
+    };
+    let bad_table = with_start("18446744073709551615");
+    let err = Aggregate::parse(&bad_table).unwrap_err().to_string();
+    assert!(err.contains("overflows"), "{err}");
+    assert!(!verify_mq(&bad_table).is_ok());
+    // A block that starts after the document does
+    let gap = with_start("1");
+    let err = Aggregate::parse(&gap).unwrap().extract("j.md").unwrap_err();
+    assert!(err.to_string().contains("does not cover"), "{err}");
+
+    let dir = common::scratch("aggregate-corrupt");
+    for (name, text) in [
+        ("j.mq", &corrupt),
+        ("o.mq", &overflow),
+        ("s.mq", &bad_table),
+        ("g.mq", &gap),
+    ] {
+        fs::write(dir.join(name), text).unwrap();
+        for args in [&["extract", name, "-O"][..], &["verify", name]] {
+            let out = mq(&dir, args);
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert_eq!(out.status.code(), Some(1), "{args:?}: {stderr}");
+            assert!(!stderr.contains("panicked"), "{stderr}");
+        }
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn cli_ls_extract_search() {
+    let dir = fixture("aggregate-extract");
+    let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(dir.join("docs/sub/b.md"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    assert!(mq(&dir, &["aggregate", "docs", "-o", "project.mq"])
+        .status
+        .success());
+
+    let out = mq(&dir, &["ls", "project.mq"]);
+    assert!(out.status.success());
+    let listing = String::from_utf8(out.stdout).unwrap();
+    assert!(listing.contains("a.md"), "{listing}");
+    assert!(listing.contains("sub/b.md"), "{listing}");
+    assert!(listing.contains("2 files"), "{listing}");
+
+    let out = mq(&dir, &["extract", "project.mq", "-C", "out"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    for (name, original) in [
+        ("a.md", "QUICK_REFERENCE.md"),
+        ("sub/b.md", "ARCHITECTURE.md"),
+    ] {
+        assert_eq!(
+            fs::read(dir.join("out").join(name)).unwrap(),
+            fs::read(Path::new("example-md").join(original)).unwrap()
+        );
+    }
+    let mtime = fs::metadata(dir.join("out/sub/b.md"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(mtime, old);
+
+    // Existing files are kept unless --force
+    assert!(!mq(&dir, &["extract", "project.mq", "-C", "out"])
+        .status
+        .success());
+    assert!(mq(&dir, &["extract", "project.mq", "-C", "out", "--force"])
+        .status
+        .success());
+
+    let out = mq(&dir, &["extract", "project.mq", "sub/b.md", "-O"]);
+    assert_eq!(out.stdout, fs::read("example-md/ARCHITECTURE.md").unwrap());
+    assert!(!mq(&dir, &["extract", "project.mq", "missing.md", "-O"])
+        .status
+        .success());
+
+    let out = mq(&dir, &["search", "project.mq", "-l", "-i", "architecture"]);
+    assert!(out.status.success());
+    let found = String::from_utf8(out.stdout).unwrap();
+    assert!(found.lines().any(|l| l == "sub/b.md"), "{found}");
+    let out = mq(&dir, &["search", "project.mq", "no such phrase anywhere"]);
+    assert_eq!(out.status.code(), Some(1));
+    // Errors exit 2 like grep, not 1 like a miss
+    for args in [
+        &["search", "missing.mq", "architecture"][..],
+        &["search", "project.mq", "architecture", "nope.md"],
+        &["search", "project.mq", "("],
+    ] {
+        let out = mq(&dir, args);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert_eq!(out.status.code(), Some(2), "{args:?}: {stderr}");
+        assert!(stderr.starts_with("error: "), "{stderr}");
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn repeated_blocks_are_stored_once() {
+    let license = "## License\n\nLicensed under the MIT license. See LICENSE for the full text, \
+                   and CONTRIBUTING.md before sending patches.\n";
+    let install = "```bash\ncargo install --path . --locked\nmq compress README.md\n```\n";
+    let files: Vec<(String, String)> = (0..4)
+        .map(|i| {
+            // The last paragraph differs only in the crate name
+            let near = format!(
+                "Every tool in this repository reads the shared configuration from \
+                 .marqant.toml, here for crate-{i}, and falls back to defaults.\n"
+            );
+            let content = format!("# Tool {i}\n\nIntro {i}.\n\n{install}\n{near}\n{license}");
+            (format!("tool{i}/README.md"), content)
+        })
+        .collect();
+    for flags in [None, Some("-zlib"), Some("-seekable:64")] {
+        let mq = Marqant::aggregate_files(&files, flags).unwrap();
+        let bundle = Aggregate::parse(&mq).unwrap();
+        for (path, content) in &files {
+            assert_eq!(&bundle.extract(path).unwrap(), content, "{path} {flags:?}");
+        }
+        assert!(verify_mq(mq.as_bytes()).is_ok(), "{flags:?}");
+
+        let shared = Aggregate::list_shared(&mq)
+            .unwrap()
+            .expect("shared segment");
+        assert_eq!(Some(&shared), bundle.shared.as_ref());
+        assert!(shared.count >= 2, "{shared:?}");
+        assert!(shared.savings > 0, "{shared:?}");
+        // The first copy of each block moves to the shared segment too
+        let entries = Aggregate::list(&mq).unwrap();
+        assert!(
+            entries.iter().all(|e| e.dedup.unwrap_or(0) > 0),
+            "{entries:?}"
+        );
+    }
+
+    // Nothing recurs: no shared segment
+    let unique = vec![("a.md".to_string(), "# A\n".to_string())];
+    let mq = Marqant::aggregate_files(&unique, None).unwrap();
+    assert_eq!(Aggregate::list_shared(&mq).unwrap(), None);
+    assert!(!mq.contains("::shared"));
+}
+
+#[test]
+fn updates_append_and_compact_reclaims() {
+    let doc = |path: &str, content: &str| AggregateFile {
+        path: path.to_string(),
+        content: content.to_string(),
+        mtime: Some(1_700_000_000),
+        mode: None,
+    };
+    let a = fs::read_to_string("example-md/QUICK_REFERENCE.md").unwrap();
+    let b = "# Notes\n\nSmart Tree installation notes. Smart Tree installation notes.\n";
+    for flags in ["", "-zlib", "-seekable:512", "-zlib -seekable:512"] {
+        let mq =
+            Marqant::aggregate_documents(&[doc("a.md", &a), doc("b.md", b)], Some(flags)).unwrap();
+
+        // Unchanged documents are not rewritten
+        let same = Marqant::update_aggregate(&mq, &[doc("b.md", b)], &[]).unwrap();
+        assert_eq!(same, mq, "{flags}");
+
+        let b2 = format!("{b}One more Smart Tree installation line.\n");
+        let c = "# C\n\nBrand new document about Smart Tree installation.\n";
+        let updated =
+            Marqant::update_aggregate(&mq, &[doc("b.md", &b2), doc("c.md", c)], &[]).unwrap();
+        // Everything already stored is kept as is, new sections go last
+        let body = |mq: &str| mq.split_once("\n---\n").unwrap().1.to_string();
+        if !flags.contains("-seekable") && !flags.contains("-zlib") {
+            assert!(body(&updated).starts_with(&body(&mq)), "{flags}");
+        }
+        let bundle = Aggregate::parse(&updated).unwrap();
+        assert_eq!(bundle.extract("a.md").unwrap(), a);
+        assert_eq!(bundle.extract("b.md").unwrap(), b2);
+        assert_eq!(bundle.extract("c.md").unwrap(), c);
+        let paths: Vec<&str> = bundle.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["a.md", "b.md", "c.md"]);
+        assert_eq!(bundle.tombstones.len(), 1);
+        assert_eq!(bundle.tombstones[0].path, "b.md");
+        assert!(verify_mq(updated.as_bytes()).is_ok(), "{flags}");
+
+        let removed = Marqant::update_aggregate(&updated, &[], &["a.md".to_string()]).unwrap();
+        let bundle = Aggregate::parse(&removed).unwrap();
+        assert!(bundle.entry("a.md").is_none());
+        assert_eq!(Aggregate::list_tombstones(&removed).unwrap().len(), 2);
+        assert_eq!(bundle.original_size as usize, b2.len() + c.len());
+        assert!(verify_mq(removed.as_bytes()).is_ok(), "{flags}");
+
+        let compact = Marqant::compact_aggregate(&removed).unwrap();
+        let bundle = Aggregate::parse(&compact).unwrap();
+        assert!(bundle.tombstones.is_empty());
+        assert_eq!(bundle.extract("b.md").unwrap(), b2);
+        assert_eq!(bundle.extract("c.md").unwrap(), c);
+        assert_eq!(bundle.entry("c.md").unwrap().mtime, Some(1_700_000_000));
+        assert!(compact.len() < removed.len(), "{flags}");
+    }
+
+    let mq = Marqant::aggregate_documents(&[doc("a.md", &a)], None).unwrap();
+    assert!(Marqant::update_aggregate(&mq, &[], &["gone.md".to_string()]).is_err());
+    // A document using one of the bundle's token chars forces a rebuild
+    let token = mq.lines().find(|l| l.contains('=')).unwrap();
+    let token = token.split_once('=').unwrap().0;
+    let odd = format!("Contains the token {token} literally\n");
+    let rebuilt = Marqant::update_aggregate(&mq, &[doc("odd.md", &odd)], &[]).unwrap();
+    let bundle = Aggregate::parse(&rebuilt).unwrap();
+    assert!(bundle.tombstones.is_empty());
+    assert_eq!(bundle.extract("odd.md").unwrap(), odd);
+    assert_eq!(bundle.extract("a.md").unwrap(), a);
+}
+
+#[test]
+fn cli_update_and_compact() {
+    let dir = fixture("update");
+    let docs = dir.join("docs");
+    assert!(mq(&dir, &["aggregate", "docs", "-o", "p.mq"])
+        .status
+        .success());
+
+    fs::write(docs.join("sub/b.md"), "# Rewritten\n").unwrap();
+    fs::remove_file(docs.join("a.md")).unwrap();
+    fs::write(docs.join("new.md"), "# New\n").unwrap();
+    let out = mq(
+        &dir,
+        &[
+            "aggregate",
+            "--update",
+            "p.mq",
+            "docs/sub/b.md",
+            "docs/a.md",
+            "docs/new.md",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let bundle = Aggregate::parse(&fs::read_to_string(dir.join("p.mq")).unwrap()).unwrap();
+    let paths: Vec<&str> = bundle.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["sub/b.md", "new.md"]);
+    assert_eq!(bundle.extract("sub/b.md").unwrap(), "# Rewritten\n");
+
+    let ls = mq(&dir, &["ls", "p.mq"]);
+    assert!(String::from_utf8_lossy(&ls.stdout).contains("2 tombstones"));
+    assert!(!mq(
+        &dir,
+        &["aggregate", "--update", "p.mq", "docs/new.md", "--binary"]
+    )
+    .status
+    .success());
+
+    assert!(mq(&dir, &["compact", "p.mq"]).status.success());
+    let text = fs::read_to_string(dir.join("p.mq")).unwrap();
+    assert!(Aggregate::list_tombstones(&text).unwrap().is_empty());
+    assert!(verify_mq(text.as_bytes()).is_ok());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn selection_honors_ignore_files_and_globs() {
+    let dir = common::scratch("select");
+    for sub in [".git", "docs/build", "docs/api", "docs/big"] {
+        fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let write = |path: &str, text: &str| fs::write(dir.join(path), text).unwrap();
+    write(".gitignore", "build/\n*.draft.md\n!keep.draft.md\n");
+    write("docs/.ignore", "/api/private.md\n");
+    for path in [
+        "docs/a.md",
+        "docs/a.draft.md",
+        "docs/keep.draft.md",
+        "docs/build/out.md",
+        "docs/api/private.md",
+        "docs/api/public.md",
+        "docs/lib.rs",
+    ] {
+        write(path, "# x\n");
+    }
+    write("docs/big/huge.md", &"y".repeat(4096));
+    fs::write(dir.join("docs/blob.bin"), [0xff, 0xfe, 0x00]).unwrap();
+
+    let names = |selection: &Selection| -> Vec<String> {
+        let found = selection.walk(&dir.join("docs")).unwrap();
+        found.into_iter().map(|(_, name)| name).collect()
+    };
+    assert_eq!(
+        names(&Selection::default()),
+        ["a.md", "api/public.md", "big/huge.md", "keep.draft.md"]
+    );
+    let no_ignore = Selection {
+        no_ignore: true,
+        ..Selection::default()
+    };
+    assert_eq!(names(&no_ignore).len(), 7);
+    let globs = Selection {
+        include: vec!["*.md".to_string(), "*.rs".to_string(), "*.bin".to_string()],
+        exclude: vec!["api".to_string()],
+        max_size: Some(1024),
+        ..Selection::default()
+    };
+    // Binaries are skipped even when a glob names them
+    assert_eq!(names(&globs), ["a.md", "keep.draft.md", "lib.rs"]);
+
+    let out = mq(
+        &dir,
+        &[
+            "aggregate",
+            "docs",
+            "--include",
+            "**/*.rs",
+            "--include",
+            "a.md",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let bundle = Aggregate::parse(&String::from_utf8(out.stdout).unwrap()).unwrap();
+    let paths: Vec<&str> = bundle.entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, ["a.md", "lib.rs"]);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn other_text_types_use_their_tables() {
+    let rust =
+        "pub fn parse(text: &str) -> Result<String> {\n    let mut out = String::new();\n    \
+                Ok(out)\n}\n"
+            .repeat(6);
+    let toml = "[package]\nname = \"x\"\nversion = \"0.1.0\"\n\n[dependencies]\nanyhow = \"1\"\n\
+                serde = { version = \"1\", features = [\"derive\"], optional = true }\n\
+                toml = { version = \"1\", optional = true }\n";
+    let tex = fs::read_to_string("example-md/MEM8_summary.tex").unwrap();
+    let files = vec![
+        ("src/lib.rs".to_string(), rust.clone()),
+        ("Cargo.toml".to_string(), toml.to_string()),
+        ("MEM8_summary.tex".to_string(), tex.clone()),
+    ];
+    let mq = Marqant::aggregate_files(&files, None).unwrap();
+    let bundle = Aggregate::parse(&mq).unwrap();
+    for (path, content) in &files {
+        assert_eq!(&bundle.extract(path).unwrap(), content, "{path}");
+    }
+    assert!(verify_mq(mq.as_bytes()).is_ok());
+    let dictionary: Vec<&str> = mq
+        .split_once("::end-manifest::\n")
+        .unwrap()
+        .1
+        .lines()
+        .take_while(|l| *l != "---")
+        .map(|l| l.split_once('=').unwrap().1)
+        .collect();
+    for pattern in ["Result<", "String", "\\mathcal{"] {
+        assert!(dictionary.contains(&pattern), "{pattern} in {dictionary:?}");
+    }
+
+    // The same text under a markdown name gets no type table
+    let as_md = Marqant::aggregate_files(&[("lib.md".to_string(), rust)], None).unwrap();
+    assert!(!as_md.contains("=Result<\n"));
+}
+
+#[test]
+fn pack_fits_the_budget_and_records_drops() {
+    let doc = |path: &str, topic: &str| AggregateFile {
+        path: path.to_string(),
+        content: format!(
+            "# {topic}\n\n{}\n",
+            format!("Notes on {topic} and how the {topic} pieces fit. ").repeat(12)
+        ),
+        mtime: None,
+        mode: None,
+    };
+    let mut repeat = doc("copy.md", "caching");
+    repeat.path = "again.md".to_string();
+    let files = vec![
+        doc("caching.md", "caching"),
+        doc("routing.md", "routing"),
+        doc("storage.md", "storage"),
+        repeat,
+    ];
+    let (_, all) = Marqant::pack(&files, 1_000_000, None, None).unwrap();
+    assert!(all.iter().all(|f| f.packed));
+    let one = all.iter().map(|f| f.tokens).max().unwrap();
+
+    let budget = one * 2 + one / 2;
+    let (mq, ranking) = Marqant::pack(&files, budget, Some("routing"), None).unwrap();
+    assert!(estimate_tokens(&mq) <= budget);
+    assert_eq!(ranking[0].path, "routing.md");
+    // A verbatim copy of a better ranked document is worth nothing
+    let again = ranking.iter().find(|f| f.path == "again.md").unwrap();
+    assert_eq!(again.novelty, 0.0);
+    assert!(!again.packed);
+
+    let bundle = Aggregate::parse(&mq).unwrap();
+    let packing = Aggregate::list_packing(&mq).unwrap().unwrap();
+    assert_eq!(packing.budget, budget);
+    for f in &ranking {
+        assert_eq!(bundle.entry(&f.path).is_some(), f.packed, "{}", f.path);
+        assert_eq!(
+            packing.dropped.contains(&(f.path.clone(), f.tokens)),
+            !f.packed,
+            "{}",
+            f.path
+        );
+        if f.packed {
+            let original = files.iter().find(|d| d.path == f.path).unwrap();
+            assert_eq!(bundle.extract(&f.path).unwrap(), original.content);
+        }
+    }
+    assert!(verify_mq(mq.as_bytes()).is_ok());
+    let compacted = Marqant::compact_aggregate(&mq).unwrap();
+    assert_eq!(Aggregate::list_packing(&compacted).unwrap(), Some(packing));
+}
+
+#[test]
+fn cli_pack() {
+    let dir = fixture("pack");
+    let out = mq(
+        &dir,
+        &[
+            "pack",
+            "docs",
+            "--budget",
+            "1500",
+            "--query",
+            "architecture",
+            "-o",
+            "c.mq",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let report = String::from_utf8_lossy(&out.stderr);
+    assert!(report.contains("(budget 1500)"), "{report}");
+    let text = fs::read_to_string(dir.join("c.mq")).unwrap();
+    assert!(estimate_tokens(&text) <= 1500);
+    let ls = String::from_utf8_lossy(&mq(&dir, &["ls", "c.mq"]).stdout).into_owned();
+    assert!(ls.contains("packed for 1500 tokens"), "{ls}");
+    assert!(ls.contains("sub/b.md (dropped)"), "{ls}");
+    assert!(!mq(&dir, &["pack", "docs", "-o", "c.mq"]).status.success());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn archives_convert_both_ways() {
+    let doc = |path: &str, content: &str, mode| AggregateFile {
+        path: path.to_string(),
+        content: content.to_string(),
+        mtime: Some(1_700_000_000),
+        mode,
+    };
+    let files = [
+        doc("docs/run.md", "# Run\n\n./run.sh 🌳\n", Some(0o755)),
+        doc("docs/a.md", "# A\n\nPlain notes.\n", None),
+    ];
+    let mq = Marqant::aggregate_documents(&files, Some("-zlib")).unwrap();
+    let bundle = Aggregate::parse(&mq).unwrap();
+    let entries: Vec<_> = bundle.entries.iter().collect();
+    for format in [ArchiveFormat::Tar, ArchiveFormat::TarGz, ArchiveFormat::Zip] {
+        let bytes = bundle.to_archive(&entries, format).unwrap();
+        assert_eq!(ArchiveFormat::detect(&bytes), Some(format));
+        let back = AggregateFile::from_archive(&bytes, &Selection::default()).unwrap();
+        assert_eq!(back.len(), 2, "{format:?}");
+        for (got, want) in back.iter().zip(&files) {
+            assert_eq!(got.path, want.path);
+            assert_eq!(got.content, want.content);
+            assert_eq!(got.mtime, want.mtime, "{format:?}");
+            assert_eq!(got.mode, Some(want.mode.unwrap_or(0o644)), "{format:?}");
+        }
+    }
+
+    // Only regular members the selection takes; a repeated path keeps its
+    // last copy
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut add = |path: &str, kind: tar::EntryType, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        tar.append_data(&mut header, path, data).unwrap();
+    };
+    add("./docs/", tar::EntryType::Directory, b"");
+    add("./docs/a.md", tar::EntryType::Regular, b"old");
+    add("./docs/logo.png", tar::EntryType::Regular, b"\x89PNG\xff");
+    add("./docs/.draft.md", tar::EntryType::Regular, b"draft");
+    add("./docs/a.md", tar::EntryType::Regular, b"new");
+    let bytes = tar.into_inner().unwrap();
+    let back = AggregateFile::from_archive(&bytes, &Selection::default()).unwrap();
+    assert_eq!(back.len(), 1);
+    assert_eq!(
+        (back[0].path.as_str(), back[0].content.as_str()),
+        ("docs/a.md", "new")
+    );
+    assert_eq!(back[0].mode, Some(0o600));
+    let all = Selection {
+        include: vec!["*".to_string()],
+        ..Selection::default()
+    };
+    assert_eq!(AggregateFile::from_archive(&bytes, &all).unwrap().len(), 1);
+    assert!(AggregateFile::from_archive(b"plain text", &all).is_err());
+}
+
+#[test]
+fn archive_members_are_bounded() {
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut add = |path: &str, mode, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        tar.append_data(&mut header, path, data).unwrap();
+    };
+    add("run.md", 0o4755, b"# Run\n");
+    add("big.md", 0o644, &[b'x'; 4096]);
+    let bytes = tar.into_inner().unwrap();
+
+    // Setuid and friends never reach the manifest
+    let back = AggregateFile::from_archive(&bytes, &Selection::default()).unwrap();
+    assert_eq!(back[0].mode, Some(0o755));
+
+    // Members over max_size are skipped on their declared size
+    let small = Selection {
+        max_size: Some(1024),
+        ..Selection::default()
+    };
+    let back = AggregateFile::from_archive(&bytes, &small).unwrap();
+    assert_eq!(back.len(), 1);
+    assert_eq!(back[0].path, "run.md");
+}
+
+#[test]
+fn cli_tar_roundtrip() {
+    let dir = fixture("tar");
+    assert!(mq(&dir, &["aggregate", "docs", "-o", "p.mq"])
+        .status
+        .success());
+    let out = mq(&dir, &["extract", "p.mq", "--to-tar", "p.tar.gz"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let bytes = fs::read(dir.join("p.tar.gz")).unwrap();
+    assert_eq!(ArchiveFormat::detect(&bytes), Some(ArchiveFormat::TarGz));
+    assert!(mq(&dir, &["extract", "p.mq", "sub", "--to-zip", "p.zip"])
+        .status
+        .success());
+
+    assert!(
+        mq(&dir, &["aggregate", "--from-tar", "p.tar.gz", "-o", "q.mq"])
+            .status
+            .success()
+    );
+    let p = Aggregate::parse(&fs::read_to_string(dir.join("p.mq")).unwrap()).unwrap();
+    let q = Aggregate::parse(&fs::read_to_string(dir.join("q.mq")).unwrap()).unwrap();
+    assert_eq!(q.entries.len(), p.entries.len());
+    for (a, b) in p.entries.iter().zip(&q.entries) {
+        assert_eq!(a.path, b.path);
+        assert_eq!(a.mtime, b.mtime);
+        assert_eq!(p.extract(&a.path).unwrap(), q.extract(&b.path).unwrap());
+    }
+    assert!(
+        mq(&dir, &["aggregate", "--from-zip", "p.zip", "-o", "z.mq"])
+            .status
+            .success()
+    );
+    let z = Aggregate::list(&fs::read_to_string(dir.join("z.mq")).unwrap()).unwrap();
+    assert_eq!(z.len(), 1);
+    assert_eq!(z[0].path, "sub/b.md");
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,74 @@
+use marqant::{analyze_markdown, Marqant};
+
+const DOC: &str = "Intro text before any heading.\n\n\
+# Install\n\nRun the installer script now.\n\n- step one\n- step two\n- step three\n\n\
+## Usage\n\nRun the installer script now, then run the installer script again.\n\n\
+```bash\n# not a section\nmq compress docs/\n```\n\n\
+## Notes\n\nRun the installer script now if unsure.\n";
+
+#[test]
+fn analyze_mode_sizes_match_compressed_output() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let a = analyze_markdown(DOC).expect("analyze");
+    assert_eq!(a.original_size, DOC.len());
+    let names: Vec<&str> = a.modes.iter().map(|m| m.mode.as_str()).collect();
+    assert_eq!(names, ["plain", "zlib", "std", "semantic"]);
+    for m in &a.modes {
+        let out = Marqant::compress_markdown_with_flags(DOC, m.flags.as_deref()).unwrap();
+        assert_eq!(m.size, out.len(), "{}", m.mode);
+        assert_eq!(m.savings, DOC.len() as isize - out.len() as isize);
+    }
+    let best = a.modes.iter().min_by_key(|m| m.size).unwrap();
+    assert_eq!(a.best_mode, best.mode);
+}
+
+#[test]
+fn analyze_token_contributions_add_up() {
+    let a = analyze_markdown(DOC).expect("analyze");
+    let (tokens, tokenized) = Marqant::tokenize_content(DOC);
+    assert_eq!(a.tokens, tokens.len());
+    assert_eq!(a.tokenized_size, tokenized.len());
+    assert_eq!(a.phrases.len(), tokens.len());
+    assert_eq!(a.static_tokens.count + a.dynamic_tokens.count, tokens.len());
+    assert!(a.phrases.windows(2).all(|w| w[0].savings >= w[1].savings));
+    assert!(a
+        .phrases
+        .iter()
+        .any(|p| p.dynamic && p.pattern.contains("installer script")));
+
+    // Body savings less one dictionary line (`k=v\n` plus the 3 counted) per entry
+    let dict: isize = tokens
+        .iter()
+        .map(|(k, v)| (k.len() + v.len() + 3) as isize)
+        .sum();
+    assert_eq!(
+        a.static_tokens.savings + a.dynamic_tokens.savings,
+        a.savings - dict
+    );
+}
+
+#[test]
+fn analyze_sections_cover_the_document() {
+    let a = analyze_markdown(DOC).expect("analyze");
+    let titles: Vec<&str> = a.sections.iter().map(|s| s.title.as_str()).collect();
+    assert_eq!(titles, ["", "Install", "Usage", "Notes"]);
+    let original: usize = a.sections.iter().map(|s| s.original_size).sum();
+    let tokenized: usize = a.sections.iter().map(|s| s.tokenized_size).sum();
+    assert_eq!(original, DOC.len());
+    assert_eq!(tokenized, a.tokenized_size);
+}
+
+#[test]
+fn analyze_warns_about_token_collisions() {
+    let clean = analyze_markdown(DOC).expect("analyze");
+    assert!(clean.warnings.is_empty(), "{:?}", clean.warnings);
+
+    let doc = format!("{DOC}\u{6}\u{1B}\u{1F}\u{7F}\u{E000}\n");
+    let a = analyze_markdown(&doc).expect("analyze");
+    assert!(
+        a.warnings.iter().any(|w| w.contains("U+0006")),
+        "{:?}",
+        a.warnings
+    );
+    assert!(a.warnings.iter().any(|w| w.starts_with("4 dynamic token")));
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+use common::mq;
+
+fn fixture(name: &str) -> PathBuf {
+    let dir = common::scratch(name);
+    fs::create_dir_all(dir.join("docs/guide")).unwrap();
+    fs::create_dir_all(dir.join("docs/.git")).unwrap();
+    for (path, src) in [
+        ("docs/ARCHITECTURE.md", "example-md/ARCHITECTURE.md"),
+        (
+            "docs/guide/QUICK_REFERENCE.md",
+            "example-md/QUICK_REFERENCE.md",
+        ),
+        ("docs/.git/HIDDEN.md", "example-md/QUICK_REFERENCE.md"),
+        ("single.md", "example-md/M8_UNIFIED_FORMAT.md"),
+    ] {
+        fs::copy(src, dir.join(path)).unwrap();
+    }
+    fs::write(dir.join("docs/notes.txt"), "not markdown").unwrap();
+    dir
+}
+
+#[test]
+fn batch_compress_mirrors_tree_and_skips_up_to_date() {
+    let dir = fixture("batch");
+    let out = mq(
+        &dir,
+        &["compress", "docs", "single.md", "--output-dir", "out"],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let table = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        table.contains("total (3 written, 0 skipped, 0 failed)"),
+        "{table}"
+    );
+    assert!(dir.join("out/ARCHITECTURE.mq").is_file());
+    assert!(dir.join("out/guide/QUICK_REFERENCE.mq").is_file());
+    assert!(dir.join("out/single.mq").is_file());
+    assert!(!dir.join("out/.git").exists());
+    assert!(!dir.join("out/notes.mq").exists());
+
+    // Second run: every output is newer than its input
+    let again = mq(
+        &dir,
+        &["compress", "docs", "single.md", "--output-dir", "out"],
+    );
+    let table = String::from_utf8_lossy(&again.stdout);
+    assert!(
+        table.contains("total (0 written, 3 skipped, 0 failed)"),
+        "{table}"
+    );
+    let forced = mq(
+        &dir,
+        &[
+            "compress",
+            "docs",
+            "--output-dir",
+            "out",
+            "--force",
+            "-j",
+            "1",
+        ],
+    );
+    let table = String::from_utf8_lossy(&forced.stdout);
+    assert!(
+        table.contains("total (2 written, 0 skipped, 0 failed)"),
+        "{table}"
+    );
+
+    // And back again, byte for byte
+    let back = mq(&dir, &["decompress", "out", "--output-dir", "back"]);
+    assert!(
+        back.status.success(),
+        "{}",
+        String::from_utf8_lossy(&back.stderr)
+    );
+    for (orig, restored) in [
+        ("docs/ARCHITECTURE.md", "back/ARCHITECTURE.md"),
+        (
+            "docs/guide/QUICK_REFERENCE.md",
+            "back/guide/QUICK_REFERENCE.md",
+        ),
+        ("single.md", "back/single.md"),
+    ] {
+        assert_eq!(
+            fs::read(dir.join(orig)).unwrap(),
+            fs::read(dir.join(restored)).unwrap(),
+            "{orig}"
+        );
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn batch_reports_failures_and_keeps_going() {
+    let dir = fixture("batch-fail");
+    fs::write(dir.join("docs/broken.md"), b"\xff\xfe not utf-8").unwrap();
+    let out = mq(&dir, &["compress", "docs"]);
+    assert!(!out.status.success());
+    let table = String::from_utf8_lossy(&out.stdout);
+    assert!(table.contains("error: input is not UTF-8 text"), "{table}");
+    assert!(
+        table.contains("total (2 written, 0 skipped, 1 failed)"),
+        "{table}"
+    );
+    // Without --output-dir, outputs land next to their inputs
+    assert!(dir.join("docs/guide/QUICK_REFERENCE.mq").is_file());
+
+    let refused = mq(&dir, &["decompress", "docs"]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--output-dir"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn batch_rejects_outputs_that_collide() {
+    let dir = fixture("batch-collide");
+    fs::create_dir_all(dir.join("other")).unwrap();
+    fs::copy(
+        dir.join("docs/ARCHITECTURE.md"),
+        dir.join("other/ARCHITECTURE.md"),
+    )
+    .unwrap();
+    let out = mq(
+        &dir,
+        &[
+            "compress",
+            "docs/ARCHITECTURE.md",
+            "other/ARCHITECTURE.md",
+            "--output-dir",
+            "out",
+        ],
+    );
+    assert!(!out.status.success());
+    let err = String::from_utf8_lossy(&out.stderr);
+    assert!(err.contains("would both be written to"), "{err}");
+    assert!(!dir.join("out").exists());
+
+    // The same file reached twice is still one job
+    let out = mq(
+        &dir,
+        &[
+            "compress",
+            "docs",
+            "docs/ARCHITECTURE.md",
+            "--output-dir",
+            "out",
+        ],
+    );
+    let table = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        table.contains("total (2 written, 0 skipped, 0 failed)"),
+        "{table}"
+    );
+    fs::remove_dir_all(&dir).ok();
+}
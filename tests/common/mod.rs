@@ -0,0 +1,30 @@
+// Helpers shared by the CLI integration tests. Each test crate uses a
+// different subset.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The `mq` binary built for this test run, started in `dir`.
+pub fn mq_command(dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_mq"));
+    cmd.current_dir(dir);
+    cmd
+}
+
+/// Run `mq <args>` in `dir`.
+pub fn mq(dir: &Path, args: &[&str]) -> Output {
+    mq_command(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run mq")
+}
+
+/// A fresh, empty `mq-<name>-<pid>` directory under the system temp dir.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mq-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,196 @@
+#![cfg(feature = "config")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use marqant::config::{Compression, Config};
+use marqant::dns::Resolver;
+use marqant::{DecodeOptions, Marqant};
+
+mod common;
+
+const CONFIG: &str = r#"
+[compression]
+default_binary = true
+default_semantic = true
+std = "std-static-v1"
+
+[dictionaries]
+resolvers = ["dns", "local"]
+
+[output]
+dir = ".mq-cache"
+ignore = ["drafts", "*.tmp.md"]
+
+[performance]
+parallel_threads = 2
+
+[[override]]
+glob = "CHANGELOG.md"
+binary = false
+
+[[override]]
+glob = "legacy/**"
+std = ""
+"#;
+
+#[test]
+fn config_parses_and_applies_overrides() {
+    let config = Config::parse(CONFIG, Path::new("/repo/.marqant.toml")).unwrap();
+    assert_eq!(config.resolvers, Some(vec![Resolver::Dns, Resolver::Local]));
+    assert_eq!(config.output_dir, Some(PathBuf::from("/repo/.mq-cache")));
+    assert_eq!(config.jobs, Some(2));
+
+    let defaults = config.compression_for(Path::new("/repo/docs/guide.md"));
+    assert_eq!(
+        defaults.flags().as_deref(),
+        Some("-zlib -semantic -std:std-static-v1")
+    );
+    let changelog = config.compression_for(Path::new("/repo/CHANGELOG.md"));
+    assert_eq!(
+        changelog,
+        Compression {
+            binary: false,
+            semantic: true,
+            std: Some("std-static-v1".into()),
+        }
+    );
+    let legacy = config.compression_for(Path::new("/repo/legacy/a/old.md"));
+    assert_eq!(legacy.flags().as_deref(), Some("-zlib -semantic"));
+
+    assert!(config.is_ignored(Path::new("/repo/docs/drafts/idea.md")));
+    assert!(config.is_ignored(Path::new("/repo/notes.tmp.md")));
+    assert!(!config.is_ignored(Path::new("/repo/docs/guide.md")));
+}
+
+#[test]
+fn config_rejects_unknown_keys_and_resolvers() {
+    let path = Path::new(".marqant.toml");
+    assert!(Config::parse("[compression]\ndefault_zlib = true\n", path).is_err());
+    assert!(Config::parse("[dictionaries]\nresolvers = [\"ftp\"]\n", path).is_err());
+    assert!(Config::parse("", path).is_ok());
+}
+
+#[test]
+fn decode_options_carry_the_resolver_order() {
+    let mq =
+        Marqant::compress_markdown_with_flags("# Title\n\n- a\n- b\n", Some("-std:std-static-v1"))
+            .unwrap();
+    let config = Config::parse(CONFIG, Path::new("/p/.marqant.toml")).unwrap();
+    let options = config.decode_options();
+    assert_eq!(
+        options.resolvers,
+        Some(vec![Resolver::Dns, Resolver::Local])
+    );
+
+    // No resolvers at all: the built-in dictionary is out of reach, while
+    // the default order still finds it
+    let none = DecodeOptions {
+        resolvers: Some(Vec::new()),
+    };
+    if std::env::var_os("MARQANT_RESOLVERS").is_none() {
+        assert!(Marqant::decompress_marqant_with_options(&mq, &none).is_err());
+    }
+    assert_eq!(
+        Marqant::decompress_marqant_with_options(&mq, &DecodeOptions::default()).unwrap(),
+        Marqant::decompress_marqant(&mq).unwrap()
+    );
+}
+
+fn mq(dir: &Path, args: &[&str]) -> Output {
+    common::mq_command(dir)
+        .args(args)
+        .env("MARQANT_TEST_TS", "0")
+        .env_remove("MARQANT_RESOLVERS")
+        // `false` exits non-zero: the DNS resolver finds nothing
+        .env("MQ_DIG_CMD", "false")
+        .output()
+        .expect("Failed to run mq")
+}
+
+#[test]
+fn cli_reads_config_from_parent_dirs() {
+    let dir = common::scratch("config");
+    fs::create_dir_all(dir.join("docs/drafts")).unwrap();
+    fs::write(dir.join(".marqant.toml"), CONFIG).unwrap();
+    let md = fs::read_to_string("example-md/QUICK_REFERENCE.md").unwrap();
+    for name in ["docs/guide.md", "docs/CHANGELOG.md", "docs/drafts/idea.md"] {
+        fs::write(dir.join(name), &md).unwrap();
+    }
+
+    let out = mq(&dir.join("docs"), &["compress", "."]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let header = |name: &str| {
+        let mq = fs::read_to_string(dir.join(".mq-cache").join(name)).unwrap();
+        mq.lines().next().unwrap().to_string()
+    };
+    assert!(header("guide.mq").ends_with(" -zlib -semantic -std:std-static-v1"));
+    assert!(header("CHANGELOG.mq").ends_with(" -semantic -std:std-static-v1"));
+    assert!(!dir.join(".mq-cache/drafts").exists());
+
+    // CLI switches win over the config; --no-config drops it
+    let out = mq(&dir.join("docs"), &["compress", "guide.md"]);
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.starts_with("MARQANT 0 4703 "));
+    assert!(text.lines().next().unwrap().ends_with("-std:std-static-v1"));
+    let out = mq(
+        &dir.join("docs"),
+        &["compress", "--no-binary", "--no-std", "guide.md"],
+    );
+    let flags = |out: &Output| -> Vec<String> {
+        let text = String::from_utf8_lossy(&out.stdout);
+        text.lines()
+            .next()
+            .unwrap()
+            .split(' ')
+            .skip(4)
+            .map(String::from)
+            .collect()
+    };
+    assert_eq!(flags(&out), ["-semantic"]);
+    let out = mq(
+        &dir.join("docs"),
+        &[
+            "compress",
+            "--no-semantic",
+            "--std",
+            "std-static-v1",
+            "guide.md",
+        ],
+    );
+    assert_eq!(flags(&out), ["-zlib", "-std:std-static-v1"]);
+    let out = mq(&dir.join("docs"), &["compress", "--no-config", "guide.md"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .next()
+            .unwrap()
+            .split(' ')
+            .count(),
+        4
+    );
+
+    // Resolver order comes from the config: DNS only cannot find the
+    // built-in dictionary, the default order can
+    fs::write(
+        dir.join(".marqant.toml"),
+        "[dictionaries]\nresolvers = [\"dns\"]\n",
+    )
+    .unwrap();
+    let out = mq(&dir, &["decompress", ".mq-cache/guide.mq"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unresolvable standard dict"));
+    let out = mq(&dir, &["decompress", "--no-config", ".mq-cache/guide.mq"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&out.stdout), md);
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,95 @@
+use marqant::semantic::SemanticEncoder;
+use marqant::{detect_format, mq2_uni_encode, Format, Marqant};
+
+const DOC: &str = "# Title\n\n## Setup\n\n- Run the installer.\n- Run the installer again.\n";
+
+fn mq2_payload() -> Vec<u8> {
+    let dict =
+        mq2_uni::Dictionary::new(vec![(0xC0, b"\n\n".to_vec()), (0xC1, b"## ".to_vec())]).unwrap();
+    mq2_uni::encode(DOC.as_bytes(), &dict, "0")
+}
+
+#[test]
+fn detect_format_by_magic() {
+    let marqant = Marqant::compress_markdown(DOC).unwrap();
+    let smq = SemanticEncoder::to_bytes(&SemanticEncoder::encode("Alexandra is learning Rust"));
+    assert_eq!(detect_format(marqant.as_bytes()), Format::Marqant);
+    assert_eq!(detect_format(&mq2_payload()), Format::Mq2);
+    assert_eq!(
+        detect_format(&mq2_uni_encode(DOC.as_bytes()).unwrap()),
+        Format::Mq2UniBare
+    );
+    assert_eq!(detect_format(&smq), Format::Semantic);
+    assert_eq!(detect_format(b"MQB\x02\0\0\0\0"), Format::Mqb);
+    assert_eq!(detect_format(b"MEM8\x01"), Format::M8);
+    assert_eq!(detect_format(b"plain text"), Format::Unknown);
+    assert_eq!(detect_format(b""), Format::Unknown);
+}
+
+#[test]
+fn detect_format_needs_full_magic_and_canonical_uni() {
+    assert_eq!(detect_format(b"SMQL is a query language"), Format::Unknown);
+    assert_eq!(detect_format(b"MQB files"), Format::Unknown);
+    // Markdown that writes out a UNI escape but keeps its own blank lines
+    let prose = b"# Escapes\n\nUNI writes a tab as `~TB`.\n";
+    assert_eq!(detect_format(prose), Format::Unknown);
+    let mut bare = mq2_uni_encode(DOC.as_bytes()).unwrap();
+    assert_eq!(detect_format(&bare), Format::Mq2UniBare);
+    bare.extend_from_slice(b"\n\nmore");
+    assert_eq!(detect_format(&bare), Format::Unknown);
+}
+
+#[test]
+fn decompress_auto_dispatches() {
+    for flags in [None, Some("-zlib -semantic")] {
+        let mq = Marqant::compress_markdown_with_flags(DOC, flags).unwrap();
+        let out = Marqant::decompress_auto(mq.as_bytes()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().trim_end(), DOC.trim_end());
+    }
+    assert_eq!(
+        Marqant::decompress_auto(&mq2_payload()).unwrap(),
+        DOC.as_bytes()
+    );
+    let bare = mq2_uni_encode(DOC.as_bytes()).unwrap();
+    assert_eq!(Marqant::decompress_auto(&bare).unwrap(), DOC.as_bytes());
+
+    let units = SemanticEncoder::encode("Alexandra is learning Rust with Claude!");
+    let english = Marqant::decompress_auto(&SemanticEncoder::to_bytes(&units)).unwrap();
+    let english = String::from_utf8(english).unwrap();
+    assert!(
+        english.contains("Alexandra") && english.contains("Rust"),
+        "{english}"
+    );
+
+    let err = Marqant::decompress_auto(b"MQB\x02")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("MQB"), "{err}");
+    let err = Marqant::decompress_auto(b"just markdown")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unrecognized input"), "{err}");
+}
+
+#[test]
+fn cli_decompress_autodetects_mq2() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut decode = Command::new(env!("CARGO_BIN_EXE_mq"))
+        .arg("decompress")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mq decompress");
+    decode
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&mq2_payload())
+        .unwrap();
+    let out = decode
+        .wait_with_output()
+        .expect("Failed to run mq decompress");
+    assert!(out.status.success());
+    assert_eq!(out.stdout, DOC.as_bytes());
+}
@@ -0,0 +1,95 @@
+use std::process::Command;
+
+use marqant::{diff_mq, DictChange, Marqant};
+
+const OLD: &str =
+    "# Notes\n\nhello world again\nhello world again\nfoo bar baz qux\nfoo bar baz qux\n";
+const NEW: &str =
+    "# Notes\n\nhello world again\nhello world again\nsome other phrase\nsome other phrase\n";
+
+fn compress(md: &str, flags: Option<&str>) -> String {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    Marqant::compress_markdown_with_flags(md, flags).expect("compress")
+}
+
+#[test]
+fn diff_reports_text_header_and_dictionary() {
+    let (old, new) = (compress(OLD, None), compress(NEW, Some("-zlib")));
+    let diff = diff_mq(old.as_bytes(), new.as_bytes(), "a.mq", "b.mq").unwrap();
+
+    assert!(
+        diff.text
+            .starts_with("--- a.mq\n+++ b.mq\n@@ -2,5 +2,5 @@\n"),
+        "{}",
+        diff.text
+    );
+    assert!(diff.text.contains("\n-foo bar baz qux\n"));
+    assert!(diff.text.contains("\n+some other phrase\n"));
+
+    let fields: Vec<&str> = diff.header.iter().map(|c| c.field.as_str()).collect();
+    assert_eq!(fields, ["original_size", "compressed_size", "flags"]);
+    assert_eq!(diff.header[2].new.as_deref(), Some("-zlib"));
+
+    assert!(diff.dictionary.iter().any(|c| matches!(
+        c,
+        DictChange::Removed { pattern, .. } if pattern == b"foo bar baz qux"
+    )));
+    assert!(diff.dictionary.iter().any(|c| matches!(
+        c,
+        DictChange::Added { pattern, .. } if pattern == b"some other phrase"
+    )));
+}
+
+#[test]
+fn diff_matches_tokens_by_pattern() {
+    // Same phrase, but another token char is taken by the text itself
+    let old = compress(OLD, None);
+    let new = compress(&format!("{OLD}\u{1C}\n"), None);
+    let diff = diff_mq(old.as_bytes(), new.as_bytes(), "a", "b").unwrap();
+    let reassigned: Vec<_> = diff
+        .dictionary
+        .iter()
+        .filter_map(|c| match c {
+            DictChange::Reassigned { old_id, new_id, .. } => Some((old_id, new_id)),
+            _ => None,
+        })
+        .collect();
+    assert!(!reassigned.is_empty(), "{:?}", diff.dictionary);
+    assert!(reassigned.iter().all(|(a, b)| a != b));
+    assert!(!diff
+        .dictionary
+        .iter()
+        .any(|c| matches!(c, DictChange::Removed { .. })));
+
+    let same = diff_mq(old.as_bytes(), old.as_bytes(), "a", "b").unwrap();
+    assert!(same.is_empty());
+}
+
+#[test]
+fn diff_git_driver_handles_added_files() {
+    let dir = std::env::temp_dir().join(format!("mq-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let new = dir.join("new.mq");
+    std::fs::write(&new, compress(NEW, None)).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_mq"))
+        .args(["diff", "--git", "docs/x.mq", "/dev/null", "."])
+        .args(["."])
+        .arg(&new)
+        .args(["abc123", "100644"])
+        .output()
+        .expect("Failed to run mq");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.starts_with("mq diff a/docs/x.mq b/docs/x.mq\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("kind: (none) -> MARQANT"));
+    assert!(stdout.contains("--- a/docs/x.mq\n+++ b/docs/x.mq\n@@ -0,0 +1,6 @@\n+# Notes\n"));
+    std::fs::remove_dir_all(&dir).ok();
+}
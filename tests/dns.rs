@@ -1,16 +1,25 @@
 use marqant::dns::resolve_dns_dict;
 use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
 
-fn mock_dig(exit_code: i32, stdout: &str) {
+// MQ_DIG_CMD is process-global, so tests that mock dig must not overlap
+static DIG_LOCK: Mutex<()> = Mutex::new(());
+
+fn mock_dig(exit_code: i32, stdout: &str) -> MutexGuard<'static, ()> {
+    let guard = DIG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     let script = format!("#!/bin/sh\necho '{}'\nexit {}", stdout, exit_code);
     let path = "target/debug/mock_dig";
-    std::fs::write(path, script).unwrap();
+    // Write to a temp name and rename, so a previous exec never sees a half-written file
+    let tmp = format!("{path}.tmp");
+    std::fs::write(&tmp, script).unwrap();
+    std::fs::rename(&tmp, path).unwrap();
     std::process::Command::new("chmod")
         .arg("+x")
         .arg(path)
         .status()
         .unwrap();
     std::env::set_var("MQ_DIG_CMD", path);
+    guard
 }
 
 #[test]
@@ -18,7 +27,7 @@ fn resolve_dns_success() {
     let b64_key1 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, "\x01");
     let b64_val1 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, "# ");
     let mock_stdout = format!("\"{}={}\"", b64_key1, b64_val1);
-    mock_dig(0, &mock_stdout);
+    let _dig = mock_dig(0, &mock_stdout);
     let dict = resolve_dns_dict("test-ok").unwrap().unwrap();
     let mut expected = HashMap::new();
     expected.insert("\x01".to_string(), "# ".to_string());
@@ -27,13 +36,13 @@ fn resolve_dns_success() {
 
 #[test]
 fn resolve_dns_nxdomain() {
-    mock_dig(1, ""); // dig returns non-zero for NXDOMAIN
+    let _dig = mock_dig(1, ""); // dig returns non-zero for NXDOMAIN
     let dict = resolve_dns_dict("test-nx").unwrap();
     assert!(dict.is_none());
 }
 
 #[test]
 fn resolve_dns_invalid_pair() {
-    mock_dig(0, "\"invalid-pair\"");
+    let _dig = mock_dig(0, "\"invalid-pair\"");
     assert!(resolve_dns_dict("test-invalid").is_err());
 }
@@ -1,19 +1,18 @@
 use std::fs;
 use std::io::Write;
-use std::path::Path;
 use std::process::{Command, Stdio};
 
+// Emoji-heavy documents, round-tripped by both the CLI and mq2-uni tests
+const TEST_CASES: [(&str, &str); 4] = [
+    ("simple_emoji", "Hello 👋 World! 🌍"),
+    ("markdown_with_emoji", "# Title 🎯\n\n- Item 1 ✅\n- Item 2 ❌\n- Item 3 🚀"),
+    ("complex_unicode", "Emotions: 😀 😢 😡 🥰\nSymbols: ♠️ ♣️ ♥️ ♦️\nArrows: ↑ → ↓ ←"),
+    ("mixed_content", "## Section 📊\n\nThis is **bold** and _italic_ with emoji 🎨\n\n```rust\nfn main() { println!(\"🦀\"); }\n```"),
+];
+
 #[test]
 fn test_emoji_preservation() {
-    // Test that emojis are preserved through encoding/decoding
-    let test_cases = vec![
-        ("simple_emoji", "Hello 👋 World! 🌍"),
-        ("markdown_with_emoji", "# Title 🎯\n\n- Item 1 ✅\n- Item 2 ❌\n- Item 3 🚀"),
-        ("complex_unicode", "Emotions: 😀 😢 😡 🥰\nSymbols: ♠️ ♣️ ♥️ ♦️\nArrows: ↑ → ↓ ←"),
-        ("mixed_content", "## Section 📊\n\nThis is **bold** and _italic_ with emoji 🎨\n\n```rust\nfn main() { println!(\"🦀\"); }\n```"),
-    ];
-
-    for (name, content) in test_cases {
+    for (name, content) in TEST_CASES {
         println!("\n=== Testing: {} ===", name);
         println!("Original: {}", content);
 
@@ -112,3 +111,39 @@ fn test_byte_level_emoji() {
     assert_eq!(bytes, decoded.as_slice(), "Byte-level round-trip failed");
     assert_eq!(test_emoji, decoded_str, "String round-trip failed");
 }
+
+#[test]
+fn test_mq2_uni_crate_byte_tokens_preserve_emoji() {
+    // Token ids 0x80.. collide with UTF-8 continuation bytes; the escape byte
+    // keeps them from being expanded inside multibyte characters
+    use mq2_uni::{decode, encode, Dictionary, UTF8_SAFE_TOKENS};
+
+    let patterns: [&[u8]; 4] = [b"# ", b"\n\n", b"\n- ", b"```"];
+    let colliding = Dictionary::new(
+        (0x80..)
+            .zip(patterns)
+            .map(|(t, p)| (t, p.to_vec()))
+            .collect(),
+    )
+    .unwrap();
+    let safe = Dictionary::new(
+        UTF8_SAFE_TOKENS
+            .into_iter()
+            .zip(patterns)
+            .map(|(t, p)| (t, p.to_vec()))
+            .collect(),
+    )
+    .unwrap();
+
+    for (_, content) in TEST_CASES {
+        for dict in [&colliding, &safe] {
+            let encoded = encode(content.as_bytes(), dict, "0");
+            let decoded = decode(&encoded).expect("decode");
+            assert_eq!(
+                String::from_utf8(decoded).expect("valid UTF-8"),
+                content,
+                "mq2-uni round-trip failed for {content:?}"
+            );
+        }
+    }
+}
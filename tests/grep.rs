@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::PathBuf;
+
+use marqant::Marqant;
+
+mod common;
+use common::mq;
+
+fn archive(name: &str) -> PathBuf {
+    let dir = common::scratch(name);
+    fs::create_dir_all(dir.join("docs/sub")).unwrap();
+    let docs = [
+        (
+            "docs/a.mq",
+            "# Alpha\n\none\ntwo\nthree needle\nfour\nfive\nsix\nseven\nNeedle eight\n",
+            None,
+        ),
+        ("docs/sub/b.mq", "# Beta\n\nno match here\n", Some("-zlib")),
+    ];
+    for (path, md, flags) in docs {
+        let mq = Marqant::compress_markdown_with_flags(md, flags).unwrap();
+        fs::write(dir.join(path), mq).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn cat_decodes_to_stdout() {
+    let dir = archive("cat");
+    let out = mq(&dir, &["cat", "docs/a.mq", "docs/sub/b.mq"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let text = String::from_utf8(out.stdout).unwrap();
+    assert!(text.starts_with("# Alpha\n\none\n"));
+    assert!(text.ends_with("# Beta\n\nno match here\n"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn grep_prints_matches_with_context() {
+    let dir = archive("grep");
+    let out = mq(&dir, &["grep", "needle", "docs"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "docs/a.mq:5:three needle\n"
+    );
+
+    let out = mq(&dir, &["grep", "-i", "-C", "1", "needle", "docs"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "docs/a.mq-4-two\ndocs/a.mq:5:three needle\ndocs/a.mq-6-four\n--\n\
+         docs/a.mq-9-seven\ndocs/a.mq:10:Needle eight\n"
+    );
+
+    let out = mq(&dir, &["grep", "-c", "-F", "o m", "docs"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "docs/a.mq:0\ndocs/sub/b.mq:1\n"
+    );
+    let out = mq(&dir, &["grep", "-l", "e", "docs/sub/b.mq", "docs/a.mq"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "docs/sub/b.mq\ndocs/a.mq\n"
+    );
+
+    let none = mq(&dir, &["grep", "absent", "docs"]);
+    assert_eq!(none.status.code(), Some(1));
+    assert!(none.stdout.is_empty());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn grep_errors_exit_2() {
+    let dir = archive("grep-errors");
+    fs::write(dir.join("docs/broken.mq"), b"\xff\xfe not mq").unwrap();
+    // A match elsewhere does not hide the undecodable file
+    let out = mq(&dir, &["grep", "needle", "docs"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("docs/a.mq:5:three needle"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("docs/broken.mq"));
+
+    for args in [
+        &["grep", "needle", "missing.mq"][..],
+        &["grep", "(", "docs/a.mq"],
+        &["grep", "--bogus", "needle"],
+    ] {
+        let out = mq(&dir, args);
+        assert_eq!(out.status.code(), Some(2), "{args:?}");
+    }
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "serde")]
+
+use marqant::novelty::NoveltyTracker;
+use marqant::semantic::SemanticEncoder;
+use marqant::{read_mq_metadata, Marqant, MqInfo};
+
+fn mq(args: &[&str], stdin: &[u8]) -> serde_json::Value {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mq"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mq");
+    child.stdin.as_mut().unwrap().write_all(stdin).unwrap();
+    let out = child.wait_with_output().expect("Failed to run mq");
+    assert!(out.status.success(), "mq {args:?} failed");
+    serde_json::from_slice(&out.stdout).expect("json output")
+}
+
+#[test]
+fn mq_info_roundtrips_through_json() {
+    let dict =
+        mq2_uni::Dictionary::new(vec![(0xC0, b"\n\n".to_vec()), (0x41, b"## ".to_vec())]).unwrap();
+    let payload = mq2_uni::encode(b"## a\n\nb", &dict, "0");
+    let info = read_mq_metadata(&payload).expect("meta");
+    let json = serde_json::to_value(&info).unwrap();
+    // UTF-8 bytes read as strings, anything else as a byte array
+    assert_eq!(json["tokens"][0]["id"], "A");
+    assert_eq!(json["tokens"][0]["pattern"], "## ");
+    assert_eq!(json["tokens"][1]["id"], serde_json::json!([0xC0]));
+    let back: MqInfo = serde_json::from_value(json).unwrap();
+    assert_eq!(back, info);
+}
+
+#[test]
+fn novelty_and_semantic_types_serialize() {
+    let units = SemanticEncoder::encode("Alexandra is learning Rust with Claude");
+    assert!(!units.is_empty());
+    let mut tracker = NoveltyTracker::new();
+    let score = tracker.calculate_novelty(&units);
+    let json = serde_json::to_string(&score).unwrap();
+    assert!(json.contains("\"classification\""));
+    let back: marqant::novelty::NoveltyScore = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.classification, score.classification);
+
+    let json = serde_json::to_string(&units[0]).unwrap();
+    let back: marqant::semantic::SemanticUnit = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.tokens, units[0].tokens);
+}
+
+#[test]
+fn cli_json_output() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let md = "# Title\n\n## Setup\n\nRun the installer.\n\n## Usage\n\nRun the installer.\n";
+    let compressed = Marqant::compress_markdown(md).expect("compress");
+
+    let info = mq(&["inspect", "--json"], compressed.as_bytes());
+    assert_eq!(info["kind"], "MARQANT");
+    assert_eq!(info["original_size"], md.len());
+    assert!(!info["tokens"].as_array().unwrap().is_empty());
+
+    let analysis = mq(&["analyze", "--json"], md.as_bytes());
+    assert_eq!(analysis["original_size"], md.len());
+    assert!(analysis["tokens"].as_u64().unwrap() > 0);
+
+    let id = mq(&["dict-id", "--uni", "--json"], b"");
+    assert_eq!(id["dict_id"], marqant::MQ2_UNI_DICT_ID);
+}
@@ -0,0 +1,210 @@
+use std::fs;
+use std::process::Command;
+
+use marqant::m8::{self, MarqantSection, M8};
+use marqant::{verify_mq, Marqant, ProjectIdentity, Selection};
+
+const DOC: &str = "# Title\n\n## Setup\n\n- Run the installer.\n- Run the installer again.\n";
+
+#[test]
+fn container_roundtrips_sections() {
+    let mut container = M8::new();
+    container.push_markdown(DOC, None).unwrap();
+    container
+        .push_markdown(DOC, Some("-zlib -semantic"))
+        .unwrap();
+    container.push(m8::QUANTUM_TREE, b"src/\nsrc/lib.rs\t120\n".to_vec());
+    container.push(m8::WAVE_MEMORY_BLOB, vec![0, 0xFF, 7]);
+    let bytes = container.to_bytes().unwrap();
+    assert_eq!(&bytes[..4], b"MEM8");
+
+    let parsed = M8::parse(&bytes).unwrap();
+    assert_eq!(parsed, container);
+    let docs = parsed.documents().unwrap();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].decompress().unwrap(), DOC);
+    assert_eq!(docs[1].decompress().unwrap().trim_end(), DOC.trim_end());
+    assert_eq!(docs[0].header.flags, 0);
+    assert_eq!(
+        docs[1].header.flags,
+        m8::FLAG_SECTIONS | m8::FLAG_SEMANTIC | m8::FLAG_ZLIB
+    );
+    assert_eq!(docs[0].header.original_size, DOC.len() as u32);
+    assert_eq!(docs[0].header.compressed_size, docs[0].mq.len() as u32);
+    assert!(docs[0].header.token_count > 0);
+    assert_eq!(docs[0].header.version, m8::MARQANT_TEXT_VERSION);
+    let again = MarqantSection::parse(&docs[1].to_bytes()).unwrap();
+    assert_eq!(again, docs[1]);
+
+    let names: Vec<_> = parsed.list().into_iter().map(|s| s.name).collect();
+    assert_eq!(
+        names,
+        [
+            "Marqant Document",
+            "Marqant Document",
+            "Quantum Tree",
+            "WaveMemoryBlob"
+        ]
+        .map(|n| Some(n.to_string()))
+    );
+    assert!(verify_mq(&bytes).is_ok());
+    let markdown = String::from_utf8(Marqant::decompress_auto(&bytes).unwrap()).unwrap();
+    assert!(markdown.starts_with(DOC), "{markdown}");
+}
+
+#[test]
+fn damaged_containers_are_rejected() {
+    let mut container = M8::new();
+    container.push_markdown(DOC, None).unwrap();
+    let bytes = container.to_bytes().unwrap();
+
+    let mut flipped = bytes.clone();
+    *flipped.last_mut().unwrap() ^= 1;
+    let err = M8::parse(&flipped).unwrap_err().to_string();
+    assert!(err.contains("checksum"), "{err}");
+    assert!(!verify_mq(&flipped).is_ok());
+    assert!(M8::parse(&bytes[..10]).is_err());
+    assert!(M8::parse(b"MARK0000000000000000").is_err());
+    assert!(Marqant::decompress_auto(&M8::new().to_bytes().unwrap()).is_err());
+
+    // The spec's token-entry layout is version 1; payloads here are `.mq` text
+    let mut spec = container.documents().unwrap()[0].to_bytes();
+    spec[0] = 1;
+    let err = MarqantSection::parse(&spec).unwrap_err().to_string();
+    assert!(err.contains("version 1"), "{err}");
+
+    // A section whose recorded sizes disagree with its payload
+    let mut doc = container.documents().unwrap().remove(0);
+    doc.header.compressed_size += 1;
+    let mut forged = M8::new();
+    forged.push(m8::MARQANT_DOCUMENT, doc.to_bytes());
+    let report = verify_mq(forged.to_bytes().unwrap());
+    assert!(!report.is_ok(), "{report:?}");
+}
+
+#[test]
+fn cli_m8_compress_ls_extract() {
+    let dir = std::env::temp_dir().join(format!("mq-m8-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("tree/sub")).unwrap();
+    fs::write(dir.join("README.md"), DOC).unwrap();
+    fs::write(dir.join("tree/sub/a.txt"), "abc").unwrap();
+    fs::write(dir.join("waves.bin"), [1u8, 2, 3]).unwrap();
+    let mq = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_mq"))
+            .current_dir(&dir)
+            .args(["--no-config", "m8"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(mq(&["compress", "README.md", "--binary"]).status.success());
+    let out = mq(&[
+        "compress",
+        "README.md",
+        "--append",
+        "-o",
+        "README.m8",
+        "--tree",
+        "tree",
+        "--waves",
+        "waves.bin",
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let ls = String::from_utf8(mq(&["ls", "README.m8"]).stdout).unwrap();
+    assert_eq!(ls.lines().count(), 4, "{ls}");
+    assert!(ls.contains("0x0A Quantum Tree"), "{ls}");
+
+    let first = mq(&["extract", "README.m8", "--index", "0"]);
+    assert_eq!(String::from_utf8(first.stdout).unwrap(), DOC);
+    let tree = mq(&["extract", "README.m8", "--tree"]);
+    assert_eq!(
+        String::from_utf8(tree.stdout).unwrap(),
+        "sub/\nsub/a.txt\t3\n"
+    );
+    assert_eq!(
+        mq(&["extract", "README.m8", "--section", "0x0F"]).stdout,
+        [1, 2, 3]
+    );
+    let raw = mq(&["extract", "README.m8", "--mq", "--index", "1"]);
+    assert!(raw.stdout.starts_with(b"MARQANT "));
+    assert!(!mq(&["extract", "README.m8", "--section", "0x11"])
+        .status
+        .success());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn summarize_projects() {
+    let dir = std::env::temp_dir().join(format!("mq-summary-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let project = dir.join("demo");
+    fs::create_dir_all(project.join("docs")).unwrap();
+    fs::create_dir_all(project.join("target")).unwrap();
+    fs::write(
+        project.join("Cargo.toml"),
+        "[package]\nname = \"demo\"\nversion = \"0.3.1\"\ndescription = \"A demo crate\"\n",
+    )
+    .unwrap();
+    fs::write(project.join(".gitignore"), "target/\n").unwrap();
+    fs::write(project.join("README.md"), DOC).unwrap();
+    fs::write(project.join("docs/guide.md"), "# Guide\n\nRun it.\n").unwrap();
+    fs::write(project.join("target/out.md"), "# Build output\n").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_mq"))
+        .args(["--no-config", "summarize"])
+        .arg(&project)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let container = M8::parse(&fs::read(project.join("demo.m8")).unwrap()).unwrap();
+    let kinds: Vec<u8> = container.sections.iter().map(|s| s.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            m8::IDENTITY,
+            m8::MARQANT_DOCUMENT,
+            m8::MARQANT_DOCUMENT,
+            m8::QUANTUM_TREE
+        ]
+    );
+    let identity =
+        ProjectIdentity::parse(std::str::from_utf8(&container.sections[0].data).unwrap());
+    assert_eq!(identity.name, "demo");
+    assert_eq!(identity.version.as_deref(), Some("0.3.1"));
+    assert_eq!(identity.description.as_deref(), Some("A demo crate"));
+    assert_eq!(identity.source.as_deref(), Some("Cargo.toml"));
+    let docs = container.documents().unwrap();
+    assert_eq!(docs[0].decompress().unwrap(), DOC);
+    assert!(docs[1].mq.contains("docs/guide.md"));
+    assert!(!docs[1].mq.contains("target/out.md"));
+    let tree = String::from_utf8(container.sections[3].data.clone()).unwrap();
+    assert!(tree.starts_with("Cargo.toml\t"), "{tree}");
+    assert!(tree.contains("docs/\ndocs/guide.md\t17\n"), "{tree}");
+    assert!(!tree.contains("target"), "{tree}");
+
+    // package.json, and a budget the README already fills
+    fs::remove_file(project.join("Cargo.toml")).unwrap();
+    fs::write(
+        project.join("package.json"),
+        r#"{"name": "web-demo", "scripts": {"version": "x"}, "version": "2.0.0"}"#,
+    )
+    .unwrap();
+    let identity = ProjectIdentity::read(&project);
+    assert_eq!(identity.name, "web-demo");
+    assert_eq!(identity.version.as_deref(), Some("2.0.0"));
+    assert_eq!(identity.description, None);
+    let container =
+        Marqant::summarize_project(&project, &Selection::default(), Some(1), None).unwrap();
+    assert_eq!(container.documents().unwrap().len(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -34,3 +34,90 @@ fn read_meta_marqant_and_unknown() {
     let unk = read_mq_metadata("garbage").expect("meta");
     assert_eq!(unk.kind, "UNKNOWN");
 }
+
+#[test]
+fn read_meta_mq2_parses_binary_token_map() {
+    // Patterns containing newlines (and a newline token id) used to truncate
+    // the line-based view of ~T
+    let dict = mq2_uni::Dictionary::new(vec![
+        (0xC0, b"\n\n".to_vec()),
+        (0x0A, b"## ".to_vec()),
+        (0xF5, b"```\nfn main() {}\n```".to_vec()),
+    ])
+    .unwrap();
+    let mq = mq2_uni::encode(b"## a\n\nb\n\n", &dict, "0");
+    let info = read_mq_metadata(&mq).expect("meta");
+    assert_eq!(info.kind, "MQ2");
+    assert_eq!(info.token_count, Some(3));
+    let entries: Vec<(Vec<u8>, &[u8])> = info
+        .tokens
+        .iter()
+        .map(|e| (e.id.clone(), &e.pattern[..]))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (vec![0x0A], &b"## "[..]),
+            (vec![0xC0], &b"\n\n"[..]),
+            (vec![0xF5], &b"```\nfn main() {}\n```"[..]),
+        ]
+    );
+    let t = mq2_uni::t_section(&dict);
+    assert_eq!(info.dict_t.as_deref(), Some(&t[2..t.len() - 6]));
+    assert!(info.dict_id.is_some());
+}
+
+#[test]
+fn read_meta_marqant_token_entries() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let md = "## Setup\n\nRun the installer now.\n\n## Usage\n\nRun the installer now.\n";
+    let mq = Marqant::compress_markdown(md).expect("compress");
+    let info = read_mq_metadata(mq.as_bytes()).expect("meta");
+    assert!(!info.tokens.is_empty());
+    for entry in &info.tokens {
+        let id = std::str::from_utf8(&entry.id).expect("utf8 token");
+        assert_eq!(id.chars().count(), 1);
+        assert!(!entry.pattern.is_empty());
+    }
+    assert!(info
+        .tokens
+        .iter()
+        .any(|e| e.pattern == b"\n\n" || e.pattern.starts_with(b"Run the")));
+    assert!(info.dict_id.is_none());
+}
+
+#[test]
+fn cli_inspect_show_tokens_prints_table() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let dict =
+        mq2_uni::Dictionary::new(vec![(0xC0, b"\n\n".to_vec()), (0xC1, b"## ".to_vec())]).unwrap();
+    let mq = mq2_uni::encode(b"## a\n\n## b\n\n", &dict, "0");
+    let mut inspect = Command::new("cargo")
+        .args(["run", "--quiet", "--", "inspect", "--show-tokens"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mq inspect");
+    inspect.stdin.as_mut().unwrap().write_all(&mq).unwrap();
+    let out = inspect
+        .wait_with_output()
+        .expect("Failed to run mq inspect");
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("tokens: 2"), "{stdout}");
+    assert!(
+        stdout.contains("0xC0") && stdout.contains(r#""\n\n""#),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("0xC1") && stdout.contains(r###""## ""###),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn marqant_flags_are_read_in_full() {
+    let info = read_mq_metadata("MARQANT 0 10 8 -zlib -semantic\n---\nx").unwrap();
+    assert_eq!(info.level.as_deref(), Some("-zlib -semantic"));
+}
@@ -39,7 +39,7 @@ fn test_roundtrip_all_markdown_files() {
         println!("Testing: {}", filename);
 
         // Read original
-        let original = fs::read_to_string(&path).expect(&format!("Failed to read {}", filename));
+        let original = fs::read_to_string(&path).unwrap_or_else(|_| panic!("Failed to read {}", filename));
 
         // Encode via CLI
         let mut encode_cmd = Command::new("cargo")
@@ -202,8 +202,8 @@ fn test_emoji_bytes_explicit() {
         // Test encoding/decoding preserves bytes exactly
         use marqant::{mq2_uni_decode, mq2_uni_encode};
 
-        let encoded = mq2_uni_encode(&actual_bytes).expect(&format!("Failed to encode {}", name));
-        let decoded = mq2_uni_decode(&encoded).expect(&format!("Failed to decode {}", name));
+        let encoded = mq2_uni_encode(&actual_bytes).unwrap_or_else(|_| panic!("Failed to encode {}", name));
+        let decoded = mq2_uni_decode(&encoded).unwrap_or_else(|_| panic!("Failed to decode {}", name));
 
         assert_eq!(
             decoded, expected_bytes,
@@ -213,7 +213,7 @@ fn test_emoji_bytes_explicit() {
 
         // Verify string reconstruction
         let reconstructed =
-            String::from_utf8(decoded).expect(&format!("Invalid UTF-8 after decoding {}", name));
+            String::from_utf8(decoded).unwrap_or_else(|_| panic!("Invalid UTF-8 after decoding {}", name));
         assert_eq!(
             reconstructed, emoji,
             "Emoji '{}' string mismatch after roundtrip",
@@ -0,0 +1,142 @@
+use marqant::{verify_mq, Aggregate, Marqant};
+
+fn doc() -> String {
+    std::fs::read_to_string("example-md/ARCHITECTURE.md").unwrap()
+}
+
+// Replace the stored text of block `i` with garbage of the same length
+fn corrupt_block(mq: &str, i: usize) -> String {
+    let index = Marqant::seek_table(mq).unwrap().unwrap();
+    let body_at = mq.find("\n---\n").unwrap() + 5;
+    let start = body_at + index[i].stored_start;
+    let mut out = mq.to_string();
+    let garbage: String = "%".repeat(index[i].stored_len - 1);
+    out.replace_range(start..start + garbage.len(), &garbage);
+    out
+}
+
+#[test]
+fn seekable_blocks_decode_ranges() {
+    let content = doc();
+    for flags in [
+        "-seekable:1024",
+        "-zlib -seekable:1024",
+        "-seekable:700 -std:std-static-v1",
+    ] {
+        let mq = Marqant::compress_markdown_with_flags(&content, Some(flags)).unwrap();
+        assert_eq!(
+            Marqant::decompress_marqant(&mq).unwrap(),
+            content,
+            "{flags}"
+        );
+        assert!(
+            verify_mq(&mq).is_ok(),
+            "{flags}: {:?}",
+            verify_mq(&mq).diagnostics
+        );
+
+        let index = Marqant::seek_table(&mq).unwrap().unwrap();
+        assert!(index.len() > 5, "{flags}: {} blocks", index.len());
+        assert_eq!(index.last().unwrap().original().end, content.len());
+        for (a, b) in [
+            (0, 10),
+            (1000, 5000),
+            (content.len() - 7, content.len()),
+            (42, 42),
+        ] {
+            assert_eq!(
+                Marqant::decode_range(&mq, a..b).unwrap(),
+                content.as_bytes()[a..b],
+                "{flags} {a}..{b}"
+            );
+        }
+        // Past the end is clipped, like slicing a file
+        let tail = Marqant::decode_range(&mq, content.len() - 3..content.len() + 100).unwrap();
+        assert_eq!(tail, content.as_bytes()[content.len() - 3..]);
+    }
+}
+
+#[test]
+fn ranges_only_touch_their_blocks() {
+    let content = doc();
+    let mq = Marqant::compress_markdown_with_flags(&content, Some("-zlib -seekable:1024")).unwrap();
+    let index = Marqant::seek_table(&mq).unwrap().unwrap();
+    let broken = corrupt_block(&mq, index.len() - 1);
+    assert!(Marqant::decompress_marqant(&broken).is_err());
+    assert!(!verify_mq(&broken).is_ok());
+    let head = index[0].original();
+    assert_eq!(
+        Marqant::decode_range(&broken, head.clone()).unwrap(),
+        content.as_bytes()[head]
+    );
+}
+
+#[test]
+fn semantic_blocks_drop_section_markers() {
+    let content = doc();
+    let mq =
+        Marqant::compress_markdown_with_flags(&content, Some("-semantic -seekable:900")).unwrap();
+    let whole = Marqant::decode_range(&mq, 0..usize::MAX).unwrap();
+    assert_eq!(
+        String::from_utf8(whole).unwrap().trim_end(),
+        content.trim_end()
+    );
+    assert_eq!(
+        Marqant::decode_range(&mq, 300..900).unwrap(),
+        content.as_bytes()[300..900]
+    );
+}
+
+#[test]
+fn plain_files_decode_ranges_without_a_table() {
+    let content = doc();
+    for flags in [None, Some("-zlib")] {
+        let mq = Marqant::compress_markdown_with_flags(&content, flags).unwrap();
+        assert!(Marqant::seek_table(&mq).unwrap().is_none());
+        assert_eq!(
+            Marqant::decode_range(&mq, 100..200).unwrap(),
+            content.as_bytes()[100..200]
+        );
+    }
+}
+
+#[test]
+fn seekable_aggregates_extract_single_files() {
+    let files: Vec<(String, String)> = [
+        "ARCHITECTURE.md",
+        "QUICK_REFERENCE.md",
+        "MARQANT_SPECIFICATION.md",
+    ]
+    .iter()
+    .map(|n| {
+        (
+            n.to_string(),
+            std::fs::read_to_string(format!("example-md/{n}")).unwrap(),
+        )
+    })
+    .collect();
+    let mq = Marqant::aggregate_files(&files, Some("-zlib -seekable:4096")).unwrap();
+    assert!(verify_mq(&mq).is_ok(), "{:?}", verify_mq(&mq).diagnostics);
+
+    // Every file starts a block
+    let index = Marqant::seek_table(&mq).unwrap().unwrap();
+    let bundle = Aggregate::parse(&mq).unwrap();
+    for entry in &bundle.entries {
+        assert!(
+            index.iter().any(|b| b.tokenized_start == entry.start),
+            "{}",
+            entry.path
+        );
+    }
+    for (path, content) in &files {
+        assert_eq!(&bundle.extract(path).unwrap(), content);
+    }
+    // The body is markers, documents and shared blocks, not one document
+    let err = Marqant::decode_range(&mq, 0..100).unwrap_err().to_string();
+    assert!(err.contains("Aggregate::extract"), "{err}");
+
+    // A damaged last block leaves the other files readable
+    let broken = Aggregate::parse(&corrupt_block(&mq, index.len() - 1)).unwrap();
+    assert_eq!(broken.extract(&files[0].0).unwrap(), files[0].1);
+    assert!(broken.extract(&files[2].0).is_err());
+}
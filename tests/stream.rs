@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use marqant::Marqant;
+
+fn collect(mq: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    Marqant::decompress_marqant_with(mq, |chunk| {
+        out.extend_from_slice(chunk);
+        Ok(())
+    })
+    .expect("decompress");
+    out
+}
+
+#[test]
+fn visitor_matches_decompress_on_example_docs() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    for entry in std::fs::read_dir("example-md").expect("example-md") {
+        let path = entry.expect("entry").path();
+        if path.extension().is_none_or(|e| e != "md") {
+            continue;
+        }
+        let md = std::fs::read_to_string(&path).expect("read");
+        for flags in [
+            None,
+            Some("-zlib"),
+            Some("-semantic -zlib -std:std-static-v1"),
+        ] {
+            let mq = Marqant::compress_markdown_with_flags(&md, flags).expect("compress");
+            let whole = Marqant::decompress_marqant(&mq).expect("decompress");
+            let streamed = String::from_utf8(collect(&mq)).expect("utf8");
+            assert_eq!(
+                streamed.trim_end(),
+                whole.trim_end(),
+                "{} {:?}",
+                path.display(),
+                flags
+            );
+            assert_eq!(
+                whole.trim_end(),
+                md.trim_end(),
+                "{} {:?}",
+                path.display(),
+                flags
+            );
+            if !flags.is_some_and(|f| f.contains("-semantic")) {
+                assert_eq!(streamed, md, "{} {:?}", path.display(), flags);
+            }
+        }
+    }
+}
+
+#[test]
+fn visitor_yields_patterns_and_literal_runs() {
+    let mq = "MARQANT 0 20 10\n\x01=# \n\x06=\\n\\n\n---\n\x01Title\x06Body";
+    let mut chunks: Vec<String> = Vec::new();
+    Marqant::decompress_marqant_with(mq, |chunk| {
+        chunks.push(String::from_utf8(chunk.to_vec())?);
+        Ok(())
+    })
+    .expect("decompress");
+    assert_eq!(chunks, ["# ", "Title", "\n\n", "Body"]);
+}
+
+#[test]
+fn visitor_handles_multichar_tokens_across_zlib_blocks() {
+    // Dictionaries resolved via DNS may use multi-char tokens; a 60 KB body
+    // makes tokens straddle the decoder's inflate blocks
+    let body = "@@ x ".repeat(12_000);
+    let mut enc = ZlibEncoder::new(Vec::new(), Compression::best());
+    enc.write_all(body.as_bytes()).unwrap();
+    let b64 = base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        enc.finish().unwrap(),
+    );
+    let mq = format!("MARQANT 0 0 0 -zlib\n@@=word\n---\n{b64}\n");
+    let out = String::from_utf8(collect(&mq)).unwrap();
+    assert_eq!(out, "word x ".repeat(12_000));
+}
+
+#[test]
+fn visitor_error_stops_decoding() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let mq = Marqant::compress_markdown("# A\n\nsome text\n").expect("compress");
+    let mut calls = 0;
+    let res = Marqant::decompress_marqant_with(&mq, |_| {
+        calls += 1;
+        anyhow::bail!("sink closed")
+    });
+    assert!(res.is_err());
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn mq2_uni_chunks_concatenate_to_decode() {
+    let md = "# Title\n\n## Café ☕\n\n- item\n- second item\n\n\n```rust\nfn main() {}\n```\n";
+    let enc = marqant::mq2_uni_encode(md.as_bytes()).unwrap();
+    let chunks: Vec<&[u8]> = marqant::mq2_uni_decode_chunks(&enc).collect();
+    assert!(chunks.len() > 1);
+    assert_eq!(chunks.concat(), marqant::mq2_uni_decode(&enc).unwrap());
+    assert_eq!(chunks.concat(), md.as_bytes());
+}
@@ -22,3 +22,20 @@ fn thank_you_bondee_in_japanese_roundtrip() {
     println!("   Original: {}", original);
     println!("   Bytes: {} → {} (encoded)", original.len(), encoded.len());
 }
+
+#[test]
+fn thank_you_survives_mq2_uni_byte_tokens() {
+    use mq2_uni::{decode, encode, Dictionary};
+
+    let original = "この修正をありがとうございます！";
+    // Every token id is a byte that occurs inside the message's UTF-8
+    let mut ids: Vec<u8> = original.bytes().filter(|b| *b >= 0x80).collect();
+    ids.sort();
+    ids.dedup();
+    let dict =
+        Dictionary::new(ids.into_iter().map(|t| (t, "ありがとう".into())).collect()).unwrap();
+
+    let encoded = encode(original.as_bytes(), &dict, "0");
+    let decoded = String::from_utf8(decode(&encoded).unwrap()).expect("valid UTF-8");
+    assert_eq!(decoded, original);
+}
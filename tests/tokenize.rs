@@ -22,3 +22,47 @@ fn tokenize_static_tokens_present() {
     let has_expected = tokens.values().any(|v| v == "## " || v == "- ");
     assert!(has_expected);
 }
+
+#[test]
+fn tokenize_dynamic_tokens_never_collide_with_text() {
+    // Many repeated phrases used to exhaust 0x1B..0x1F and spill into
+    // printable ASCII (space, '!', ...), corrupting every decode
+    let mut content = String::new();
+    for i in 0..40 {
+        content.push_str(&format!("const VALUE_{i}: usize = 256; // fixed width\n"));
+        content.push_str(&format!("let item_{i} = Vec::with_capacity(1024);\n"));
+    }
+    let (tokens, _tokenized) = Marqant::tokenize_content(&content);
+    for token in tokens.keys() {
+        assert!(!token.chars().any(|c| c.is_ascii_graphic() || c == ' '));
+        assert!(!content.contains(token.as_str()));
+    }
+    let mq = Marqant::compress_markdown(&content).expect("compress");
+    let back = Marqant::decompress_marqant(&mq).expect("decompress");
+    assert_eq!(back, content);
+}
+
+#[test]
+fn roundtrip_is_byte_exact() {
+    // Token chars already in the text, phrases holding `\n` literally,
+    // CRLF line ends and trailing blank lines all come back unchanged
+    let cases = [
+        "# One\x01 \x06two\n\n# One\x01 \x06two\n\n# One\x01 \x06two\n".to_string(),
+        "Use C:\\new\\table here.\n".repeat(6),
+        "line one here\r\nline one here\r\nline one here\r\n\n\n".to_string(),
+    ];
+    for content in cases {
+        for flags in [None, Some("-zlib")] {
+            let mq = Marqant::compress_markdown_with_flags(&content, flags).expect("compress");
+            let back = Marqant::decompress_marqant(&mq).expect("decompress");
+            assert_eq!(back, content, "{flags:?}");
+        }
+    }
+}
+
+#[test]
+fn body_without_separator_is_kept() {
+    // Files with no `---` line read everything after the header as the body
+    let mq = "MARQANT 0 15 15\n# Title\nhello";
+    assert_eq!(Marqant::decompress_marqant(mq).unwrap(), "# Title\nhello");
+}
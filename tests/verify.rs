@@ -0,0 +1,147 @@
+use marqant::{verify_mq, Check, Marqant, Severity, VerifyReport};
+
+fn errors(report: &VerifyReport) -> Vec<Check> {
+    report
+        .diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| d.check)
+        .collect()
+}
+
+const DOC: &str = "# Title\n\n## Setup\n\nRun the installer now.\n\n## Usage\n\n- Run the installer now.\n- Then check the logs.\n";
+
+#[test]
+fn verify_accepts_fresh_output_for_every_flag_set() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let md = std::fs::read_to_string("example-md/QUICK_REFERENCE.md").expect("read");
+    for doc in [DOC, md.as_str(), "no trailing newline"] {
+        for flags in [
+            None,
+            Some("-zlib"),
+            Some("-semantic"),
+            Some("-semantic -zlib -std:std-static-v1"),
+        ] {
+            let mq = Marqant::compress_markdown_with_flags(doc, flags).expect("compress");
+            let report = verify_mq(&mq);
+            assert_eq!(report.kind, "MARQANT");
+            assert!(report.is_ok(), "{flags:?}: {:?}", report.diagnostics);
+        }
+    }
+}
+
+#[test]
+fn verify_reports_size_token_and_dictionary_damage() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let mq = Marqant::compress_markdown(DOC).expect("compress");
+    let (header, rest) = mq.split_once('\n').unwrap();
+
+    let wrong_size = mq.replacen(&format!(" {} ", DOC.len()), " 1 ", 1);
+    assert_eq!(errors(&verify_mq(&wrong_size)), [Check::Sizes]);
+
+    // Drop the first dictionary entry: its token is now undefined
+    let (_, without_first) = rest.split_once('\n').unwrap();
+    let report = verify_mq(format!("{header}\n{without_first}"));
+    assert!(
+        errors(&report).contains(&Check::Tokens),
+        "{:?}",
+        report.diagnostics
+    );
+
+    let printable = verify_mq(format!("{header}\nab=xyz\n{rest}"));
+    assert!(errors(&printable).contains(&Check::Dictionary));
+
+    let no_separator = verify_mq(format!("{header}\n\x01=# \n"));
+    assert_eq!(errors(&no_separator), [Check::Dictionary]);
+
+    assert_eq!(errors(&verify_mq("MARQANT x\n---\n")), [Check::Header]);
+    assert_eq!(errors(&verify_mq("# just markdown")), [Check::Header]);
+}
+
+#[test]
+fn verify_reports_unresolvable_std_dictionary() {
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    std::env::set_var("MQ_DIG_CMD", "false");
+    let mq =
+        Marqant::compress_markdown_with_flags(DOC, Some("-std:std-static-v1")).expect("compress");
+    let report = verify_mq(mq.replace("-std:std-static-v1", "-std:no-such-dict"));
+    assert_eq!(errors(&report), [Check::StdDict]);
+}
+
+#[test]
+fn verify_mq2_payloads() {
+    let dict =
+        mq2_uni::Dictionary::new(vec![(0xC0, b"\n\n".to_vec()), (0xC1, b"## ".to_vec())]).unwrap();
+    let mq = mq2_uni::encode("## a\n\nb \u{C0}\n\n".as_bytes(), &dict, "0");
+    let report = verify_mq(&mq);
+    assert_eq!(report.kind, "MQ2");
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+
+    let header_end = mq.iter().position(|&b| b == b'\n').unwrap();
+    let header = std::str::from_utf8(&mq[..header_end]).unwrap();
+    let mut fields: Vec<&str> = header.split('~').collect();
+    fields[5] = "3";
+    let mut wrong_count = fields.join("~").into_bytes();
+    wrong_count.extend_from_slice(&mq[header_end..]);
+    assert_eq!(errors(&verify_mq(&wrong_count)), [Check::Dictionary]);
+
+    let mut dangling = mq.clone();
+    dangling.push(0xFF);
+    assert!(errors(&verify_mq(&dangling)).contains(&Check::Body));
+
+    let truncated = &mq[..header_end + 8];
+    assert_eq!(errors(&verify_mq(truncated)), [Check::Dictionary]);
+
+    // Legacy unescaped streams decode and round-trip the same way
+    let text = [
+        header.replace("~esc", "~text").as_bytes(),
+        &mq[header_end..],
+    ]
+    .concat();
+    let report = verify_mq(&text);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+}
+
+#[test]
+fn cli_verify_exits_non_zero_on_failure() {
+    use std::process::Command;
+    std::env::set_var("MARQANT_TEST_TS", "0");
+    let dir = std::env::temp_dir().join(format!("mq-verify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.mq");
+    let bad = dir.join("bad.mq");
+    let mq = Marqant::compress_markdown(DOC).expect("compress");
+    std::fs::write(&good, &mq).unwrap();
+    std::fs::write(&bad, mq.replacen(&format!(" {} ", DOC.len()), " 1 ", 1)).unwrap();
+
+    let run = |files: &[&std::path::Path]| {
+        Command::new(env!("CARGO_BIN_EXE_mq"))
+            .arg("verify")
+            .args(files)
+            .output()
+            .expect("Failed to run mq verify")
+    };
+    let ok = run(&[&good]);
+    assert!(ok.status.success());
+    let failed = run(&[&good, &bad]);
+    assert!(!failed.status.success());
+    let stdout = String::from_utf8_lossy(&failed.stdout);
+    assert!(
+        stdout.contains("FAIL") && stdout.contains("error[sizes]"),
+        "{stdout}"
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn verify_bare_mq2_uni() {
+    let bare = marqant::mq2_uni_encode(DOC.as_bytes()).unwrap();
+    let report = verify_mq(&bare);
+    assert_eq!(report.kind, "MQ2-UNI");
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+
+    // A raw paragraph break the encoder would have escaped
+    let mut edited = bare.clone();
+    edited.extend_from_slice(b"\n\nmore");
+    assert_eq!(errors(&verify_mq(&edited)), [Check::RoundTrip]);
+}
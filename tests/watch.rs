@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn fixture(name: &str) -> PathBuf {
+    let dir = common::scratch(name);
+    fs::create_dir_all(dir.join("docs/sub")).unwrap();
+    fs::copy("example-md/QUICK_REFERENCE.md", dir.join("docs/a.md")).unwrap();
+    fs::copy("example-md/ARCHITECTURE.md", dir.join("docs/sub/b.md")).unwrap();
+    dir
+}
+
+fn wait_for(what: &str, cond: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !cond() {
+        assert!(
+            start.elapsed() < Duration::from_secs(20),
+            "timed out: {what}"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn watch_once_mirrors_tree() {
+    let dir = fixture("watch-once");
+    let out = common::mq_command(&dir)
+        .args([
+            "watch",
+            "docs",
+            "--output-dir",
+            "cache",
+            "--once",
+            "--semantic",
+        ])
+        .output()
+        .expect("Failed to run mq watch");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let log = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        log.contains("compressed docs/sub/b.md -> cache/sub/b.mq"),
+        "{log}"
+    );
+    let mq = fs::read_to_string(dir.join("cache/a.mq")).unwrap();
+    assert!(mq.lines().next().unwrap().ends_with(" -semantic"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn watch_recompresses_changes_and_removes_orphans() {
+    let dir = fixture("watch");
+    let (a, b) = (dir.join("cache/a.mq"), dir.join("cache/sub/b.mq"));
+    let mut child = common::mq_command(&dir)
+        .args([
+            "watch",
+            "docs",
+            "--output-dir",
+            "cache",
+            "--interval",
+            "0.05",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start mq watch");
+
+    wait_for("initial pass", || a.exists() && b.exists());
+    let before = fs::read(&a).unwrap();
+    let mut md = fs::read_to_string(dir.join("docs/a.md")).unwrap();
+    md.push_str("\n## Appendix\n\nAdded while watching.\n");
+    // Leave the mtime clearly behind the first output's
+    std::thread::sleep(Duration::from_millis(20));
+    fs::write(dir.join("docs/a.md"), &md).unwrap();
+    wait_for("recompress", || fs::read(&a).is_ok_and(|now| now != before));
+    let decoded = marqant::Marqant::decompress_marqant(&fs::read_to_string(&a).unwrap()).unwrap();
+    assert_eq!(decoded, md);
+
+    fs::remove_file(dir.join("docs/sub/b.md")).unwrap();
+    wait_for("orphan removal", || !b.exists());
+
+    child.kill().unwrap();
+    let out = child.wait_with_output().unwrap();
+    let log = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(log.matches("compressed docs/a.md").count(), 2, "{log}");
+    assert!(
+        log.contains("removed cache/sub/b.mq (source deleted)"),
+        "{log}"
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn watch_removes_orphans_left_while_stopped() {
+    let dir = fixture("watch-orphans");
+    fs::create_dir_all(dir.join("cache")).unwrap();
+    fs::write(dir.join("cache/project.mq"), "not ours").unwrap();
+    fs::copy("example-md/QUICK_REFERENCE.md", dir.join("single.md")).unwrap();
+    let once = |inputs: &[&str]| {
+        let out = common::mq_command(&dir)
+            .arg("watch")
+            .args(inputs)
+            .args(["--output-dir", "cache", "--once"])
+            .output()
+            .expect("Failed to run mq watch");
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    let log = once(&["docs", "single.md"]);
+    assert!(!log.contains("watching"), "{log}");
+    assert!(dir.join("cache/sub/b.mq").is_file());
+
+    fs::remove_file(dir.join("docs/sub/b.md")).unwrap();
+    let log = once(&["docs", "single.md"]);
+    assert!(
+        log.contains("removed cache/sub/b.mq (source deleted)"),
+        "{log}"
+    );
+    assert!(!dir.join("cache/sub/b.mq").exists());
+    assert!(dir.join("cache/a.mq").is_file());
+    // Outputs watch didn't write stay, and so do those of missing file inputs
+    assert!(dir.join("cache/project.mq").is_file());
+    fs::remove_file(dir.join("single.md")).unwrap();
+    let log = once(&["docs"]);
+    assert!(!log.contains("removed"), "{log}");
+    assert!(dir.join("cache/single.mq").is_file());
+    fs::remove_dir_all(&dir).ok();
+}